@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
 use twelf::{config, Layer};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -11,12 +14,55 @@ pub enum MarkdownFormat {
     Mdx,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MarkdownInput {
     #[serde(default)]
     pub format: MarkdownFormat,
     #[serde(default)]
     pub frontmatter: bool,
+    #[serde(default)]
+    pub fail_on_invalid_utf8: bool,
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Render `[^1]` footnotes in every format, not just GFM
+    #[serde(default = "default_true")]
+    pub footnotes: bool,
+    /// Render `- [ ]` / `- [x]` task lists as checkboxes in every format, not just GFM
+    #[serde(default)]
+    pub tasklists: bool,
+    /// Convert straight quotes, `--`/`---` and `...` to typographic forms
+    #[serde(default)]
+    pub smart_punctuation: bool,
+    /// Heading level page titles are taken from, for sites whose H1 comes from elsewhere
+    #[serde(default = "default_title_heading_level")]
+    pub title_heading_level: u8,
+    /// Language for fenced code blocks that don't name one; `text` opts a block out
+    #[serde(default)]
+    pub default_code_language: Option<String>,
+}
+
+impl Default for MarkdownInput {
+    fn default() -> Self {
+        Self {
+            format: MarkdownFormat::default(),
+            frontmatter: false,
+            fail_on_invalid_utf8: false,
+            extensions: default_extensions(),
+            footnotes: true,
+            tasklists: false,
+            smart_punctuation: false,
+            title_heading_level: default_title_heading_level(),
+            default_code_language: None,
+        }
+    }
+}
+
+const fn default_title_heading_level() -> u8 {
+    1
+}
+
+fn default_extensions() -> Vec<String> {
+    vec!["md".to_string(), "markdown".to_string(), "mdx".to_string()]
 }
 
 #[config]
@@ -32,6 +78,15 @@ pub struct BookConfig {
     pub markdown: MarkdownInput,
     #[serde(default)]
     pub paths: Paths,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub syntax: SyntaxConfig,
+    /// Settings for the pages matching a glob, applied in order
+    #[serde(default)]
+    pub overrides: Vec<ConfigOverride>,
+    #[serde(default)]
+    pub blog: Blog,
 }
 
 #[config]
@@ -53,6 +108,16 @@ pub struct Book {
     pub github_url: Option<String>,
     #[serde(default)]
     pub github_edit_url_base: Option<String>,
+    #[serde(default)]
+    pub root_section_title: Option<String>,
+    #[serde(default)]
+    pub flatten_root_section: bool,
+    /// Ask search engines not to index any page, e.g. for staging deploys
+    #[serde(default)]
+    pub noindex: bool,
+    /// Release or revision label shown in the footer
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 fn default_title() -> String {
@@ -67,6 +132,31 @@ fn default_logo() -> String {
     "/img/default_logo.svg".to_string()
 }
 
+/// How highlighted code is styled
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SyntaxConfig {
+    #[serde(default)]
+    pub style: HighlightStyle,
+    /// Fence languages mapped to the language they highlight as, e.g.
+    /// `svelte = "html"`; these take precedence over the built-in aliases
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Renderers for custom fence languages, registered by library users
+    #[cfg(feature = "syntax-highlighting")]
+    #[serde(skip)]
+    pub renderers: crate::core::CodeBlockRenderers,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    /// CSS classes, styled by the generated `css/syntax.css`
+    #[default]
+    Classed,
+    /// `style` attributes on every token, for output embedded without the stylesheet
+    Inline,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Rust {
     #[serde(default = "default_edition")]
@@ -82,7 +172,7 @@ pub struct Output {
     pub html: HtmlOutput,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct HtmlOutput {
     #[serde(default)]
@@ -93,6 +183,271 @@ pub struct HtmlOutput {
     pub playground: PlaygroundConfig,
     #[serde(default)]
     pub search: SearchConfig,
+    #[serde(default)]
+    pub number_headings: NumberHeadingsConfig,
+    #[serde(default)]
+    pub sidebar: SidebarConfig,
+    #[serde(default)]
+    pub print_page: bool,
+    #[serde(default)]
+    pub optimize_images: bool,
+    #[serde(default)]
+    pub last_updated: LastUpdatedSource,
+    /// strftime-style format for displayed dates
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default = "default_true")]
+    pub generate_index: bool,
+    #[serde(default = "default_true")]
+    pub open_graph: bool,
+    /// Link preview image; defaults to `book.logo`
+    #[serde(default)]
+    pub og_image: Option<String>,
+    /// Old output paths mapped to the URL they moved to
+    #[serde(default)]
+    pub redirect: BTreeMap<String, String>,
+    #[serde(default)]
+    pub redirect_format: RedirectFormat,
+    /// Drop a leading heading that matches the page title, for templates that render the title themselves
+    #[serde(default)]
+    pub strip_title_heading: bool,
+    #[serde(default)]
+    pub robots: RobotsConfig,
+    /// Write an `llms.txt` index of the pages at the site root
+    #[serde(default)]
+    pub llms_txt: bool,
+    /// Also write `llms-full.txt`, the text of every page in one file
+    #[serde(default)]
+    pub llms_full_txt: bool,
+    /// Write an empty `.nojekyll` so GitHub Pages serves `_`-prefixed directories
+    #[serde(default)]
+    pub github_pages: bool,
+    /// Announcement shown above the header of every page
+    #[serde(default)]
+    pub banner: Option<BannerConfig>,
+    /// Show the short SHA of the source's git commit in the footer
+    #[serde(default)]
+    pub git_commit: bool,
+    /// Length of a page's summary when it has neither a `summary` frontmatter
+    /// key nor a `<!-- more -->` marker
+    #[serde(default = "default_excerpt_words")]
+    pub excerpt_words: usize,
+    /// CSS length capping the width of page content, e.g. `"60rem"`
+    #[serde(default)]
+    pub content_max_width: Option<String>,
+    /// CSS `font-family` for body text
+    #[serde(default)]
+    pub font_family: Option<String>,
+    /// CSS `font-family` for code
+    #[serde(default)]
+    pub code_font_family: Option<String>,
+    /// Link `index.html` pages by their directory, with or without a
+    /// trailing slash; unset keeps linking the file itself
+    #[serde(default)]
+    pub trailing_slash: Option<TrailingSlash>,
+    /// Write `guide/setup.md` to `guide/setup/index.html`, so pages are
+    /// served at `/guide/setup/`; links are then written with a trailing
+    /// slash unless `trailing-slash` says otherwise
+    #[serde(default)]
+    pub pretty_urls: bool,
+    /// Inline stylesheets, scripts and images smaller than this many bytes
+    /// into each page, saving a request apiece; larger ones stay linked
+    #[serde(default)]
+    pub inline_assets_under: Option<u64>,
+    /// Color mobile browsers tint their chrome with, such as `#1f6feb`
+    #[serde(default)]
+    pub theme_color: Option<String>,
+    /// Emit a `manifest.webmanifest` so the book can be installed as an app
+    #[serde(default)]
+    pub pwa: bool,
+    /// Emit a service worker caching the book for offline reading; left out
+    /// of watch-mode builds so live reload keeps working
+    #[serde(default)]
+    pub offline: bool,
+    /// What to do about images without alt text
+    #[serde(default)]
+    pub require_alt_text: AltTextPolicy,
+    /// Open absolute `http(s)` links in page content in a new tab
+    #[serde(default)]
+    pub external_links_new_tab: bool,
+    /// Mark external links with an icon after the link text
+    #[serde(default)]
+    pub external_link_icon: bool,
+    /// Markdown rendered below each page's content, such as a license
+    /// notice; `{year}` and `{title}` become the build year and page title
+    #[serde(default)]
+    pub page_footer: Option<String>,
+}
+
+/// How `output.html.trailing-slash` writes links to a directory's index page
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// `guide/`
+    Always,
+    /// `guide`
+    Never,
+}
+
+/// How `output.html.require-alt-text` treats images without alt text
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AltTextPolicy {
+    Off,
+    /// Report a build warning
+    #[default]
+    Warn,
+    /// Fail the page, as for a markdown error
+    Strict,
+}
+
+/// How `output.html.redirect` entries are emitted
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectFormat {
+    /// HTML stubs with a meta refresh at each old path
+    #[default]
+    Meta,
+    /// A `_redirects` file for Netlify / Cloudflare Pages 301s
+    Netlify,
+    Both,
+}
+
+impl Default for HtmlOutput {
+    fn default() -> Self {
+        Self {
+            mathjax_support: false,
+            allow_html: false,
+            playground: PlaygroundConfig::default(),
+            search: SearchConfig::default(),
+            number_headings: NumberHeadingsConfig::default(),
+            sidebar: SidebarConfig::default(),
+            print_page: false,
+            optimize_images: false,
+            last_updated: LastUpdatedSource::default(),
+            date_format: default_date_format(),
+            generate_index: true,
+            open_graph: true,
+            og_image: None,
+            redirect: BTreeMap::new(),
+            redirect_format: RedirectFormat::default(),
+            strip_title_heading: false,
+            robots: RobotsConfig::default(),
+            llms_txt: false,
+            llms_full_txt: false,
+            github_pages: false,
+            banner: None,
+            git_commit: false,
+            excerpt_words: default_excerpt_words(),
+            content_max_width: None,
+            font_family: None,
+            code_font_family: None,
+            trailing_slash: None,
+            pretty_urls: false,
+            inline_assets_under: None,
+            theme_color: None,
+            pwa: false,
+            offline: false,
+            require_alt_text: AltTextPolicy::default(),
+            external_links_new_tab: false,
+            external_link_icon: false,
+            page_footer: None,
+        }
+    }
+}
+
+const fn default_excerpt_words() -> usize {
+    50
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+/// Where a page's "Last updated" date comes from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LastUpdatedSource {
+    #[default]
+    Off,
+    /// The date of the last commit touching the source file
+    Git,
+    /// The source file's modification time
+    Mtime,
+    /// The page's `updated` frontmatter key
+    Frontmatter,
+}
+
+/// The `robots.txt` written at the root of the site
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct RobotsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Path prefixes crawlers may visit
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Path prefixes crawlers should stay out of; `/` for the whole site
+    #[serde(default)]
+    pub disallow: Vec<String>,
+}
+
+/// A site-wide announcement; an empty `text` shows nothing
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct BannerConfig {
+    #[serde(default)]
+    pub text: String,
+    /// Where the banner text links to
+    #[serde(default)]
+    pub link: Option<String>,
+    /// Show a close button; the choice is remembered until the text changes
+    #[serde(default)]
+    pub dismissible: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct SidebarConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub width: Option<String>,
+}
+
+impl Default for SidebarConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            width: None,
+        }
+    }
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct NumberHeadingsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_number_headings_max_level")]
+    pub max_level: u8,
+}
+
+impl Default for NumberHeadingsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_level: default_number_headings_max_level(),
+        }
+    }
+}
+
+const fn default_number_headings_max_level() -> u8 {
+    3
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
@@ -104,7 +459,7 @@ pub struct PlaygroundConfig {
     pub line_numbers: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct SearchConfig {
     #[serde(default = "default_limit_results")]
@@ -121,11 +476,59 @@ pub struct SearchConfig {
     pub expand: bool,
     #[serde(default = "default_heading_split_level")]
     pub heading_split_level: u32,
+    /// Element to index instead of the templates' `data-pagefind-body` content region
+    #[serde(default)]
+    pub root_selector: Option<String>,
+    #[serde(default)]
+    pub exclude_selectors: Vec<String>,
+    /// Where the index is written inside the output directory, and where the
+    /// search client loads it from
+    #[serde(default = "default_index_dir")]
+    pub index_dir: String,
+    /// Number of words in each result's excerpt
+    #[serde(default = "default_excerpt_length")]
+    pub excerpt_length: u32,
+    /// Sites indexed at once when the indexer is given several; defaults to
+    /// the number of CPUs
+    #[serde(default)]
+    pub concurrency: Option<NonZeroUsize>,
+    /// Path to the pagefind executable, for CI images that install it
+    /// outside `PATH`; the `PAGEFIND_BIN` environment variable takes precedence
+    #[serde(default, alias = "pagefind_binary")]
+    pub pagefind_binary: Option<String>,
+    /// Run pagefind as `npx pagefind`, fetching it from npm if needed, when
+    /// no binary is configured
+    #[serde(default, alias = "pagefind_npx")]
+    pub pagefind_npx: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            limit_results: default_limit_results(),
+            use_boolean_and: false,
+            boost_title: default_boost_title(),
+            boost_hierarchy: default_boost_hierarchy(),
+            boost_paragraph: default_boost_paragraph(),
+            expand: false,
+            heading_split_level: default_heading_split_level(),
+            root_selector: None,
+            exclude_selectors: Vec::new(),
+            index_dir: default_index_dir(),
+            excerpt_length: default_excerpt_length(),
+            concurrency: None,
+            pagefind_binary: None,
+            pagefind_npx: false,
+        }
+    }
 }
 
 const fn default_limit_results() -> u32 {
     20
 }
+const fn default_excerpt_length() -> u32 {
+    30
+}
 const fn default_boost_title() -> u32 {
     2
 }
@@ -138,6 +541,9 @@ const fn default_boost_paragraph() -> u32 {
 const fn default_heading_split_level() -> u32 {
     2
 }
+fn default_index_dir() -> String {
+    "pagefind".to_string()
+}
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Paths {
@@ -149,6 +555,191 @@ fn default_templates_dir() -> String {
     "templates".to_string()
 }
 
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Build {
+    /// Threads rendering pages in parallel; defaults to the number of CPUs
+    #[serde(default)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Directory holding the build manifest; defaults to a per-output
+    /// directory under the OS cache dir
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// Empty the output directory before every build
+    #[serde(default)]
+    pub clean_before: bool,
+    /// Globs of sources, relative to the input directory, left out of the
+    /// book, such as `drafts/**`; a pattern naming a directory covers
+    /// everything under it, the last matching pattern wins, and a leading `!`
+    /// makes an exception such as `!drafts/published.md`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Globs of sources kept even when `ignore` matches them, with the same
+    /// syntax
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Extra files or directories whose changes trigger a rebuild in watch mode
+    #[serde(default)]
+    pub watch_paths: Vec<String>,
+    /// Directory the book is read from when `--input` is not given, relative
+    /// to the directory holding the config file; defaults to `src`
+    #[serde(default, alias = "src-dir")]
+    pub src_dir: Option<String>,
+    /// Directory the book is written to when `--output` is not given,
+    /// relative to the directory holding the config file; defaults to `book`
+    #[serde(default, alias = "build-dir")]
+    pub build_dir: Option<String>,
+    /// Octal permissions such as `"0644"` for the files written; Unix only
+    #[serde(default)]
+    pub file_mode: Option<String>,
+    /// Octal permissions such as `"0755"` for the directories created; Unix only
+    #[serde(default)]
+    pub dir_mode: Option<String>,
+}
+
+impl Build {
+    /// Resolves `src_dir` and `build_dir`, or their defaults, against `root`,
+    /// the directory holding the config file
+    fn resolve_dirs(&mut self, root: &std::path::Path) {
+        for (dir, default) in [
+            (&mut self.src_dir, crate::core::DEFAULT_SRC_DIR),
+            (&mut self.build_dir, crate::core::DEFAULT_BUILD_DIR),
+        ] {
+            let resolved = root.join(dir.as_deref().unwrap_or(default));
+            *dir = Some(resolved.display().to_string());
+        }
+    }
+}
+
+/// `[blog]`: pages under a directory listed newest-first by their
+/// frontmatter `date`, with a generated listing page
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Blog {
+    /// Directory holding the posts, relative to the input directory, such as
+    /// `blog`; unset leaves blog mode off
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Output path of the listing page; defaults to `index.html` in `dir`
+    #[serde(default)]
+    pub listing: Option<String>,
+    /// Heading of the listing page
+    #[serde(default = "default_blog_title")]
+    pub title: String,
+}
+
+impl Default for Blog {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            listing: None,
+            title: default_blog_title(),
+        }
+    }
+}
+
+fn default_blog_title() -> String {
+    "Blog".to_string()
+}
+
+/// An `[[overrides]]` entry: markdown and HTML settings for the pages whose
+/// path relative to the book root matches `glob`, such as `api/**`
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ConfigOverride {
+    pub glob: String,
+    #[serde(default)]
+    pub markdown: MarkdownOverride,
+    #[serde(default)]
+    pub output: OutputOverride,
+    /// `glob`, compiled the first time the override is matched or validated
+    #[serde(skip)]
+    pub(crate) compiled: OnceLock<Result<globset::GlobMatcher, globset::Error>>,
+}
+
+/// The `markdown` settings an override can change; unset ones are inherited
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct MarkdownOverride {
+    #[serde(default)]
+    pub format: Option<MarkdownFormat>,
+    #[serde(default)]
+    pub frontmatter: Option<bool>,
+    #[serde(default)]
+    pub footnotes: Option<bool>,
+    #[serde(default)]
+    pub tasklists: Option<bool>,
+    #[serde(default)]
+    pub smart_punctuation: Option<bool>,
+    #[serde(default)]
+    pub default_code_language: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct OutputOverride {
+    #[serde(default)]
+    pub html: HtmlOverride,
+}
+
+/// The `output.html` settings an override can change; unset ones are inherited
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct HtmlOverride {
+    #[serde(default)]
+    pub allow_html: Option<bool>,
+    #[serde(default)]
+    pub strip_title_heading: Option<bool>,
+}
+
+/// Compiles a glob matched against paths relative to the book root, where
+/// `*` stops at `/` and `**` spans directories
+pub(crate) fn path_glob(glob: &str) -> Result<globset::Glob, globset::Error> {
+    globset::GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+}
+
+impl ConfigOverride {
+    fn matcher(&self) -> Result<&globset::GlobMatcher, &globset::Error> {
+        self.compiled
+            .get_or_init(|| path_glob(&self.glob).map(|glob| glob.compile_matcher()))
+            .as_ref()
+    }
+
+    /// Whether a source path relative to the book root falls under this override
+    pub fn matches(&self, rel_path: &std::path::Path) -> bool {
+        self.matcher()
+            .is_ok_and(|matcher| matcher.is_match(rel_path))
+    }
+
+    /// Replaces the settings this override sets in `config`
+    pub fn apply(&self, config: &mut BookConfig) {
+        let markdown = &self.markdown;
+        if let Some(format) = markdown.format {
+            config.markdown.format = format;
+        }
+        if let Some(frontmatter) = markdown.frontmatter {
+            config.markdown.frontmatter = frontmatter;
+        }
+        if let Some(footnotes) = markdown.footnotes {
+            config.markdown.footnotes = footnotes;
+        }
+        if let Some(tasklists) = markdown.tasklists {
+            config.markdown.tasklists = tasklists;
+        }
+        if let Some(smart_punctuation) = markdown.smart_punctuation {
+            config.markdown.smart_punctuation = smart_punctuation;
+        }
+        if let Some(language) = &markdown.default_code_language {
+            config.markdown.default_code_language = Some(language.clone());
+        }
+
+        let html = &self.output.html;
+        if let Some(allow_html) = html.allow_html {
+            config.output.html.allow_html = allow_html;
+        }
+        if let Some(strip_title_heading) = html.strip_title_heading {
+            config.output.html.strip_title_heading = strip_title_heading;
+        }
+    }
+}
+
 /// Load configuration from file or use defaults
 ///
 /// # Errors
@@ -183,9 +774,141 @@ pub fn load_config(config_path: Option<&str>) -> anyhow::Result<BookConfig> {
     }
 
     let config = BookConfig::with_layers(&layers)?;
+    let env_vars = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    let mut config = apply_env_overrides(config, env_vars)?;
+    // As in mdBook, a book's directories sit next to its book.toml
+    let root = config_path
+        .map(std::path::Path::new)
+        .filter(|path| path.exists())
+        .and_then(std::path::Path::parent)
+        .filter(|root| !root.as_os_str().is_empty());
+    if let Some(root) = root {
+        config.build.resolve_dirs(root);
+    }
+    config.validate()?;
     Ok(config)
 }
 
+/// Applies nested `MDBOOK_` variables such as `MDBOOK_OUTPUT__HTML__OG_IMAGE`
+/// over the config files, with `__` separating table names. Values that parse
+/// as JSON (`true`, `3`, `["a"]`) keep that type; anything else is a string.
+fn apply_env_overrides(
+    config: BookConfig,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> anyhow::Result<BookConfig> {
+    let mut overrides = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let path = name.strip_prefix("MDBOOK_")?;
+            // Top-level names are read by the `Layer::Env` layer
+            path.contains("__").then(|| (path.to_lowercase(), value))
+        })
+        .peekable();
+    if overrides.peek().is_none() {
+        return Ok(config);
+    }
+
+    let mut root = serde_json::to_value(&config)?;
+    for (path, raw) in overrides {
+        let value = serde_json::from_str(&raw).unwrap_or(serde_json::Value::String(raw));
+        let mut target = &mut root;
+        for key in path.split("__") {
+            if !target.is_object() {
+                *target = serde_json::Value::Object(serde_json::Map::new());
+            }
+            let table = target.as_object_mut().expect("just made an object");
+            // `output.html` keys are kebab-case, the rest snake_case
+            let kebab = key.replace('_', "-");
+            let key = if !table.contains_key(key) && table.contains_key(&kebab) {
+                kebab
+            } else {
+                key.to_string()
+            };
+            target = table.entry(key).or_insert(serde_json::Value::Null);
+        }
+        *target = value;
+    }
+
+    serde_json::from_value(root)
+        .map_err(|e| anyhow::anyhow!("Invalid MDBOOK_ environment override: {e}"))
+}
+
+impl BookConfig {
+    /// Checks settings that deserialize fine but can't be used
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first invalid setting
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(1..=6).contains(&self.markdown.title_heading_level) {
+            anyhow::bail!(
+                "Invalid markdown.title_heading_level `{}`: must be between 1 and 6",
+                self.markdown.title_heading_level
+            );
+        }
+        let sample = jiff::civil::date(2024, 1, 1)
+            .at(0, 0, 0, 0)
+            .to_zoned(jiff::tz::TimeZone::UTC)?;
+        jiff::fmt::strtime::format(&self.output.html.date_format, &sample).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid output.html.date-format `{}`: {e}",
+                self.output.html.date_format
+            )
+        })?;
+        if let Some(width) = &self.output.html.content_max_width {
+            if !is_css_length(width) {
+                anyhow::bail!(
+                    "Invalid output.html.content-max-width `{width}`: expected a CSS length such as 60rem"
+                );
+            }
+        }
+        let html = &self.output.html;
+        for (key, value) in [
+            ("font-family", &html.font_family),
+            ("code-font-family", &html.code_font_family),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| v.contains([';', '}', '<'])) {
+                anyhow::bail!(
+                    "Invalid output.html.{key} `{value}`: must not contain `;`, `}}` or `<`"
+                );
+            }
+        }
+        for entry in &self.overrides {
+            entry
+                .matcher()
+                .map_err(|e| anyhow::anyhow!("Invalid overrides glob `{}`: {e}", entry.glob))?;
+        }
+        for (key, patterns) in [
+            ("build.ignore", &self.build.ignore),
+            ("build.include", &self.build.include),
+        ] {
+            for pattern in patterns {
+                path_glob(pattern.strip_prefix('!').unwrap_or(pattern))
+                    .map_err(|e| anyhow::anyhow!("Invalid {key} pattern `{pattern}`: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `value` is a plain CSS length such as `720px`, `60rem` or `80%`,
+/// or `none` to leave the width unbounded
+fn is_css_length(value: &str) -> bool {
+    const UNITS: &[&str] = &[
+        "px", "rem", "em", "ch", "ex", "vw", "vh", "vmin", "vmax", "%", "cm", "mm", "in", "pt",
+        "pc",
+    ];
+    if value == "none" || value == "0" {
+        return true;
+    }
+    UNITS.iter().any(|unit| {
+        value.strip_suffix(unit).is_some_and(|number| {
+            !number.is_empty() && !number.starts_with('-') && number.parse::<f64>().is_ok()
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,6 +943,11 @@ mod tests {
         let input = MarkdownInput::default();
         assert!(matches!(input.format, MarkdownFormat::Markdown));
         assert!(!input.frontmatter);
+        assert!(!input.fail_on_invalid_utf8);
+        assert_eq!(input.extensions, vec!["md", "markdown", "mdx"]);
+        assert!(input.footnotes);
+        assert!(!input.smart_punctuation);
+        assert_eq!(input.title_heading_level, 1);
     }
 
     #[test]
@@ -318,6 +1046,118 @@ frontmatter = true
         Ok(())
     }
 
+    #[test]
+    fn test_build_dirs_relative_to_config_file() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let book_dir = temp_dir.path().join("other");
+        fs::create_dir_all(&book_dir)?;
+        let book_toml = book_dir.join("book.toml");
+
+        fs::write(&book_toml, "[build]\nsrc-dir = \"docs\"\n")?;
+        let config = load_config(Some(book_toml.to_str().unwrap()))?;
+        let dir = |dir: &Option<String>| dir.as_deref().map(std::path::PathBuf::from);
+        assert_eq!(dir(&config.build.src_dir), Some(book_dir.join("docs")));
+        assert_eq!(dir(&config.build.build_dir), Some(book_dir.join("book")));
+
+        // Absolute directories stay where they are
+        let site = temp_dir.path().join("site");
+        fs::write(&book_toml, format!("[build]\nbuild_dir = {site:?}\n"))?;
+        let config = load_config(Some(book_toml.to_str().unwrap()))?;
+        assert_eq!(dir(&config.build.src_dir), Some(book_dir.join("src")));
+        assert_eq!(dir(&config.build.build_dir), Some(site));
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides_book_toml_in_dump() -> anyhow::Result<()> {
+        let _guard = CWD_MUTEX.lock().unwrap();
+
+        let temp_dir = TempDir::new()?;
+        fs::write(
+            temp_dir.path().join("book.toml"),
+            "[output.html]\nog-image = \"/img/from-toml.png\"\n",
+        )?;
+        let original_dir = std::env::current_dir()?;
+        std::env::set_current_dir(temp_dir.path())?;
+        std::env::set_var("MDBOOK_OUTPUT__HTML__OG_IMAGE", "/img/from-env.png");
+
+        let config = load_config(None);
+
+        std::env::remove_var("MDBOOK_OUTPUT__HTML__OG_IMAGE");
+        std::env::set_current_dir(original_dir)?;
+
+        let dump = crate::core::dump_config(&config?, crate::core::ConfigFormat::Toml)?;
+        assert!(dump.contains("og-image = '/img/from-env.png'"));
+        assert!(!dump.contains("from-toml"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_env_overrides() -> anyhow::Result<()> {
+        let vars = [
+            ("MDBOOK_BOOK__TITLE", "From env"),
+            ("MDBOOK_MARKDOWN__SMART_PUNCTUATION", "true"),
+            ("MDBOOK_OUTPUT__HTML__DATE_FORMAT", "%d %B %Y"),
+            ("MDBOOK_OUTPUT__HTML__SIDEBAR__WIDTH", "20rem"),
+            ("MDBOOK_BUILD__JOBS", "2"),
+            ("MDBOOK_TITLE", "ignored"),
+            ("OTHER__TITLE", "ignored"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let config = apply_env_overrides(BookConfig::default(), vars)?;
+        assert_eq!(config.book.title, "From env");
+        assert!(config.markdown.smart_punctuation);
+        assert_eq!(config.output.html.date_format, "%d %B %Y");
+        assert_eq!(config.output.html.sidebar.width.as_deref(), Some("20rem"));
+        assert_eq!(config.build.jobs.map(|jobs| jobs.get()), Some(2));
+
+        let invalid = [("MDBOOK_BUILD__JOBS".to_string(), "many".to_string())];
+        assert!(apply_env_overrides(BookConfig::default(), invalid).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_with_overrides() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("overrides.toml");
+        fs::write(
+            &config_path,
+            r#"
+[[overrides]]
+glob = "api/**"
+markdown = { format = "gfm" }
+
+[[overrides]]
+glob = "blog/*.md"
+markdown = { frontmatter = true }
+output.html = { strip-title-heading = true }
+"#,
+        )?;
+
+        let config = load_config(Some(config_path.to_str().unwrap()))?;
+        assert_eq!(config.overrides.len(), 2);
+        assert!(config.overrides[0].matches(std::path::Path::new("api/v1/client.md")));
+        assert!(!config.overrides[0].matches(std::path::Path::new("intro.md")));
+        assert!(!config.overrides[1].matches(std::path::Path::new("blog/2024/post.md")));
+
+        let mut page_config = config.clone();
+        config.overrides[1].apply(&mut page_config);
+        assert!(page_config.markdown.frontmatter);
+        assert!(page_config.output.html.strip_title_heading);
+        assert!(matches!(
+            page_config.markdown.format,
+            MarkdownFormat::Markdown
+        ));
+
+        fs::write(&config_path, "[[overrides]]\nglob = \"api/[\"\n")?;
+        assert!(load_config(Some(config_path.to_str().unwrap())).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_load_config_with_custom_json() -> anyhow::Result<()> {
         // Lock mutex to prevent race conditions with other tests that change cwd
@@ -431,6 +1271,64 @@ frontmatter = true
         assert_eq!(config.boost_paragraph, 1);
         assert!(!config.expand);
         assert_eq!(config.heading_split_level, 2);
+        assert_eq!(config.root_selector, None);
+        assert!(config.exclude_selectors.is_empty());
+        assert_eq!(config.index_dir, "pagefind");
+
+        let default = SearchConfig::default();
+        assert_eq!(default.limit_results, config.limit_results);
+        assert_eq!(default.boost_title, config.boost_title);
+        assert_eq!(default.heading_split_level, config.heading_split_level);
+        assert_eq!(default.index_dir, config.index_dir);
+    }
+
+    #[test]
+    fn test_validate_date_format() {
+        let mut config = BookConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.output.html.date_format = "%d %B %Y".to_string();
+        assert!(config.validate().is_ok());
+
+        config.output.html.date_format = "%Y-%!".to_string();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("date-format"));
+
+        let mut config = BookConfig::default();
+        config.markdown.title_heading_level = 7;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("title_heading_level"));
+    }
+
+    #[test]
+    fn test_validate_content_max_width() {
+        let mut config = BookConfig::default();
+        for width in ["60rem", "720px", "85%", "0", "none", "42.5em"] {
+            config.output.html.content_max_width = Some(width.to_string());
+            assert!(config.validate().is_ok(), "{width} should be accepted");
+        }
+        for width in ["wide", "60", "-3px", "rem", "60rem; color: red"] {
+            config.output.html.content_max_width = Some(width.to_string());
+            let err = config.validate().unwrap_err();
+            assert!(err.to_string().contains("content-max-width"), "{width}");
+        }
+    }
+
+    #[test]
+    fn test_validate_font_families() {
+        let mut config = BookConfig::default();
+        config.output.html.font_family = Some("'Inter', sans-serif".to_string());
+        config.output.html.code_font_family = Some("\"Fira Code\", monospace".to_string());
+        assert!(config.validate().is_ok());
+        for font in [
+            "serif; color: red",
+            "serif } body { color: red",
+            "serif</style>",
+        ] {
+            config.output.html.code_font_family = Some(font.to_string());
+            let err = config.validate().unwrap_err();
+            assert!(err.to_string().contains("code-font-family"), "{font}");
+        }
     }
 
     #[test]
@@ -438,5 +1336,30 @@ frontmatter = true
         let output = HtmlOutput::default();
         assert!(!output.mathjax_support);
         assert!(!output.allow_html);
+        assert!(!output.number_headings.enabled);
+        assert_eq!(output.number_headings.max_level, 3);
+        assert!(output.sidebar.enabled);
+        assert_eq!(output.sidebar.width, None);
+        assert!(!output.print_page);
+        assert!(!output.optimize_images);
+        assert_eq!(output.last_updated, LastUpdatedSource::Off);
+        assert_eq!(output.date_format, "%Y-%m-%d");
+        assert!(output.generate_index);
+        assert!(output.open_graph);
+        assert_eq!(output.og_image, None);
+        assert!(output.redirect.is_empty());
+        assert_eq!(output.redirect_format, RedirectFormat::Meta);
+        assert!(!output.strip_title_heading);
+    }
+
+    #[test]
+    fn test_sidebar_config_serde() {
+        let sidebar: SidebarConfig = serde_json::from_str("{}").unwrap();
+        assert!(sidebar.enabled);
+
+        let sidebar: SidebarConfig =
+            serde_json::from_str(r#"{"enabled": false, "width": "360px"}"#).unwrap();
+        assert!(!sidebar.enabled);
+        assert_eq!(sidebar.width.as_deref(), Some("360px"));
     }
 }
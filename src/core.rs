@@ -1,39 +1,60 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use jiff::Zoned;
+use clap::{Parser, Subcommand};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
 
 use markdown::to_html_with_options;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 use tera::{Context as TeraContext, Tera};
 use walkdir::WalkDir;
 
-use crate::config::{BookConfig, MarkdownFormat};
-use crate::pagefind_service::PagefindBuilder;
+use crate::config::{
+    AltTextPolicy, Book, BookConfig, LastUpdatedSource, MarkdownFormat, RedirectFormat,
+    RobotsConfig, SearchConfig, TrailingSlash,
+};
+#[cfg(feature = "syntax-highlighting")]
+use crate::config::{HighlightStyle, SyntaxConfig};
+use crate::pagefind_service::{PagefindBuilder, PagefindError};
 use markdown::mdast::Node;
 use markdown::to_mdast;
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "parallel")]
+use std::num::NonZeroUsize;
+#[cfg(feature = "syntax-highlighting")]
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "syntax-highlighting")]
+use std::sync::{Arc, OnceLock};
+use std::time::UNIX_EPOCH;
+#[cfg(feature = "syntax-highlighting")]
+use syntect::easy::HighlightLines;
 #[cfg(feature = "syntax-highlighting")]
 use syntect::highlighting::ThemeSet;
 #[cfg(feature = "syntax-highlighting")]
-use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::html::{
+    styled_line_to_highlighted_html, ClassStyle, ClassedHTMLGenerator, IncludeBackground,
+};
 #[cfg(feature = "syntax-highlighting")]
 use syntect::parsing::SyntaxSet;
 #[cfg(feature = "syntax-highlighting")]
 use syntect::util::LinesWithEndings;
+use thiserror::Error;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Input directory containing markdown files
+    /// Input directory containing markdown files; defaults to `build.src_dir`, then `src`
     #[arg(short, long)]
-    pub input: String,
+    pub input: Option<String>,
 
-    /// Output directory for HTML files
+    /// Output directory for HTML files; defaults to `build.build_dir`, then `book`
     #[arg(short, long)]
-    pub output: String,
+    pub output: Option<String>,
 
     /// Optional path to config file
     #[arg(short, long)]
@@ -44,6 +65,12 @@ pub struct Args {
     #[cfg(feature = "watcher")]
     pub watch: bool,
 
+    /// Another file or directory to watch, besides the input, templates and
+    /// config (repeatable; added to `build.watch_paths`)
+    #[arg(long = "watch-path", value_name = "PATH")]
+    #[cfg(feature = "watcher")]
+    pub watch_paths: Vec<String>,
+
     /// Serve the book at <http://localhost:3000>
     #[arg(short, long)]
     #[cfg(feature = "server")]
@@ -53,1147 +80,8877 @@ pub struct Args {
     #[arg(long, default_value = "3000")]
     #[cfg(feature = "server")]
     pub port: u16,
-}
 
-#[derive(Serialize, Debug, Clone)]
-struct PageData {
-    title: String,
-    content: String,
-    sections: Vec<Section>,
-    previous: Option<PageInfo>,
-    next: Option<PageInfo>,
-}
+    /// Serve the book under this path instead of the root, e.g. `/docs` to
+    /// match the path of `book.base_url`; the book's links and assets are
+    /// built to point there
+    #[arg(long, value_name = "PATH")]
+    #[cfg(feature = "server")]
+    pub mount: Option<String>,
 
-#[derive(Serialize, Debug, Clone)]
-struct Section {
-    title: String,
-    pages: Vec<PageInfo>,
-}
+    /// Open the served book in the default browser once the server is up
+    #[arg(long)]
+    #[cfg(feature = "server")]
+    pub open: bool,
 
-#[derive(Serialize, Debug, Clone)]
-pub struct PageInfo {
-    pub title: String,
-    pub path: String,
-}
+    /// Bundle the whole book into one self-contained index.html
+    #[arg(long)]
+    pub single_file: bool,
 
-#[cfg(feature = "tokio")]
-/// Build the book from markdown files to HTML
-///
-/// # Errors
-///
-/// Returns an error if the build process fails, including template rendering,
-/// file I/O errors, or search indexing failures
-pub async fn build(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    build_impl(args, config, watch_enabled).await
-}
+    /// Run the whole pipeline and report what would be written, without touching the disk
+    #[arg(long)]
+    pub dry_run: bool,
 
-#[cfg(not(feature = "tokio"))]
-pub fn build(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    build_impl(args, config, watch_enabled)
-}
+    /// Produce byte-identical output for identical input: dates come from
+    /// `SOURCE_DATE_EPOCH` (or the Unix epoch) instead of the clock
+    #[arg(long)]
+    pub deterministic: bool,
 
-#[cfg(feature = "tokio")]
-async fn build_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    build_sync_impl(args, config, watch_enabled).await
+    /// Print the effective configuration, after merging book.toml, `--config`
+    /// and `MDBOOK_` variables, then exit without building
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "toml")]
+    pub print_config: Option<ConfigFormat>,
+
+    /// Fail the build if it reports any warnings
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Keep rendering when a page fails, writing an error page in its place,
+    /// and report every failure at the end
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Build pages whose frontmatter sets `draft: true`, e.g. for a preview
+    #[arg(long)]
+    pub drafts: bool,
+
+    /// Set by the `lint` subcommand: report markdown issues rather than build
+    #[arg(skip)]
+    pub lint: bool,
+
+    /// Empty the output directory before building (default: `build.clean_before`)
+    #[arg(long)]
+    pub clean: bool,
+
+    /// Number of threads rendering pages (default: `build.jobs`, or the number of CPUs)
+    #[arg(short, long)]
+    #[cfg(feature = "parallel")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Directory for the build manifest (default: `build.cache_dir`, or a
+    /// per-output directory under the OS cache dir)
+    #[arg(long)]
+    pub cache_dir: Option<String>,
+
+    /// Whether a build given no cache directory keeps its manifest in the OS
+    /// cache dir. Set for the command line; library builds keep none unless
+    /// `cache_dir` or `build.cache_dir` names one.
+    #[arg(skip)]
+    pub default_cache_dir: bool,
+
+    /// Output format: `html` builds the site, `json` writes a single book.json export
+    #[arg(long, value_enum, default_value_t = OutputFormat::Html, conflicts_with = "single_file")]
+    pub format: OutputFormat,
 }
 
-#[cfg(not(feature = "tokio"))]
-fn build_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    build_sync_impl_sync(args, config, watch_enabled)
+/// The command line: build flags, or a subcommand taking the same flags
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: Args,
 }
 
-#[cfg(feature = "tokio")]
-async fn build_sync_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    build_sync_impl_sync(args, config, watch_enabled)?;
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Build the book once, then serve it; add `--watch` to rebuild on changes
+    #[cfg(feature = "server")]
+    Serve(Args),
+    /// Check pages for common markdown issues instead of building
+    Lint(Args),
+}
 
-    // After generating HTML files, run Pagefind indexing if search feature is enabled
-    #[cfg(all(feature = "search", feature = "tokio"))]
-    {
-        match PagefindBuilder::new(PathBuf::from(&args.output)).await {
-            Ok(pagefind) => {
-                if let Err(e) = pagefind.build().await {
-                    eprintln!("Search indexing failed: {e}");
-                }
+impl Cli {
+    /// The build arguments, with whatever the subcommand implies applied
+    pub fn into_args(self) -> Args {
+        let mut args = match self.command {
+            #[cfg(feature = "server")]
+            Some(Command::Serve(mut args)) => {
+                args.serve = true;
+                args
             }
-            Err(e) => {
-                eprintln!("Failed to create search builder: {e}");
+            Some(Command::Lint(mut args)) => {
+                args.lint = true;
+                args
             }
+            None => self.args,
+        };
+        args.default_cache_dir = true;
+        args
+    }
+}
+
+impl Args {
+    /// Whether the build writes a browsable multi-page HTML site
+    fn builds_site(&self) -> bool {
+        self.format == OutputFormat::Html && !self.single_file
+    }
+
+    /// The URL path the book is served from, which its root-relative links
+    /// start with: `--mount`, else the path of `book.base_url`, else `/`
+    fn site_root(&self, config: &BookConfig) -> String {
+        #[cfg(feature = "server")]
+        if let Some(mount) = &self.mount {
+            return url_root(mount);
         }
+        let base_url = config.book.base_url.as_deref().unwrap_or_default();
+        let path = match base_url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |at| &rest[at..]),
+            None => base_url,
+        };
+        url_root(path)
     }
 
-    Ok(())
+    /// The directory the book is read from: `--input`, else `build.src_dir`,
+    /// else `src`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if that directory does not exist
+    pub fn input_dir<'a>(&'a self, config: &'a BookConfig) -> Result<&'a str> {
+        let input = self
+            .input
+            .as_deref()
+            .or(config.build.src_dir.as_deref())
+            .unwrap_or(DEFAULT_SRC_DIR);
+        if !Path::new(input).is_dir() {
+            anyhow::bail!(
+                "Input directory `{input}` not found: pass --input or set build.src_dir in book.toml"
+            );
+        }
+        Ok(input)
+    }
+
+    /// The directory the book is written to: `--output`, else
+    /// `build.build_dir`, else `book`
+    pub fn output_dir<'a>(&'a self, config: &'a BookConfig) -> &'a str {
+        self.output
+            .as_deref()
+            .or(config.build.build_dir.as_deref())
+            .unwrap_or(DEFAULT_BUILD_DIR)
+    }
 }
 
-fn build_sync_impl_sync(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
-    // Initialize Tera with configured templates directory
-    let mut tera = Tera::default();
+/// Performs the build's writes to the output directory, or for `--dry-run`
+/// only reports them
+#[derive(Debug, Clone, Copy, Default)]
+struct OutputFs {
+    dry_run: bool,
+    /// Permissions set on every file written, from `build.file_mode`
+    file_mode: Option<u32>,
+    /// Permissions set on every directory created, from `build.dir_mode`
+    dir_mode: Option<u32>,
+}
 
-    // Add template files from the configured directory
-    let template_files = [
-        ("page", "page.html.tera"),
-        ("index", "index.html.tera"),
-        ("sidebar", "sidebar.html.tera"),
-        ("footer", "footer.html.tera"),
-        ("header", "header.html.tera"),
-    ];
+const BUILD_MANIFEST: &str = "build-manifest.json";
 
-    for (name, file) in template_files {
-        let template_path = format!("{}/{}", config.paths.templates, file);
-        let template_content = if Path::new(&template_path).exists() {
-            fs::read_to_string(&template_path)
-                .with_context(|| format!("Failed to read template: {template_path}"))?
-        } else {
-            // Load default template content directly
-            match file {
-                "page.html.tera" => include_str!("templates/page.html.tera").to_string(),
-                "index.html.tera" => include_str!("templates/index.html.tera").to_string(),
-                "sidebar.html.tera" => include_str!("templates/sidebar.html.tera").to_string(),
-                "footer.html.tera" => include_str!("templates/footer.html.tera").to_string(),
-                "header.html.tera" => include_str!("templates/header.html.tera").to_string(),
-                _ => return Err(anyhow::anyhow!("Unknown template file: {}", file)),
-            }
-        };
+/// Left in output directories md-book creates, so `--clean` knows it may
+/// empty them
+const OUTPUT_MARKER: &str = ".md-book";
 
-        tera.add_raw_template(name, &template_content)
-            .with_context(|| format!("Failed to add template: {name}"))?;
-    }
+/// Where the book is read from and written to when neither the command line
+/// nor `[build]` says
+pub(crate) const DEFAULT_SRC_DIR: &str = "src";
+pub(crate) const DEFAULT_BUILD_DIR: &str = "book";
 
-    // Create output directory if it doesn't exist
-    fs::create_dir_all(&args.output)?;
+/// Distinguishes the temporary manifests of builds running in one process
+static MANIFEST_WRITES: AtomicUsize = AtomicUsize::new(0);
 
-    // Copy static assets
-    copy_static_assets(&args.output, &config.paths.templates, config)?;
+/// What the previous build wrote to each output file, keyed by its path
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BuildManifest {
+    #[serde(default)]
+    files: BTreeMap<String, ManifestEntry>,
+}
 
-    // Collect all pages first
-    let mut all_pages = Vec::new();
-    let mut section_map: BTreeMap<String, Vec<PageInfo>> = BTreeMap::new();
-    let mut root_pages: Vec<PageInfo> = Vec::new();
+/// A written file's SHA-256, with the size and modification time it had
+/// afterwards so edits made outside the build are noticed
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    hash: String,
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
 
-    // First pass: collect all pages
-    let mut entries: Vec<_> = WalkDir::new(&args.input)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
-        .collect();
+impl ManifestEntry {
+    fn stat(dest: &Path, hash: String) -> Option<Self> {
+        let metadata = fs::metadata(dest).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            hash,
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
 
-    // Sort entries by path to ensure consistent ordering
-    entries.sort_by_key(|e| e.path().to_path_buf());
+impl BuildManifest {
+    /// Loads the manifest in `cache_dir`; a missing or unreadable one is empty
+    fn load(cache_dir: &Path) -> Self {
+        fs::read(cache_dir.join(BUILD_MANIFEST))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
 
-    for entry in &entries {
-        let rel_path = entry.path().strip_prefix(&args.input)?;
-        let parent_dir = rel_path.parent().and_then(|p| p.to_str()).unwrap_or("");
+    /// Whether `dest` still holds exactly what writing `contents` left there
+    fn is_fresh(&self, dest: &Path, contents: &[u8]) -> bool {
+        let Some(entry) = self.files.get(&manifest_key(dest)) else {
+            return false;
+        };
+        entry.hash == content_hash(contents)
+            && ManifestEntry::stat(dest, entry.hash.clone()).as_ref() == Some(entry)
+    }
 
-        let content = fs::read_to_string(entry.path())?;
-        let page_info = PageInfo {
-            title: extract_title(&content).unwrap_or_else(|| {
-                entry.path().file_stem().map_or_else(
-                    || "Untitled".to_string(),
-                    |s| s.to_string_lossy().into_owned(),
-                )
-            }),
-            path: format!("/{}", rel_path.with_extension("html").display()),
+    fn record(&mut self, dest: &Path, contents: &[u8]) {
+        let key = manifest_key(dest);
+        match ManifestEntry::stat(dest, content_hash(contents)) {
+            Some(entry) => self.files.insert(key, entry),
+            None => self.files.remove(&key),
         };
+    }
 
-        all_pages.push(page_info.clone());
+    /// Drops the entries for files under `output` that this build did not
+    /// write, such as pages since removed, and for files no longer there
+    fn prune(&mut self, output: &Path, written: &BTreeSet<String>) {
+        let output = fs::canonicalize(output).unwrap_or_else(|_| output.to_path_buf());
+        self.files.retain(|key, _| {
+            let path = Path::new(key);
+            path.exists() && (written.contains(key) || !path.starts_with(&output))
+        });
+    }
 
-        if parent_dir.is_empty() {
-            root_pages.push(page_info);
+    /// Writes the manifest to a temporary file and renames it into place, so
+    /// builds sharing `cache_dir` never read a half-written manifest
+    fn save(&self, cache_dir: &Path) -> Result<()> {
+        fs::create_dir_all(cache_dir).with_context(|| {
+            format!("Failed to create cache directory: {}", cache_dir.display())
+        })?;
+        let temp = cache_dir.join(format!(
+            "{BUILD_MANIFEST}.{}-{}.tmp",
+            std::process::id(),
+            MANIFEST_WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write file: {}", temp.display()))?;
+        fs::rename(&temp, cache_dir.join(BUILD_MANIFEST)).with_context(|| {
+            format!(
+                "Failed to replace build manifest in {}",
+                cache_dir.display()
+            )
+        })
+    }
+}
+
+fn manifest_key(dest: &Path) -> String {
+    let dest = dest
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+        .zip(dest.file_name())
+        .map_or_else(|| dest.to_path_buf(), |(parent, name)| parent.join(name));
+    dest.to_string_lossy().into_owned()
+}
+
+/// The hex SHA-256 of `bytes`, the same on every platform and toolchain
+fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The cache directory used when neither `--cache-dir` nor `build.cache_dir`
+/// is set: one per output directory under `$XDG_CACHE_HOME` (or the platform
+/// equivalent), falling back to `.md-book-cache` next to the output
+fn default_cache_dir(output: &Path) -> PathBuf {
+    let output = fs::canonicalize(output).unwrap_or_else(|_| output.to_path_buf());
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    match base {
+        Some(base) => base
+            .join("md-book")
+            .join(&content_hash(output.as_os_str().as_encoded_bytes())[..16]),
+        None => output
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(".md-book-cache"),
+    }
+}
+
+impl OutputFs {
+    fn create_dir_all(self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let path = path.as_ref();
+        let created: Vec<&Path> = path
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+        fs::create_dir_all(path)?;
+        for dir in created {
+            set_mode(dir, self.dir_mode)?;
+        }
+        Ok(())
+    }
+
+    fn write(self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+        if self.dry_run {
+            println!("Would write {}", path.as_ref().display());
+            return Ok(());
+        }
+        fs::write(&path, contents)?;
+        set_mode(path.as_ref(), self.file_mode)
+    }
+
+    fn remove(self, path: &Path) -> std::io::Result<()> {
+        if self.dry_run {
+            println!("Would remove {}", path.display());
+            return Ok(());
+        }
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)
         } else {
-            section_map
-                .entry(parent_dir.to_string())
-                .or_default()
-                .push(page_info);
+            fs::remove_file(path)
         }
     }
 
-    // Convert the map to sections
-    let mut sections = Vec::new();
+    fn copy(self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> std::io::Result<u64> {
+        if self.dry_run {
+            println!(
+                "Would copy {} to {}",
+                from.as_ref().display(),
+                to.as_ref().display()
+            );
+            return Ok(0);
+        }
+        let copied = fs::copy(from, &to)?;
+        set_mode(to.as_ref(), self.file_mode)?;
+        Ok(copied)
+    }
+}
 
-    // Add root pages first if they exist
-    if !root_pages.is_empty() {
-        sections.push(Section {
-            title: "Guide".to_string(),
-            pages: root_pages,
-        });
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
     }
+}
 
-    // Add other sections
-    for (title, pages) in section_map {
-        sections.push(Section { title, pages });
+/// Permissions are left to the platform outside Unix
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Parses an octal permission setting such as `"0644"` or `"0o755"`
+fn parse_mode(key: &str, value: Option<&str>) -> Result<Option<u32>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+        _ => anyhow::bail!("Invalid {key} `{value}`: expected an octal mode such as 0644"),
     }
+}
 
-    let total_pages = all_pages.len();
-    println!("Total pages: {total_pages}");
+/// What a build produces
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// A static HTML site
+    #[default]
+    Html,
+    /// One JSON document with the book metadata, section tree and every page
+    Json,
+}
 
-    // Get current year using Jiff
-    let current_year = Zoned::now().year().to_string();
+/// How `--print-config` writes the effective configuration
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+}
 
-    // Initialize syntax highlighting if feature is enabled
-    #[cfg(feature = "syntax-highlighting")]
-    let ss = SyntaxSet::load_defaults_newlines();
+/// Serializes the configuration a build would use, for `--print-config`
+///
+/// # Errors
+///
+/// Returns an error if the configuration can't be represented in `format`
+pub fn dump_config(config: &BookConfig, format: ConfigFormat) -> Result<String> {
+    Ok(match format {
+        // Going through `Value` writes plain keys before tables, as TOML requires
+        ConfigFormat::Toml => toml::to_string_pretty(&toml::Value::try_from(config)?)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(config)? + "\n",
+    })
+}
 
-    #[cfg(feature = "syntax-highlighting")]
-    {
-        // Add syntax highlighting CSS
-        let ts = ThemeSet::load_defaults();
-        // TODO: Make this configurable
-        let theme = &ts.themes["Solarized (light)"];
-        let syntax_css = syntect::html::css_for_theme_with_class_style(theme, ClassStyle::Spaced)
-            .map_err(|e| anyhow::anyhow!("CSS generation error: {:?}", e))?;
+#[derive(Serialize, Debug, Clone)]
+struct PageData {
+    title: String,
+    content: String,
+    /// Plain-text summary for cards and feeds; see [`page_summary`]
+    summary: String,
+    sections: Vec<Section>,
+    previous: Option<PageInfo>,
+    next: Option<PageInfo>,
+}
 
-        fs::write(format!("{}/css/syntax.css", args.output), syntax_css)?;
-    }
+/// A frontmatter tag and the pages carrying it, for its generated index page
+#[derive(Serialize, Debug, Clone)]
+struct Tag {
+    /// The tag as spelled by the first page carrying it, in book order
+    name: String,
+    path: String,
+    #[serde(skip)]
+    html_path: PathBuf,
+    pages: Vec<PageInfo>,
+}
 
-    // Process each markdown file
-    for (current_page, entry) in entries.iter().enumerate() {
-        if entry.path().extension().is_some_and(|ext| ext == "md") {
-            let rel_path = entry.path().strip_prefix(&args.input)?;
-            let html_path = format!(
-                "{}/{}",
-                args.output,
-                rel_path.with_extension("html").display()
-            );
+/// A `[blog]` post, as shown on its own page and on the listing
+#[derive(Serialize, Debug, Clone)]
+struct BlogPost {
+    title: String,
+    path: String,
+    date: Option<DisplayDate>,
+    author: Option<String>,
+    summary: String,
+}
 
-            if let Some(parent) = Path::new(&html_path).parent() {
-                fs::create_dir_all(parent)?;
-            }
+/// A group of pages in the navigation, one per source directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Section {
+    /// `None` for root pages rendered without a section heading
+    pub title: Option<String>,
+    pub pages: Vec<PageInfo>,
+    /// Whether this section contains the page being rendered
+    pub is_active: bool,
+}
 
-            let markdown_content = fs::read_to_string(entry.path())?;
-            #[cfg(feature = "syntax-highlighting")]
-            let html_content = convert_md_links_to_html(&process_markdown_with_highlighting(
-                &markdown_content,
-                &ss,
-                config,
-            )?);
-            #[cfg(not(feature = "syntax-highlighting"))]
-            let html_content =
-                convert_md_links_to_html(&process_markdown_basic(&markdown_content, config)?);
+/// A rendered page as it appears in the combined print and single-file pages
+#[derive(Serialize, Debug, Clone)]
+struct PrintChapter {
+    /// Anchor id of the chapter within the combined page
+    id: String,
+    title: String,
+    path: String,
+    content: String,
+}
 
-            let previous = if current_page > 0 {
-                Some(all_pages[current_page - 1].clone())
-            } else {
-                None
-            };
+/// A page's card on the generated landing page
+#[derive(Serialize, Debug, Clone)]
+struct Card {
+    title: String,
+    path: String,
+    summary: String,
+    /// Shoelace icon name, from the `icon` frontmatter key
+    icon: Option<String>,
+    /// From the `image` frontmatter key, resolved against the page's directory
+    image: Option<String>,
+}
 
-            let next = if current_page + 1 < total_pages {
-                Some(all_pages[current_page + 1].clone())
-            } else {
-                None
-            };
+/// A section of the landing page's card grid, mirroring [`Section`]
+#[derive(Serialize, Debug, Clone)]
+struct CardSection {
+    title: Option<String>,
+    pages: Vec<Card>,
+}
 
-            let page_data = PageData {
-                title: extract_title(&markdown_content).unwrap_or_else(|| {
-                    entry
-                        .path()
-                        .file_stem()
-                        .map(|s| s.to_string_lossy().into_owned())
-                        .unwrap_or_else(|| "Untitled".to_string())
-                }),
-                content: html_content,
-                sections: sections.clone(),
-                previous,
-                next,
-            };
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageInfo {
+    pub title: String,
+    pub path: String,
+}
 
-            let mut context = TeraContext::new();
-            context.insert("year", &current_year);
-            context.insert("page", &page_data);
-            context.insert("config", &config);
-            context.insert(
-                "current_path",
-                &rel_path.with_extension("html").display().to_string(),
-            );
-            context.insert("watch_enabled", &watch_enabled);
+/// A page as it appears in the JSON export
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ExportedPage {
+    title: String,
+    path: String,
+    frontmatter: Frontmatter,
+    html: String,
+    /// The page's readable text, one line per block
+    text: String,
+}
+
+/// The whole book as written by `--format json`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BookExport {
+    book: Book,
+    sections: Vec<Section>,
+    pages: Vec<ExportedPage>,
+}
 
-            let rendered = tera
-                .render("page", &context)
-                .with_context(|| format!("Failed to render page: {}", html_path))?;
-            fs::write(&html_path, rendered)
-                .with_context(|| format!("Failed to write file: {}", html_path))?;
+/// Metadata from a page's YAML (`---`) or TOML (`+++`) frontmatter block
+pub type Frontmatter = serde_json::Map<String, serde_json::Value>;
+
+/// A markdown file read from the input directory
+struct SourceFile {
+    rel_path: PathBuf,
+    /// Where the page is written, relative to the output directory
+    html_path: PathBuf,
+    content: String,
+    /// Empty unless `markdown.frontmatter` is enabled
+    frontmatter: Frontmatter,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Open Graph and Twitter Card values for a page's `<head>`
+#[derive(Serialize, Debug, Clone)]
+struct OpenGraph {
+    title: String,
+    /// Absolute page URL; needs `book.base_url`
+    url: Option<String>,
+    image: Option<String>,
+    /// Twitter card type
+    card: &'static str,
+}
+
+impl OpenGraph {
+    /// `None` when `output.html.open_graph` is off
+    fn new(config: &BookConfig, title: &str, current_path: &str) -> Option<Self> {
+        let html = &config.output.html;
+        if !html.open_graph {
+            return None;
         }
-    }
 
-    // Generate index page
-    let mut context = TeraContext::new();
-    context.insert("year", &current_year);
-    context.insert("config", &config);
-    context.insert("sections", &sections);
-    context.insert("current_path", &"index.html");
-
-    let index_page = all_pages.iter().find(|p| p.path == "/index.html");
-
-    if let Some(index) = index_page {
-        // If index.md exists, use its content
-        let index_path = Path::new(&args.input).join("index.md");
-        let markdown_content = fs::read_to_string(&index_path)
-            .with_context(|| format!("Failed to read index file: {}", index_path.display()))?;
-        #[cfg(feature = "syntax-highlighting")]
-        let html_content = convert_md_links_to_html(&process_markdown_with_highlighting(
-            &markdown_content,
-            &ss,
-            config,
-        )?);
-        #[cfg(not(feature = "syntax-highlighting"))]
-        let html_content =
-            convert_md_links_to_html(&process_markdown_basic(&markdown_content, config)?);
+        let image = html.og_image.as_deref().unwrap_or(&config.book.logo);
+        let image = if image.is_empty() {
+            None
+        } else if image.contains("://") {
+            Some(image.to_string())
+        } else {
+            canonical_url(config, image)
+        };
 
-        context.insert("has_index", &true);
-        context.insert("title", &index.title);
-        context.insert("content", &html_content);
-    } else {
-        // If no index.md, use the default template with cards
-        context.insert("has_index", &false);
-        context.insert("title", &"Documentation");
+        Some(Self {
+            title: title.to_string(),
+            url: canonical_url(config, current_path),
+            // A dedicated preview image gets the large card, a logo the small one
+            card: if html.og_image.is_some() {
+                "summary_large_image"
+            } else {
+                "summary"
+            },
+            image,
+        })
     }
+}
 
-    let rendered = tera
-        .render("index", &context)
-        .context("Failed to render index page")?;
-    fs::write(format!("{}/index.html", args.output), rendered)
-        .context("Failed to write index.html")?;
+/// A date as shown on a page
+#[derive(Serialize, Debug, Clone)]
+struct DisplayDate {
+    /// Machine-readable form for `<time datetime>`
+    datetime: String,
+    display: String,
+}
 
-    // Search indexing handled in async wrapper or skipped
-    #[cfg(not(all(feature = "search", feature = "tokio")))]
-    {
-        println!("Skipping search indexing (search or tokio feature not enabled)");
+impl DisplayDate {
+    /// Formats `date` with `output.html.date_format`
+    fn new(date: &Zoned, config: &BookConfig) -> Result<Self> {
+        let format = &config.output.html.date_format;
+        Ok(Self {
+            datetime: date.strftime("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            display: jiff::fmt::strtime::format(format, date)
+                .with_context(|| format!("Invalid date format: {format}"))?,
+        })
     }
-
-    Ok(())
 }
 
-fn extract_title(markdown: &str) -> Option<String> {
-    markdown
-        .lines()
-        .find(|line| line.starts_with("# "))
-        .map(|line| line[2..].trim().to_string())
+/// Looks up when pages last changed, according to `output.html.last_updated`.
+/// Git lookups spawn a process, so their results are cached per file.
+struct LastUpdated {
+    source: LastUpdatedSource,
+    git_dates: BTreeMap<PathBuf, Option<Zoned>>,
+    /// Reproducible builds clamp file modification times to the build date
+    latest: Option<Zoned>,
 }
 
-fn copy_static_assets(output_dir: &str, templates_dir: &str, _config: &BookConfig) -> Result<()> {
-    // Create components directory
-    fs::create_dir_all(format!("{}/components", output_dir))?;
-
-    // Copy CSS directory
-    let css_source = format!("{}/css", templates_dir);
-    let css_dest = format!("{}/css/", output_dir);
-    fs::create_dir_all(&css_dest)?;
-    if std::path::Path::new(&css_source).exists() {
-        for entry in WalkDir::new(&css_source) {
-            let entry = entry?;
-            let dest_path = css_dest.clone()
-                + entry
-                    .path()
-                    .strip_prefix(&css_source)?
-                    .to_str()
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Invalid UTF-8 in CSS path: {:?}", entry.path())
-                    })?;
-            if entry.file_type().is_file() {
-                fs::copy(entry.path(), dest_path)?;
-            }
+impl LastUpdated {
+    fn new(source: LastUpdatedSource, latest: Option<Zoned>) -> Self {
+        Self {
+            source,
+            git_dates: BTreeMap::new(),
+            latest,
         }
     }
 
-    // Copy JS directory
-    let js_source = format!("{}/js", templates_dir);
-    let js_dest = format!("{}/js/", output_dir);
-    fs::create_dir_all(&js_dest)?;
-    if std::path::Path::new(&js_source).exists() {
-        for entry in WalkDir::new(&js_source) {
-            let entry = entry?;
-            let dest_path = js_dest.clone()
-                + entry
-                    .path()
-                    .strip_prefix(&js_source)?
-                    .to_str()
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Invalid UTF-8 in JS path: {:?}", entry.path())
-                    })?;
-            if entry.file_type().is_file() {
-                fs::copy(entry.path(), dest_path)?;
+    /// `path` is the page's source file, when it came from disk
+    fn for_page(&mut self, path: Option<&Path>, frontmatter: &Frontmatter) -> Option<Zoned> {
+        match self.source {
+            LastUpdatedSource::Off => None,
+            LastUpdatedSource::Mtime => {
+                let modified = fs::metadata(path?).and_then(|m| m.modified()).ok()?;
+                let timestamp = Timestamp::try_from(modified).ok()?;
+                match &self.latest {
+                    Some(latest) if timestamp > latest.timestamp() => Some(latest.clone()),
+                    Some(latest) => Some(timestamp.to_zoned(latest.time_zone().clone())),
+                    None => Some(timestamp.to_zoned(TimeZone::system())),
+                }
             }
-        }
-    }
-    // Copy img directory from templates
-    let img_source = format!("{}/img", templates_dir);
-    let img_dest = format!("{}/img/", output_dir);
-    fs::create_dir_all(&img_dest)?;
-    if std::path::Path::new(&img_source).exists() {
-        for entry in WalkDir::new(&img_source) {
-            let entry = entry?;
-            let dest_path = img_dest.clone()
-                + entry
-                    .path()
-                    .strip_prefix(&img_source)?
-                    .to_str()
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Invalid UTF-8 in image path: {:?}", entry.path())
-                    })?;
-            if entry.file_type().is_file() {
-                fs::copy(entry.path(), dest_path)
-                    .context(format!("Failed to copy img file: {:?}", entry.path()))?;
+            LastUpdatedSource::Frontmatter => frontmatter
+                .get("updated")
+                .and_then(|value| value.as_str())
+                .and_then(parse_date),
+            LastUpdatedSource::Git => {
+                let path = path?;
+                self.git_dates
+                    .entry(path.to_path_buf())
+                    .or_insert_with(|| git_last_commit_date(path))
+                    .clone()
             }
         }
     }
+}
 
-    fs::write(
-        format!("{}/components/doc-toc.js", output_dir),
-        include_str!("templates/components/doc-toc.js"),
-    )
-    .context("Failed to write TOC component")?;
+/// The date a build is stamped with. `SOURCE_DATE_EPOCH` (seconds since the
+/// Unix epoch) wins when set; otherwise deterministic builds use the epoch
+/// itself and everything else the current time.
+fn build_date(source_date_epoch: Option<&str>, deterministic: bool) -> Result<Zoned> {
+    let seconds = match source_date_epoch {
+        Some(value) => value
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid SOURCE_DATE_EPOCH: {value}"))?,
+        None if deterministic => 0,
+        None => return Ok(Zoned::now()),
+    };
+    Ok(Timestamp::from_second(seconds)?.to_zoned(TimeZone::UTC))
+}
 
-    fs::write(
-        format!("{}/components/simple-block.js", output_dir),
-        include_str!("templates/components/simple-block.js"),
-    )
-    .context("Failed to write Simple Block component")?;
+/// The committer date of the last commit touching `path`, if it is tracked
+fn git_last_commit_date(path: &Path) -> Option<Zoned> {
+    let output = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%cI", "--"])
+        .arg(path.file_name()?)
+        .current_dir(path.parent()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_date(String::from_utf8_lossy(&output.stdout).trim())
+}
 
-    fs::write(
-        format!("{}/components/search-modal.js", output_dir),
-        include_str!("templates/components/search-modal.js"),
-    )
-    .context("Failed to write Search Modal component")?;
+/// The short SHA of the commit checked out in `dir`, if it is inside a git repository
+fn git_short_sha(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let sha = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!sha.is_empty()).then_some(sha)
+}
 
-    Ok(())
+/// Parses an RFC 3339 timestamp or a plain `YYYY-MM-DD` date
+fn parse_date(value: &str) -> Option<Zoned> {
+    if let Ok(timestamp) = value.parse::<Timestamp>() {
+        return Some(timestamp.to_zoned(TimeZone::system()));
+    }
+    if let Ok(datetime) = value.parse::<jiff::civil::DateTime>() {
+        return datetime.to_zoned(TimeZone::system()).ok();
+    }
+    value
+        .parse::<jiff::civil::Date>()
+        .ok()?
+        .to_zoned(TimeZone::system())
+        .ok()
 }
 
-#[cfg(feature = "syntax-highlighting")]
-fn process_code_block(code: &str, language: Option<&str>, ss: &SyntaxSet) -> Result<String> {
-    let syntax = match language {
-        Some("rust") => {
-            let syntax = ss
-                .find_syntax_by_extension("rs")
-                .ok_or_else(|| anyhow::anyhow!("Rust syntax not found"))?;
-            // Check if code block has editable tag
-            if code.contains("<--editable-->") {
-                let code_with_comment = format!("{}\n// <--editable-->", code);
-                process_rust_code(&code_with_comment, syntax, ss)?
-            } else {
-                process_rust_code(code, syntax, ss)?
+/// Whether `[blog]` lists the page as a post: it is under `blog.dir`, and
+/// isn't that directory's own index or README page
+fn is_blog_post(rel_path: &Path, config: &BookConfig) -> bool {
+    config.blog.dir.as_deref().is_some_and(|dir| {
+        rel_path.starts_with(dir)
+            && !rel_path
+                .file_stem()
+                .is_some_and(|stem| stem == "index" || stem.eq_ignore_ascii_case("readme"))
+    })
+}
+
+/// Where `[blog]` writes its listing page, if blog mode is on
+fn blog_listing_path(config: &BookConfig) -> Option<PathBuf> {
+    let dir = config.blog.dir.as_deref()?;
+    Some(match &config.blog.listing {
+        Some(listing) => PathBuf::from(listing.trim_start_matches('/')),
+        None => Path::new(dir).join("index.html"),
+    })
+}
+
+/// The names in a page's frontmatter `tags`, a list or a single string
+fn frontmatter_tags(frontmatter: &Frontmatter) -> impl Iterator<Item = &str> {
+    let tags = frontmatter.get("tags");
+    let list = tags
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten();
+    list.filter_map(|value| value.as_str())
+        .chain(tags.and_then(|value| value.as_str()))
+        .map(str::trim)
+        .filter(|name| !slugify(name).is_empty())
+}
+
+/// Links to the tag pages of the tags a page's frontmatter lists
+fn page_tags(frontmatter: &Frontmatter, tags: &BTreeMap<String, Tag>) -> Vec<PageInfo> {
+    frontmatter_tags(frontmatter)
+        .filter_map(|name| tags.get(&slugify(name)))
+        .map(|tag| PageInfo {
+            title: tag.name.clone(),
+            path: tag.path.clone(),
+        })
+        .collect()
+}
+
+/// Lowercases `name` and joins its runs of letters and digits with hyphens,
+/// so `Rust & WebAssembly` becomes `rust-webassembly`
+fn slugify(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Where the index page for the tag with `slug` is written
+fn tag_html_path(slug: &str, config: &BookConfig) -> PathBuf {
+    page_html_path(
+        &Path::new("tags").join(format!("{slug}.md")),
+        true,
+        config.output.html.pretty_urls,
+    )
+}
+
+/// The book's tags keyed by slug, each with the pages carrying it in book
+/// order; tags differing only in case or punctuation are one tag
+fn collect_tags(
+    all_pages: &[PageInfo],
+    sources: &[SourceFile],
+    config: &BookConfig,
+) -> BTreeMap<String, Tag> {
+    let mut tags: BTreeMap<String, Tag> = BTreeMap::new();
+    for (page, source) in all_pages.iter().zip(sources) {
+        for name in frontmatter_tags(&source.frontmatter) {
+            let slug = slugify(name);
+            let tag = tags.entry(slug).or_insert_with_key(|slug| {
+                let html_path = tag_html_path(slug, config);
+                Tag {
+                    name: name.to_string(),
+                    path: page_url(&html_path, trailing_slash(config)),
+                    html_path,
+                    pages: Vec::new(),
+                }
+            });
+            if !tag.pages.iter().any(|tagged| tagged.path == page.path) {
+                tag.pages.push(page.clone());
             }
         }
-        Some("mermaid") => {
-            // For markdown, preserve the content exactly as is
-            format!(
-                "<pre class=\"code\"><code class=\"language-mermaid\">{}</code></pre>",
-                html_escape::encode_text(code)
-            )
-        }
-        Some(lang) => {
-            let syntax = ss
-                .find_syntax_by_extension(lang)
-                .or_else(|| ss.find_syntax_by_name(lang))
-                .or_else(|| ss.find_syntax_by_token(lang))
-                .or_else(|| Some(ss.find_syntax_plain_text()))
-                .ok_or_else(|| anyhow::anyhow!("Syntax not found for language: {:?}", lang))?;
-            process_generic_code(code, syntax, ss)?
-        }
-        None => {
-            let syntax = ss.find_syntax_plain_text();
-            process_generic_code(code, syntax, ss)?
-        }
-    };
-    Ok(syntax)
+    }
+    tags
 }
 
-#[cfg(feature = "syntax-highlighting")]
-fn process_rust_code(
-    code: &str,
-    syntax: &syntect::parsing::SyntaxReference,
-    ss: &SyntaxSet,
-) -> Result<String> {
-    let mut html_generator =
-        ClassedHTMLGenerator::new_with_class_style(syntax, ss, ClassStyle::Spaced);
+/// A post's frontmatter `date`
+fn post_date(frontmatter: &Frontmatter) -> Option<Timestamp> {
+    frontmatter
+        .get("date")
+        .and_then(|value| value.as_str())
+        .and_then(parse_date)
+        .map(|date| date.timestamp())
+}
+
+/// Why a build failed, as returned by [`try_build`]
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("Failed to render template `{name}`")]
+    Template {
+        name: String,
+        #[source]
+        source: tera::Error,
+    },
+
+    #[error(
+        "Syntax error in template {} at line {line}, column {column}:\n{snippet}",
+        path.display()
+    )]
+    TemplateSyntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        /// Tera's excerpt of the offending source and what it expected there
+        snippet: String,
+    },
+
+    #[error("Failed to read {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse {}: {message}", path.display())]
+    Markdown { path: PathBuf, message: String },
+
+    #[error("Build reported {warnings} warning(s) and --strict is set")]
+    Strict { warnings: usize },
+
+    #[error(
+        "{} page(s) failed to render:{}",
+        failures.len(),
+        failures.iter().map(|failure| format!("\n  {failure}")).collect::<String>()
+    )]
+    Pages { failures: Vec<PageFailure> },
+
+    #[error("Search indexing failed: {0}")]
+    Search(#[from] PagefindError),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
 
-    for line in LinesWithEndings::from(code) {
-        html_generator
-            .parse_html_for_line_which_includes_newline(line)
-            .map_err(|e| anyhow::anyhow!("HTML generation error: {:?}", e))?;
+impl From<anyhow::Error> for BuildError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast().unwrap_or_else(Self::Other)
     }
-    let html = html_generator.finalize();
-    Ok(format!(
-        "<pre class=\"code rust\"><code>{}</code></pre>",
-        html
-    ))
 }
 
-#[cfg(feature = "syntax-highlighting")]
-fn process_generic_code(
-    code: &str,
-    syntax: &syntect::parsing::SyntaxReference,
-    ss: &SyntaxSet,
-) -> Result<String> {
-    let mut html_generator =
-        ClassedHTMLGenerator::new_with_class_style(syntax, ss, ClassStyle::Spaced);
+/// A page that failed to render in a `--keep-going` build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageFailure {
+    /// The source file, relative to the input directory
+    pub path: PathBuf,
+    pub message: String,
+}
 
-    for line in LinesWithEndings::from(code) {
-        html_generator
-            .parse_html_for_line_which_includes_newline(line)
-            .map_err(|e| anyhow::anyhow!("HTML generation error: {:?}", e))?;
+impl PageFailure {
+    fn new(path: &Path, error: &anyhow::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            message: format!("{error:#}"),
+        }
+    }
+
+    /// A bare page standing in for the one that failed; it uses no templates,
+    /// which may be what broke
+    fn placeholder(&self, title: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Build error: {title}</title></head>\n\
+             <body>\n<h1>{title} failed to render</h1>\n<pre>{message}</pre>\n</body>\n</html>\n",
+            title = html_escape::encode_text(title),
+            message = html_escape::encode_text(&self.message),
+        )
     }
-    let html = html_generator.finalize();
-    Ok(format!("<pre class=\"code\"><code>{}</code></pre>", html))
 }
 
-/// Converts internal .md links to .html links in HTML content.
-/// This ensures that links like `href="page.md"` become `href="page.html"`.
-/// External links (http://, https://, mailto:, etc.) are not modified.
-fn convert_md_links_to_html(html: &str) -> String {
-    let mut result = html.to_string();
+impl std::fmt::Display for PageFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
 
-    // Convert href="...md" to href="...html" (double quotes)
-    // We need to be careful to only convert internal links, not external ones
-    let mut start = 0;
-    while let Some(href_pos) = result[start..].find("href=\"") {
-        let abs_pos = start + href_pos;
-        let url_start = abs_pos + 6; // Position after 'href="'
-
-        if let Some(quote_end) = result[url_start..].find('"') {
-            let url_end = url_start + quote_end;
-            let url = &result[url_start..url_end];
-
-            // Only convert if it's an internal link ending with .md
-            // Skip external links (http://, https://, mailto:, //, etc.)
-            if url.ends_with(".md")
-                && !url.starts_with("http://")
-                && !url.starts_with("https://")
-                && !url.starts_with("mailto:")
-                && !url.starts_with("//")
-            {
-                // Replace .md with .html
-                let new_url = format!("{}.html", &url[..url.len() - 3]);
-                result = format!(
-                    "{}href=\"{}\"{}",
-                    &result[..abs_pos],
-                    new_url,
-                    &result[url_end + 1..]
-                );
-                start = abs_pos + 6 + new_url.len() + 1;
-            } else {
-                start = url_end + 1;
+/// A non-fatal problem found during a build, reported once the build finishes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildWarning {
+    /// A source file was skipped because it is not valid UTF-8
+    InvalidUtf8 { file: PathBuf, message: String },
+    /// A code block names a language with no known syntax and was rendered as plain text
+    UnknownLanguage { file: PathBuf, language: String },
+    /// A page references a local image that doesn't exist in the source tree
+    MissingImage { file: PathBuf, image: String },
+    /// A page has an image without alt text
+    MissingAltText { file: PathBuf, image: String },
+    /// A page links to a directory with no index page to point the link at
+    MissingDirectoryIndex { file: PathBuf, link: String },
+    /// A code block's `{lines}` spec highlights a line past the end of the block
+    HighlightLineOutOfRange {
+        file: PathBuf,
+        line: usize,
+        lines: usize,
+    },
+}
+
+impl std::fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUtf8 { file, message } => {
+                write!(f, "skipped {} (invalid UTF-8: {message})", file.display())
             }
-        } else {
-            break;
+            Self::UnknownLanguage { file, language } => write!(
+                f,
+                "{}: unknown code block language `{language}`, rendered as plain text",
+                file.display()
+            ),
+            Self::MissingImage { file, image } => {
+                write!(f, "{}: image `{image}` not found", file.display())
+            }
+            Self::MissingAltText { file, image } => {
+                write!(f, "{}: image `{image}` has no alt text", file.display())
+            }
+            Self::MissingDirectoryIndex { file, link } => write!(
+                f,
+                "{}: link `{link}` points to a directory without an index page",
+                file.display()
+            ),
+            Self::HighlightLineOutOfRange { file, line, lines } => write!(
+                f,
+                "{}: cannot highlight line {line} of a {lines}-line code block",
+                file.display()
+            ),
         }
     }
+}
+
+#[cfg(feature = "tokio")]
+/// Build the book from markdown files to HTML
+///
+/// # Errors
+///
+/// Returns an error if the build process fails, including template rendering
+/// and file I/O errors. Search indexing failures are only reported.
+pub async fn build(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
+    warn_on_search_failure(build_impl(args, config, watch_enabled).await)
+}
 
-    // Also handle single quotes (less common but possible)
-    start = 0;
-    while let Some(href_pos) = result[start..].find("href='") {
-        let abs_pos = start + href_pos;
-        let url_start = abs_pos + 6; // Position after "href='"
+#[cfg(not(feature = "tokio"))]
+pub fn build(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
+    warn_on_search_failure(build_impl(args, config, watch_enabled))
+}
 
-        if let Some(quote_end) = result[url_start..].find('\'') {
-            let url_end = url_start + quote_end;
-            let url = &result[url_start..url_end];
+#[cfg(feature = "tokio")]
+/// Build the book like [`build`], returning a [`BuildError`] callers can match on
+///
+/// # Errors
+///
+/// Returns an error if the build fails. Unlike [`build`], which only warns
+/// once the pages are written, a search indexing failure is an error too.
+pub async fn try_build(
+    args: &Args,
+    config: &BookConfig,
+    watch_enabled: bool,
+) -> Result<(), BuildError> {
+    Ok(build_impl(args, config, watch_enabled).await?)
+}
 
-            if url.ends_with(".md")
-                && !url.starts_with("http://")
-                && !url.starts_with("https://")
-                && !url.starts_with("mailto:")
-                && !url.starts_with("//")
-            {
-                let new_url = format!("{}.html", &url[..url.len() - 3]);
-                result = format!(
-                    "{}href='{}'{}",
-                    &result[..abs_pos],
-                    new_url,
-                    &result[url_end + 1..]
-                );
-                start = abs_pos + 6 + new_url.len() + 1;
-            } else {
-                start = url_end + 1;
-            }
-        } else {
-            break;
+#[cfg(not(feature = "tokio"))]
+pub fn try_build(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<(), BuildError> {
+    Ok(build_impl(args, config, watch_enabled)?)
+}
+
+/// The site is already written when indexing fails, so the CLI only warns
+fn warn_on_search_failure(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e) if matches!(e.downcast_ref::<BuildError>(), Some(BuildError::Search(_))) => {
+            eprintln!("{e}");
+            Ok(())
         }
+        result => result,
     }
+}
 
-    result
+#[cfg(feature = "tokio")]
+async fn build_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
+    build_sync_impl(args, config, watch_enabled).await
 }
 
-#[cfg(feature = "syntax-highlighting")]
-fn process_markdown_with_highlighting(
-    content: &str,
-    ss: &SyntaxSet,
-    config: &BookConfig,
-) -> Result<String> {
-    let parse_options = match config.markdown.format {
-        MarkdownFormat::Mdx => markdown::ParseOptions::mdx(),
-        MarkdownFormat::Gfm => markdown::ParseOptions::gfm(),
-        MarkdownFormat::Markdown => markdown::ParseOptions::default(),
+#[cfg(not(feature = "tokio"))]
+fn build_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
+    build_sync_impl_sync(args, config, watch_enabled).map(|_| ())
+}
+
+#[cfg(feature = "tokio")]
+async fn build_sync_impl(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<()> {
+    build_sync_impl_sync(args, config, watch_enabled)?;
+
+    // After generating HTML files, run Pagefind indexing if search feature is enabled.
+    // Single-file and JSON builds have no search UI to serve the index from.
+    #[cfg(all(feature = "search", feature = "tokio"))]
+    if args.builds_site() && !args.dry_run {
+        let pagefind = PagefindBuilder::new(
+            PathBuf::from(args.output_dir(config)),
+            &config.output.html.search,
+        )
+        .await
+        .map_err(BuildError::Search)?;
+        pagefind.build().await.map_err(BuildError::Search)?;
+    }
+
+    Ok(())
+}
+
+/// Empties the output directory, refusing when it is the filesystem root or
+/// the home directory, when any of the `protected` paths (the sources,
+/// templates and config file) live inside it, or when it holds files but
+/// md-book did not create it
+fn clean_output(output: &Path, protected: &[&Path], out: OutputFs) -> Result<()> {
+    let Ok(output) = fs::canonicalize(output) else {
+        // Nothing has been built there yet
+        return Ok(());
     };
+    let refuse =
+        |reason: String| anyhow::anyhow!("Refusing to clean {}: {reason}", output.display());
+    if output.parent().is_none() {
+        return Err(refuse("it is the filesystem root".to_string()));
+    }
+    let home = std::env::var_os("HOME").and_then(|home| fs::canonicalize(home).ok());
+    if home.as_deref() == Some(output.as_path()) {
+        return Err(refuse("it is the home directory".to_string()));
+    }
+    for dir in protected {
+        if let Ok(dir) = fs::canonicalize(dir) {
+            if dir.starts_with(&output) {
+                return Err(refuse(format!("it contains {}", dir.display())));
+            }
+        }
+    }
+    let entries = fs::read_dir(&output)
+        .with_context(|| format!("Failed to read output directory: {}", output.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if !entries.is_empty() && !output.join(OUTPUT_MARKER).exists() {
+        return Err(refuse(format!(
+            "md-book did not create it (there is no {OUTPUT_MARKER} file)"
+        )));
+    }
+    for entry in entries {
+        let path = entry.path();
+        out.remove(&path)
+            .with_context(|| format!("Failed to remove: {}", path.display()))?;
+    }
+    Ok(())
+}
 
-    let compile_options = if matches!(config.markdown.format, MarkdownFormat::Gfm) {
-        markdown::CompileOptions::gfm()
-    } else {
-        markdown::CompileOptions::default()
+/// Builds the book and returns the number of pages rendered
+fn build_sync_impl_sync(args: &Args, config: &BookConfig, watch_enabled: bool) -> Result<usize> {
+    let out = OutputFs {
+        dry_run: args.dry_run,
+        file_mode: parse_mode("build.file_mode", config.build.file_mode.as_deref())?,
+        dir_mode: parse_mode("build.dir_mode", config.build.dir_mode.as_deref())?,
     };
+    let tera = load_templates(config)?;
+    let input = Path::new(args.input_dir(config)?);
+    let output_dir = args.output_dir(config);
+    let output = Path::new(output_dir);
+
+    if args.clean || config.build.clean_before {
+        let config_file = Path::new(args.config.as_deref().unwrap_or("book.toml"));
+        let templates = Path::new(&config.paths.templates);
+        clean_output(output, &[input, templates, config_file], out)?;
+    }
 
-    let mut options = markdown::Options {
-        parse: parse_options,
-        compile: compile_options,
+    // Create output directory if it doesn't exist, marking it as md-book's
+    // when it starts out empty
+    let unclaimed = fs::read_dir(output).map_or(true, |mut entries| entries.next().is_none());
+    out.create_dir_all(output)?;
+    if unclaimed && !args.dry_run {
+        out.write(output.join(OUTPUT_MARKER), "").with_context(|| {
+            format!(
+                "Failed to write file: {}",
+                output.join(OUTPUT_MARKER).display()
+            )
+        })?;
+    }
+
+    // Copy static assets; single-file and JSON builds don't reference them
+    if args.builds_site() {
+        let site_root = args.site_root(config);
+        copy_static_assets(output_dir, &config.paths.templates, config, &site_root, out)?;
+    }
+
+    let collection = collect_sources(input, config, args.drafts)?;
+
+    let total_pages = collection.sources.len();
+    println!("Total pages: {total_pages}");
+
+    let options = RenderOptions {
+        input: Some(input),
+        format: args.format,
+        single_file: args.single_file,
+        watch_enabled,
+        deterministic: args.deterministic,
+        keep_going: args.keep_going,
+        #[cfg(feature = "parallel")]
+        jobs: args.jobs.or(config.build.jobs),
+    };
+    let RenderedBook {
+        files,
+        images,
+        mut warnings,
+        failures,
+    } = render_book(&tera, config, collection, &options)?;
+    let files = match config.output.html.inline_assets_under {
+        // Page images are only copied over later, so they are read from the sources
+        Some(limit) if args.builds_site() => {
+            let read = |asset: &Path| {
+                fs::read(output.join(asset))
+                    .or_else(|_| fs::read(input.join(asset)))
+                    .ok()
+            };
+            files
+                .into_iter()
+                .map(|(path, contents)| {
+                    let contents = if path.extension().is_some_and(|ext| ext == "html") {
+                        inline_small_assets(&contents, &path, limit, read)
+                    } else {
+                        contents
+                    };
+                    (path, contents)
+                })
+                .collect()
+        }
+        _ => files,
+    };
+    let site_root = args.site_root(config);
+    let files = match site_root.as_str() {
+        root if root != "/" && args.builds_site() => files
+            .into_iter()
+            .map(|(path, contents)| {
+                let contents = if path.extension().is_some_and(|ext| ext == "html") {
+                    prefix_root_urls(&contents, root)
+                } else {
+                    contents
+                };
+                (path, contents)
+            })
+            .collect(),
+        _ => files,
     };
 
-    // Modify constructs for HTML and frontmatter
-    options.parse.constructs.frontmatter = config.markdown.frontmatter;
-    options.parse.constructs.html_flow = config.output.html.allow_html;
-    options.parse.constructs.html_text = config.output.html.allow_html;
-    options.compile.allow_dangerous_html = config.output.html.allow_html;
-    options.compile.allow_dangerous_protocol = config.output.html.allow_html;
+    // Outputs identical to what the previous build left on disk are not
+    // rewritten, so their modification times (and the server's ETags) hold
+    let cache_dir = args
+        .cache_dir
+        .as_ref()
+        .or(config.build.cache_dir.as_ref())
+        .map(PathBuf::from)
+        .or_else(|| args.default_cache_dir.then(|| default_cache_dir(output)))
+        .filter(|_| !args.dry_run);
+    let mut manifest = cache_dir
+        .as_deref()
+        .map(BuildManifest::load)
+        .unwrap_or_default();
+    let mut written = BTreeSet::new();
+    let mut unchanged = 0;
+    for (path, contents) in &files {
+        let dest = output.join(path);
+        if manifest.is_fresh(&dest, contents.as_bytes()) {
+            written.insert(manifest_key(&dest));
+            unchanged += 1;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            out.create_dir_all(parent)?;
+        }
+        out.write(&dest, contents)
+            .with_context(|| format!("Failed to write file: {}", dest.display()))?;
+        if cache_dir.is_some() {
+            manifest.record(&dest, contents.as_bytes());
+            written.insert(manifest_key(&dest));
+        }
+    }
+    if let Some(cache_dir) = &cache_dir {
+        manifest.prune(output, &written);
+        manifest.save(cache_dir)?;
+    }
+    if unchanged > 0 {
+        println!("Skipped {unchanged} unchanged files");
+    }
+    copy_page_images(input, output, &images, &mut warnings, out)?;
 
-    let ast = to_mdast(content, &options.parse)
+    if args.format == OutputFormat::Json {
+        println!("Wrote JSON export to {output_dir}/book.json");
+    } else if args.single_file {
+        println!("Wrote single-file book to {output_dir}/index.html");
+    }
+    report_warnings(&warnings);
+    if !failures.is_empty() {
+        return Err(BuildError::Pages { failures }.into());
+    }
+    if args.strict && !warnings.is_empty() {
+        return Err(BuildError::Strict {
+            warnings: warnings.len(),
+        }
+        .into());
+    }
+
+    // Search indexing handled in async wrapper or skipped
+    #[cfg(not(all(feature = "search", feature = "tokio")))]
+    if args.builds_site() {
+        println!("Skipping search indexing (search or tokio feature not enabled)");
+    }
+
+    Ok(total_pages)
+}
+
+/// Builds an HTML site from in-memory documents, without reading the input
+/// directory or writing any output.
+///
+/// Each source pairs a path relative to the book root, such as
+/// `guide/setup.md`, with its markdown. Returns every generated page with its
+/// path relative to the output directory. Static assets like stylesheets are
+/// not included, and features that need the source tree (image dimensions,
+/// `git`/`mtime` dates) are skipped. Custom templates are still read from
+/// `paths.templates` when that directory exists.
+///
+/// # Errors
+///
+/// Returns an error if a document has invalid frontmatter or a page fails to render
+pub fn build_from_sources(
+    sources: Vec<(PathBuf, String)>,
+    config: &BookConfig,
+) -> Result<Vec<(PathBuf, String)>> {
+    let tera = load_templates(config)?;
+    let collection = collect_documents(sources, config, false, BTreeSet::new())?;
+    let options = RenderOptions {
+        input: None,
+        format: OutputFormat::Html,
+        single_file: false,
+        watch_enabled: false,
+        deterministic: false,
+        keep_going: false,
+        #[cfg(feature = "parallel")]
+        jobs: config.build.jobs,
+    };
+    let rendered = render_book(&tera, config, collection, &options)?;
+
+    report_warnings(&rendered.warnings);
+    Ok(rendered.files)
+}
+
+/// Loads the page templates from `paths.templates`, falling back to the
+/// built-in template for any file that isn't there
+fn load_templates(config: &BookConfig) -> Result<Tera> {
+    let mut tera = Tera::default();
+
+    // Add template files from the configured directory
+    let template_files = [
+        ("page", "page.html.tera"),
+        ("index", "index.html.tera"),
+        ("sidebar", "sidebar.html.tera"),
+        ("footer", "footer.html.tera"),
+        ("header", "header.html.tera"),
+        ("meta", "meta.html.tera"),
+        ("custom-properties", "custom-properties.html.tera"),
+        ("redirect", "redirect.html.tera"),
+        ("print", "print.html.tera"),
+        ("single", "single.html.tera"),
+        ("service-worker", "service-worker.js.tera"),
+        ("blog", "blog.html.tera"),
+        ("tags", "tags.html.tera"),
+    ];
+
+    for (name, file) in template_files {
+        let template_path = format!("{}/{}", config.paths.templates, file);
+        let template_content = if Path::new(&template_path).exists() {
+            fs::read_to_string(&template_path).map_err(|source| BuildError::Read {
+                path: PathBuf::from(&template_path),
+                source,
+            })?
+        } else {
+            // Load default template content directly
+            match file {
+                "page.html.tera" => include_str!("templates/page.html.tera").to_string(),
+                "index.html.tera" => include_str!("templates/index.html.tera").to_string(),
+                "sidebar.html.tera" => include_str!("templates/sidebar.html.tera").to_string(),
+                "footer.html.tera" => include_str!("templates/footer.html.tera").to_string(),
+                "header.html.tera" => include_str!("templates/header.html.tera").to_string(),
+                "meta.html.tera" => include_str!("templates/meta.html.tera").to_string(),
+                "custom-properties.html.tera" => {
+                    include_str!("templates/custom-properties.html.tera").to_string()
+                }
+                "redirect.html.tera" => include_str!("templates/redirect.html.tera").to_string(),
+                "print.html.tera" => include_str!("templates/print.html.tera").to_string(),
+                "single.html.tera" => include_str!("templates/single.html.tera").to_string(),
+                "service-worker.js.tera" => {
+                    include_str!("templates/service-worker.js.tera").to_string()
+                }
+                "blog.html.tera" => include_str!("templates/blog.html.tera").to_string(),
+                "tags.html.tera" => include_str!("templates/tags.html.tera").to_string(),
+                _ => return Err(anyhow::anyhow!("Unknown template file: {}", file)),
+            }
+        };
+
+        add_template(
+            &mut tera,
+            name,
+            Path::new(&template_path),
+            &template_content,
+        )?;
+    }
+
+    // Any other `*.html.tera` there is a partial, included by its name without the extension
+    let mut partials = Vec::new();
+    if let Ok(entries) = fs::read_dir(&config.paths.templates) {
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            let Some(name) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".html.tera"))
+            else {
+                continue;
+            };
+            let is_builtin = template_files.iter().any(|(builtin, _)| *builtin == name);
+            if !name.is_empty() && !is_builtin && path.is_file() {
+                partials.push((name.to_string(), path));
+            }
+        }
+    }
+    partials.sort();
+
+    for (name, path) in partials {
+        let template_content = fs::read_to_string(&path).map_err(|source| BuildError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        add_template(&mut tera, &name, &path, &template_content)?;
+    }
+
+    Ok(tera)
+}
+
+/// Adds a template read from `path`, pointing parse errors at their location
+fn add_template(tera: &mut Tera, name: &str, path: &Path, content: &str) -> Result<(), BuildError> {
+    tera.add_raw_template(name, content).map_err(|source| {
+        template_syntax_error(path, &source).unwrap_or_else(|| BuildError::Template {
+            name: name.to_string(),
+            source,
+        })
+    })
+}
+
+/// Tera reports parse errors as a `Failed to parse` message whose source
+/// starts with ` --> line:column`, followed by the excerpt
+fn template_syntax_error(path: &Path, error: &tera::Error) -> Option<BuildError> {
+    let detail = std::error::Error::source(error)?.to_string();
+    let (location, snippet) = detail.trim_start().strip_prefix("--> ")?.split_once('\n')?;
+    let (line, column) = location.trim().split_once(':')?;
+    Some(BuildError::TemplateSyntax {
+        path: path.to_path_buf(),
+        line: line.parse().ok()?,
+        column: column.parse().ok()?,
+        snippet: snippet.to_string(),
+    })
+}
+
+/// Renders one of the book's templates, keeping its name in the error
+fn render_template(tera: &Tera, name: &str, context: &TeraContext) -> Result<String, BuildError> {
+    tera.render(name, context)
+        .map_err(|source| BuildError::Template {
+            name: name.to_string(),
+            source,
+        })
+}
+
+/// What to render from a collection of pages
+struct RenderOptions<'a> {
+    /// The source directory, for images and file dates; `None` for in-memory builds
+    input: Option<&'a Path>,
+    format: OutputFormat,
+    single_file: bool,
+    watch_enabled: bool,
+    deterministic: bool,
+    /// Render a placeholder for failed pages instead of stopping at the first
+    keep_going: bool,
+    /// Caps the threads rendering pages; `None` uses one per CPU
+    #[cfg(feature = "parallel")]
+    jobs: Option<NonZeroUsize>,
+}
+
+/// The output of [`render_book`], ready to be written out
+struct RenderedBook {
+    /// Generated files with paths relative to the output directory
+    files: Vec<(PathBuf, String)>,
+    /// Local images each page references, as (page, `src`) pairs
+    images: Vec<(PathBuf, String)>,
+    warnings: BTreeSet<BuildWarning>,
+    /// Pages replaced by a placeholder; only a `keep_going` render has any
+    failures: Vec<PageFailure>,
+}
+
+/// Renders every page of a collection, plus the index, print, redirect and
+/// export files the options call for
+fn render_book(
+    tera: &Tera,
+    config: &BookConfig,
+    collection: Collection,
+    options: &RenderOptions,
+) -> Result<RenderedBook> {
+    let Collection {
+        all_pages,
+        sections,
+        sources,
+        mut warnings,
+    } = collection;
+    let total_pages = all_pages.len();
+    let mut files = Vec::new();
+    let mut images = Vec::new();
+    let mut failures = Vec::new();
+
+    // Get current year using Jiff
+    let source_date_epoch = std::env::var("SOURCE_DATE_EPOCH").ok();
+    let build_date = build_date(source_date_epoch.as_deref(), options.deterministic)?;
+    let current_year = build_date.year().to_string();
+
+    // Looked up once for all pages; in-memory builds have no source checkout
+    let git_commit = options
+        .input
+        .filter(|_| config.output.html.git_commit)
+        .and_then(git_short_sha);
+    // A worker caching pages would hide live-reload rebuilds
+    let offline = config.output.html.offline && !options.watch_enabled;
+
+    // Initialize syntax highlighting if feature is enabled
+    #[cfg(feature = "syntax-highlighting")]
+    let ss = SyntaxSet::load_defaults_newlines();
+
+    // Inline-styled code needs no stylesheet
+    #[cfg(feature = "syntax-highlighting")]
+    let syntax_css = if config.syntax.style == HighlightStyle::Classed {
+        let syntax_css =
+            syntect::html::css_for_theme_with_class_style(highlight_theme(), ClassStyle::Spaced)
+                .map_err(|e| anyhow::anyhow!("CSS generation error: {:?}", e))?;
+
+        if options.format == OutputFormat::Html && !options.single_file {
+            files.push((PathBuf::from("css/syntax.css"), syntax_css.clone()));
+        }
+        syntax_css
+    } else {
+        String::new()
+    };
+    #[cfg(not(feature = "syntax-highlighting"))]
+    let syntax_css = String::new();
+
+    let mut print_chapters = Vec::new();
+    let mut exported_pages = Vec::new();
+    let mut page_texts = Vec::new();
+    let mut summaries = BTreeMap::new();
+    // The book's own `index.html` page, rendered by the page loop, for the
+    // generated index to show
+    let mut index_content = None;
+    let reproducible = options.deterministic || source_date_epoch.is_some();
+    let mut last_updated = LastUpdated::new(
+        config.output.html.last_updated,
+        reproducible.then_some(build_date),
+    );
+
+    let mut html_paths: BTreeSet<PathBuf> = sources.iter().map(|s| s.html_path.clone()).collect();
+    if config.output.html.generate_index {
+        html_paths.insert(PathBuf::from("index.html"));
+    }
+    let blog_listing = blog_listing_path(config);
+    if let Some(listing) = &blog_listing {
+        if let Some(source) = sources.iter().find(|source| &source.html_path == listing) {
+            anyhow::bail!(
+                "The blog listing and {} would both be written to {}",
+                source.rel_path.display(),
+                listing.display()
+            );
+        }
+        html_paths.insert(listing.clone());
+    }
+    let mut blog_posts = Vec::new();
+
+    let tags = collect_tags(&all_pages, &sources, config);
+    if tags.contains_key("index") {
+        anyhow::bail!("A tag cannot be named `index`: tags/index.html lists all the tags");
+    }
+    if !tags.is_empty() {
+        let tag_paths = tags
+            .values()
+            .map(|tag| tag.html_path.clone())
+            .chain([tag_html_path("index", config)]);
+        for path in tag_paths {
+            if let Some(source) = sources.iter().find(|source| source.html_path == path) {
+                anyhow::bail!(
+                    "The tag index and {} would both be written to {}",
+                    source.rel_path.display(),
+                    path.display()
+                );
+            }
+            html_paths.insert(path);
+        }
+    }
+
+    // Convert the pages' markdown in parallel; templating them stays in order
+    let render_content = |(source, page): (_, &PageInfo)| {
+        render_page_content(
+            source,
+            &page.title,
+            &sections,
+            &html_paths,
+            config,
+            #[cfg(feature = "syntax-highlighting")]
+            &ss,
+            options.input,
+        )
+    };
+    #[cfg(feature = "parallel")]
+    let contents = {
+        use rayon::prelude::*;
+        let render = || {
+            sources
+                .par_iter()
+                .zip(&all_pages)
+                .map(render_content)
+                .collect::<Vec<_>>()
+        };
+        match options.jobs {
+            Some(jobs) => rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs.get())
+                .build()
+                .context("Failed to start the render threads")?
+                .install(render),
+            None => render(),
+        }
+    };
+    #[cfg(not(feature = "parallel"))]
+    let contents = sources
+        .iter()
+        .zip(&all_pages)
+        .map(render_content)
+        .collect::<Vec<_>>();
+
+    // Process each markdown file
+    for (current_page, (source, content)) in sources.iter().zip(contents).enumerate() {
+        let rel_path = &source.rel_path;
+        let PageContent {
+            markdown: markdown_content,
+            html: html_content,
+            summary,
+            warnings: page_warnings,
+        } = match content {
+            Ok(content) => content,
+            Err(e) if options.keep_going => {
+                let failure = PageFailure::new(rel_path, &e);
+                if options.format == OutputFormat::Html && !options.single_file {
+                    let placeholder = failure.placeholder(&all_pages[current_page].title);
+                    files.push((source.html_path.clone(), placeholder));
+                }
+                failures.push(failure);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        warnings.extend(page_warnings);
+        summaries.insert(all_pages[current_page].path.clone(), summary.clone());
+
+        let previous = if current_page > 0 {
+            Some(all_pages[current_page - 1].clone())
+        } else {
+            None
+        };
+
+        let next = if current_page + 1 < total_pages {
+            Some(all_pages[current_page + 1].clone())
+        } else {
+            None
+        };
+
+        if options.format == OutputFormat::Json {
+            exported_pages.push(ExportedPage {
+                title: all_pages[current_page].title.clone(),
+                path: all_pages[current_page].path.clone(),
+                frontmatter: source.frontmatter.clone(),
+                html: html_content,
+                text: markdown_to_plaintext(
+                    &markdown_content,
+                    &source_config(config, rel_path),
+                    true,
+                )?,
+            });
+            continue;
+        }
+
+        if config.output.html.print_page || options.single_file {
+            print_chapters.push(PrintChapter {
+                id: chapter_id(&source.html_path.to_string_lossy()),
+                title: all_pages[current_page].title.clone(),
+                path: all_pages[current_page].path.clone(),
+                content: html_content.clone(),
+            });
+        }
+
+        if options.single_file {
+            continue;
+        }
+
+        if config.output.html.llms_full_txt {
+            page_texts.push((
+                all_pages[current_page].clone(),
+                markdown_to_plaintext(&markdown_content, &source_config(config, rel_path), true)?,
+            ));
+        }
+
+        images.extend(
+            image_sources(&html_content)
+                .filter(|src| !is_external_url(src))
+                .map(|src| (rel_path.clone(), src.to_string())),
+        );
+
+        // Image and print links stay relative to the source; only the page
+        // itself may have moved
+        let html_content = pretty_url_links(&html_content, source, &html_paths, config);
+        if source.html_path == Path::new("index.html") {
+            index_content = Some(html_content.clone());
+        }
+
+        let current_path = source.html_path.display().to_string();
+        let current_url = &all_pages[current_page].path;
+        let page_data = PageData {
+            title: all_pages[current_page].title.clone(),
+            content: html_content,
+            summary,
+            sections: if config.output.html.sidebar.enabled {
+                mark_active_section(&sections, current_url)
+            } else {
+                Vec::new()
+            },
+            previous,
+            next,
+        };
+
+        let mut context = TeraContext::new();
+        context.insert("year", &current_year);
+        context.insert("page", &page_data);
+        context.insert("git_commit", &git_commit);
+        context.insert("config", &config);
+        context.insert("canonical_url", &canonical_url(config, current_url));
+        context.insert("current_path", &current_path);
+        context.insert("current_url", current_url);
+        context.insert("watch_enabled", &options.watch_enabled);
+        context.insert("offline", &offline);
+        context.insert(
+            "description",
+            &page_description(&source.frontmatter, config),
+        );
+        context.insert(
+            "search_excluded",
+            &excluded_from_search(&source.frontmatter),
+        );
+        context.insert(
+            "open_graph",
+            &OpenGraph::new(config, &all_pages[current_page].title, current_url),
+        );
+        context.insert(
+            "last_updated",
+            &last_updated
+                .for_page(
+                    options.input.map(|input| input.join(rel_path)).as_deref(),
+                    &source.frontmatter,
+                )
+                .map(|date| DisplayDate::new(&date, config))
+                .transpose()?,
+        );
+        context.insert(
+            "page_footer",
+            &page_footer(
+                config,
+                &html_paths,
+                &current_year,
+                &all_pages[current_page].title,
+            )?,
+        );
+        context.insert("page_tags", &page_tags(&source.frontmatter, &tags));
+        if is_blog_post(rel_path, config) {
+            let date = post_date(&source.frontmatter);
+            let post = BlogPost {
+                title: all_pages[current_page].title.clone(),
+                path: current_url.clone(),
+                date: date
+                    .map(|date| DisplayDate::new(&date.to_zoned(TimeZone::system()), config))
+                    .transpose()?,
+                author: source
+                    .frontmatter
+                    .get("author")
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string),
+                summary: page_data.summary.clone(),
+            };
+            context.insert("post", &post);
+            blog_posts.push((date, post));
+        }
+
+        let rendered = match page_template(tera, &source.frontmatter, rel_path)
+            .and_then(|template| Ok(render_template(tera, template, &context)?))
+            .with_context(|| format!("Failed to render page: {current_path}"))
+        {
+            Ok(rendered) => rendered,
+            Err(e) if options.keep_going => {
+                let failure = PageFailure::new(rel_path, &e);
+                let placeholder = failure.placeholder(&all_pages[current_page].title);
+                failures.push(failure);
+                placeholder
+            }
+            Err(e) => return Err(e),
+        };
+        files.push((source.html_path.clone(), rendered));
+    }
+
+    if options.format == OutputFormat::Json {
+        let export = BookExport {
+            book: config.book.clone(),
+            sections,
+            pages: exported_pages,
+        };
+        files.push((
+            PathBuf::from("book.json"),
+            serde_json::to_string_pretty(&export)?,
+        ));
+        return Ok(RenderedBook {
+            files,
+            images,
+            warnings,
+            failures,
+        });
+    }
+
+    if options.single_file {
+        let rendered = render_single_file(
+            tera,
+            config,
+            options.input,
+            &sources,
+            print_chapters,
+            &syntax_css,
+        )?;
+        files.push((PathBuf::from("index.html"), rendered));
+        return Ok(RenderedBook {
+            files,
+            images,
+            warnings,
+            failures,
+        });
+    }
+
+    // Listing pages the build generates use the page template with a heading
+    // for content; the caller adds what they list
+    let generated_page = |title: &str, path: &Path| {
+        let current_url = page_url(path, trailing_slash(config));
+        let page_data = PageData {
+            title: title.to_string(),
+            content: format!("<h1>{}</h1>", html_escape::encode_text(title)),
+            summary: String::new(),
+            sections: if config.output.html.sidebar.enabled {
+                mark_active_section(&sections, &current_url)
+            } else {
+                Vec::new()
+            },
+            previous: None,
+            next: None,
+        };
+
+        let mut context = TeraContext::new();
+        context.insert("year", &current_year);
+        context.insert("page", &page_data);
+        context.insert("git_commit", &git_commit);
+        context.insert("config", &config);
+        context.insert("canonical_url", &canonical_url(config, &current_url));
+        context.insert("current_path", &path.display().to_string());
+        context.insert("current_url", &current_url);
+        context.insert("watch_enabled", &options.watch_enabled);
+        context.insert("offline", &offline);
+        context
+    };
+
+    if let Some(listing) = blog_listing {
+        // Newest first; undated posts keep their path order at the end
+        blog_posts.sort_by(|(a, _), (b, _)| b.cmp(a));
+        let posts: Vec<_> = blog_posts.into_iter().map(|(_, post)| post).collect();
+        let mut context = generated_page(&config.blog.title, &listing);
+        context.insert("posts", &posts);
+        let rendered =
+            render_template(tera, "page", &context).context("Failed to render the blog listing")?;
+        files.push((listing, rendered));
+    }
+
+    if !tags.is_empty() {
+        for tag in tags.values() {
+            let mut context = generated_page(&tag.name, &tag.html_path);
+            context.insert("tag", tag);
+            let rendered = render_template(tera, "page", &context)
+                .with_context(|| format!("Failed to render the page for tag `{}`", tag.name))?;
+            files.push((tag.html_path.clone(), rendered));
+        }
+
+        let cloud_path = tag_html_path("index", config);
+        let mut context = generated_page("Tags", &cloud_path);
+        context.insert("tag_cloud", &tags.values().collect::<Vec<_>>());
+        let rendered =
+            render_template(tera, "page", &context).context("Failed to render the tag index")?;
+        files.push((cloud_path, rendered));
+    }
+
+    // Generate the combined print page
+    if config.output.html.print_page {
+        let mut context = TeraContext::new();
+        context.insert("year", &current_year);
+        context.insert("config", &config);
+        context.insert("chapters", &print_chapters);
+
+        let rendered =
+            render_template(tera, "print", &context).context("Failed to render print page")?;
+        files.push((PathBuf::from("print.html"), rendered));
+    }
+
+    if config.output.html.robots.enabled {
+        files.push((
+            PathBuf::from("robots.txt"),
+            robots_txt(&config.output.html.robots),
+        ));
+    }
+
+    if config.output.html.pwa {
+        files.push((
+            PathBuf::from("manifest.webmanifest"),
+            web_manifest(config, options.input)?,
+        ));
+    }
+
+    if config.output.html.llms_txt {
+        let descriptions = all_pages
+            .iter()
+            .zip(&sources)
+            .filter_map(|(page, source)| {
+                let description = source.frontmatter.get("description")?.as_str()?;
+                Some((page.path.as_str(), description))
+            })
+            .collect();
+        files.push((
+            PathBuf::from("llms.txt"),
+            llms_txt(config, &sections, &descriptions),
+        ));
+    }
+    if config.output.html.llms_full_txt {
+        files.push((
+            PathBuf::from("llms-full.txt"),
+            llms_full_txt(config, &page_texts),
+        ));
+    }
+
+    if config.output.html.github_pages {
+        files.push((PathBuf::from(".nojekyll"), String::new()));
+    }
+
+    // Generate index page, unless the book's own index page should stand
+    // alone, or failed and has its placeholder already
+    let index_page = all_pages
+        .iter()
+        .zip(&sources)
+        .find(|(_, source)| source.html_path == Path::new("index.html"));
+    let index_failed = index_page.is_some_and(|(_, source)| {
+        failures
+            .iter()
+            .any(|failure| failure.path == source.rel_path)
+    });
+    if config.output.html.generate_index && !index_failed {
+        let mut context = TeraContext::new();
+        context.insert("year", &current_year);
+        context.insert("config", &config);
+        context.insert("sections", &sections);
+        context.insert("offline", &offline);
+        context.insert(
+            "cards",
+            &landing_cards(&sections, &all_pages, &sources, &summaries),
+        );
+        let current_url = page_url(Path::new("index.html"), trailing_slash(config));
+        context.insert("canonical_url", &canonical_url(config, &current_url));
+        context.insert("current_path", &"index.html");
+        context.insert("current_url", &current_url);
+
+        if let Some(((index, source), html_content)) = index_page.zip(index_content) {
+            // If index.md exists, use its content
+            context.insert("has_index", &true);
+            context.insert("title", &index.title);
+            context.insert(
+                "search_excluded",
+                &excluded_from_search(&source.frontmatter),
+            );
+            context.insert(
+                "description",
+                &page_description(&source.frontmatter, config),
+            );
+            context.insert(
+                "open_graph",
+                &OpenGraph::new(config, &index.title, "index.html"),
+            );
+            context.insert("content", &html_content);
+            context.insert(
+                "page_footer",
+                &page_footer(config, &html_paths, &current_year, &index.title)?,
+            );
+            context.insert("page_tags", &page_tags(&source.frontmatter, &tags));
+            context.insert(
+                "last_updated",
+                &last_updated
+                    .for_page(
+                        options
+                            .input
+                            .map(|input| input.join(&source.rel_path))
+                            .as_deref(),
+                        &source.frontmatter,
+                    )
+                    .map(|date| DisplayDate::new(&date, config))
+                    .transpose()?,
+            );
+        } else {
+            // If no index.md, use the default template with cards
+            let title = if config.book.title.is_empty() {
+                "Documentation"
+            } else {
+                config.book.title.as_str()
+            };
+            context.insert("has_index", &false);
+            context.insert("title", title);
+            context.insert("description", &config.book.description);
+            context.insert("open_graph", &OpenGraph::new(config, title, "index.html"));
+        }
+
+        let rendered =
+            render_template(tera, "index", &context).context("Failed to render index page")?;
+        files.push((PathBuf::from("index.html"), rendered));
+    }
+
+    // Last, so stubs can be checked against every page the book generates
+    let redirects = page_redirects(config, &all_pages, &sources)?;
+    let stubs = render_redirects(tera, config, &redirects, &files)?;
+    files.extend(stubs);
+
+    if offline {
+        let worker = service_worker(tera, &files)?;
+        files.push((PathBuf::from("service-worker.js"), worker));
+    }
+
+    Ok(RenderedBook {
+        files,
+        images,
+        warnings,
+        failures,
+    })
+}
+
+/// A page's converted content, from [`render_page_content`]
+struct PageContent<'a> {
+    /// The markdown the HTML was rendered from
+    markdown: Cow<'a, str>,
+    html: String,
+    summary: String,
+    warnings: BTreeSet<BuildWarning>,
+}
+
+/// Converts a page's markdown to HTML
+fn render_page_content<'a>(
+    source: &'a SourceFile,
+    title: &str,
+    sections: &[Section],
+    html_paths: &BTreeSet<PathBuf>,
+    config: &BookConfig,
+    #[cfg(feature = "syntax-highlighting")] ss: &SyntaxSet,
+    input: Option<&Path>,
+) -> Result<PageContent<'a>> {
+    let rel_path = &source.rel_path;
+    let page_config = source_config(config, rel_path);
+    let markdown_content = expand_toc(page_markdown(source, title, &page_config), sections)
+        .map_err(markdown_error(rel_path))?;
+    let more = more_marker(&markdown_content);
+    let summary = page_summary(
+        &markdown_content,
+        more.as_ref().map(|marker| marker.start),
+        &source.frontmatter,
+        title,
+        &page_config,
+    )
+    .map_err(markdown_error(rel_path))?;
+    // The marker only splits the summary off; a blank line keeps the blocks apart
+    let markdown_content = match more {
+        Some(marker) => {
+            let mut markdown = markdown_content.into_owned();
+            markdown.replace_range(marker, "\n");
+            Cow::Owned(markdown)
+        }
+        None => markdown_content,
+    };
+    let mut warnings = BTreeSet::new();
+    check_alt_text(&markdown_content, &page_config, rel_path, &mut warnings)
+        .map_err(markdown_error(rel_path))?;
+    #[cfg(feature = "syntax-highlighting")]
+    let html_content = postprocess_html(
+        &process_markdown_with_highlighting(
+            &markdown_content,
+            ss,
+            &page_config,
+            rel_path,
+            &mut warnings,
+        )
+        .map_err(markdown_error(rel_path))?,
+        rel_path,
+        html_paths,
+        config,
+    );
+    #[cfg(not(feature = "syntax-highlighting"))]
+    let html_content = postprocess_html(
+        &process_markdown_basic(&markdown_content, &page_config)
+            .map_err(markdown_error(rel_path))?,
+        rel_path,
+        html_paths,
+        config,
+    );
+    let html_content = link_directory_indexes(
+        &html_content,
+        rel_path,
+        html_paths,
+        trailing_slash(config),
+        &mut warnings,
+    );
+    let html_content = if config.output.html.optimize_images {
+        optimize_images(
+            &html_content,
+            input,
+            rel_path.parent().unwrap_or(Path::new("")),
+        )
+    } else {
+        html_content
+    };
+    Ok(PageContent {
+        markdown: markdown_content,
+        html: html_content,
+        summary,
+        warnings,
+    })
+}
+
+/// Applies `output.html.require-alt-text` to the page's markdown images and
+/// raw `<img>` tags, warning about or rejecting those without alt text
+fn check_alt_text(
+    markdown: &str,
+    config: &BookConfig,
+    file: &Path,
+    warnings: &mut BTreeSet<BuildWarning>,
+) -> Result<()> {
+    fn missing_alt(node: &Node, images: &mut Vec<String>) {
+        match node {
+            Node::Image(image) if image.alt.trim().is_empty() => images.push(image.url.clone()),
+            Node::ImageReference(image) if image.alt.trim().is_empty() => {
+                images.push(format!("[{}]", image.identifier));
+            }
+            Node::Html(html) => {
+                for (start, _) in html.value.match_indices("<img") {
+                    let tag = &html.value[start..];
+                    let tag = &tag[..tag.find('>').map_or(tag.len(), |end| end + 1)];
+                    if attribute_value(tag, "alt").is_none_or(|alt| alt.trim().is_empty()) {
+                        images.push(attribute_value(tag, "src").unwrap_or("<img>").to_string());
+                    }
+                }
+            }
+            _ => {
+                for child in node.children().into_iter().flatten() {
+                    missing_alt(child, images);
+                }
+            }
+        }
+    }
+
+    let policy = config.output.html.require_alt_text;
+    if policy == AltTextPolicy::Off {
+        return Ok(());
+    }
+    let ast = to_mdast(markdown, &build_markdown_options(config).parse)
         .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+    let mut images = Vec::new();
+    missing_alt(&ast, &mut images);
+
+    if policy == AltTextPolicy::Strict && !images.is_empty() {
+        anyhow::bail!(
+            "image(s) without alt text: {} (output.html.require-alt-text is strict)",
+            images.join(", ")
+        );
+    }
+    for image in images {
+        warnings.insert(BuildWarning::MissingAltText {
+            file: file.to_path_buf(),
+            image,
+        });
+    }
+    Ok(())
+}
+
+/// The byte range of a `<!-- more -->` line ending a page's summary, outside
+/// fenced code
+fn more_marker(markdown: &str) -> Option<std::ops::Range<usize>> {
+    let is_marker = |line: &str| {
+        line.trim()
+            .strip_prefix("<!--")
+            .and_then(|rest| rest.strip_suffix("-->"))
+            .is_some_and(|inner| inner.trim().eq_ignore_ascii_case("more"))
+    };
+
+    let mut offset = 0;
+    let mut fence = None;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let delimiter = ["```", "~~~"]
+            .into_iter()
+            .find(|delimiter| trimmed.starts_with(delimiter));
+        match (fence, delimiter) {
+            (None, Some(delimiter)) => fence = Some(delimiter),
+            (Some(open), Some(delimiter)) if open == delimiter => fence = None,
+            (None, None) if is_marker(line) => return Some(offset..offset + line.len()),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// A page's plain-text summary: its `summary` frontmatter, else the text
+/// before the `<!-- more -->` marker at `more`, else its first
+/// `output.html.excerpt-words` words. Code and a leading title are left out.
+fn page_summary(
+    markdown: &str,
+    more: Option<usize>,
+    frontmatter: &Frontmatter,
+    title: &str,
+    config: &BookConfig,
+) -> Result<String> {
+    if let Some(summary) = frontmatter.get("summary").and_then(|value| value.as_str()) {
+        return Ok(summary.trim().to_string());
+    }
+
+    let text = markdown_to_plaintext(&markdown[..more.unwrap_or(markdown.len())], config, false)?;
+    let mut lines = text.lines().peekable();
+    lines.next_if_eq(&title);
+    let mut words = lines.flat_map(str::split_whitespace);
+    if more.is_some() {
+        return Ok(words.collect::<Vec<_>>().join(" "));
+    }
+
+    let mut summary = words
+        .by_ref()
+        .take(config.output.html.excerpt_words)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if words.next().is_some() {
+        summary.push('…');
+    }
+    Ok(summary)
+}
+
+/// Attributes a failure to convert a page's markdown to its source file
+fn markdown_error(path: &Path) -> impl FnOnce(anyhow::Error) -> BuildError + '_ {
+    move |e| BuildError::Markdown {
+        path: path.to_path_buf(),
+        message: format!("{e:#}"),
+    }
+}
+
+/// Returns true if `path` has one of the configured markdown extensions
+fn is_markdown_file(path: &Path, config: &BookConfig) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            config
+                .markdown
+                .extensions
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Whether a source, by its path relative to the input directory, is part
+/// of the book. Builds a [`SourceFilter`] for the one path; checking many
+/// paths, build the filter once.
+pub fn should_include(path: &Path, config: &BookConfig) -> bool {
+    SourceFilter::new(config).includes(path)
+}
+
+/// Decides which sources are part of the book, with `build.ignore` and
+/// `build.include` compiled once for every path checked
+pub struct SourceFilter<'a> {
+    config: &'a BookConfig,
+    ignore: PathPatterns,
+    include: PathPatterns,
+}
+
+impl<'a> SourceFilter<'a> {
+    pub fn new(config: &'a BookConfig) -> Self {
+        Self {
+            config,
+            ignore: PathPatterns::new(&config.build.ignore),
+            include: PathPatterns::new(&config.build.include),
+        }
+    }
+
+    /// Whether a source, by its path relative to the input directory, is
+    /// part of the book: it must have a markdown extension, and is dropped
+    /// when `build.ignore` selects it unless `build.include` selects it back
+    pub fn includes(&self, path: &Path) -> bool {
+        is_markdown_file(path, self.config)
+            && (self.include.select(path) || !self.ignore.select(path))
+    }
+}
+
+/// Gitignore-style patterns compiled into one set. Invalid globs are
+/// rejected when the config is validated, so here they are left out.
+struct PathPatterns {
+    set: globset::GlobSet,
+    /// Whether each glob in `set` had a `!` prefix, making it an exception
+    negated: Vec<bool>,
+}
+
+impl PathPatterns {
+    fn new(patterns: &[String]) -> Self {
+        let mut builder = globset::GlobSetBuilder::new();
+        let mut negated = Vec::new();
+        for pattern in patterns {
+            let (is_negated, glob) = match pattern.strip_prefix('!') {
+                Some(glob) => (true, glob),
+                None => (false, pattern.as_str()),
+            };
+            if let Ok(glob) = crate::config::path_glob(glob) {
+                builder.add(glob);
+                negated.push(is_negated);
+            }
+        }
+        let set = builder
+            .build()
+            .unwrap_or_else(|_| globset::GlobSet::empty());
+        Self { set, negated }
+    }
+
+    /// Whether the patterns select `path`: the last pattern matching it or
+    /// one of its directories decides
+    fn select(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .flat_map(|ancestor| self.set.matches(ancestor))
+            .max()
+            .is_some_and(|index| !self.negated[index])
+    }
+}
+
+/// Returns the config a source file should be rendered with.
+///
+/// Matching `[[overrides]]` entries apply in order, and `.mdx` files are
+/// always parsed as MDX, whatever the configured format.
+pub(crate) fn source_config<'a>(config: &'a BookConfig, rel_path: &Path) -> Cow<'a, BookConfig> {
+    let is_mdx = rel_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mdx"));
+    let needs_mdx = is_mdx && !matches!(config.markdown.format, MarkdownFormat::Mdx);
+    let mut overrides = config
+        .overrides
+        .iter()
+        .filter(|entry| entry.matches(rel_path))
+        .peekable();
+    if !needs_mdx && overrides.peek().is_none() {
+        return Cow::Borrowed(config);
+    }
+
+    let mut page_config = config.clone();
+    for entry in overrides {
+        entry.apply(&mut page_config);
+    }
+    if is_mdx {
+        page_config.markdown.format = MarkdownFormat::Mdx;
+    }
+    Cow::Owned(page_config)
+}
+
+/// Renders a single markdown document to HTML using the same pipeline as a
+/// full build, without templates.
+///
+/// # Errors
+///
+/// Returns an error if the markdown cannot be parsed with the configured options
+pub fn render_markdown(content: &str, config: &BookConfig) -> Result<String> {
+    #[cfg(feature = "syntax-highlighting")]
+    let html = {
+        let ss = SyntaxSet::load_defaults_newlines();
+        process_markdown_with_highlighting(
+            content,
+            &ss,
+            config,
+            Path::new(""),
+            &mut BTreeSet::new(),
+        )?
+    };
+    #[cfg(not(feature = "syntax-highlighting"))]
+    let html = process_markdown_basic(content, config)?;
+
+    Ok(postprocess_html(
+        &html,
+        Path::new(""),
+        &BTreeSet::new(),
+        config,
+    ))
+}
+
+/// The pages found in an input directory, in build order
+struct Collection {
+    all_pages: Vec<PageInfo>,
+    sections: Vec<Section>,
+    sources: Vec<SourceFile>,
+    warnings: BTreeSet<BuildWarning>,
+}
+
+/// Returns the book's section and page tree without rendering anything.
+/// Drafts are left out, as in a normal build.
+///
+/// # Errors
+///
+/// Returns an error if a source file can't be read or has invalid frontmatter
+pub fn collect_pages(input: &Path, config: &BookConfig) -> Result<Vec<Section>> {
+    Ok(collect_sources(input, config, false)?.sections)
+}
+
+/// Reads every markdown page under `input` and groups them into sections,
+/// skipping drafts unless `drafts` is set
+fn collect_sources(input: &Path, config: &BookConfig, drafts: bool) -> Result<Collection> {
+    let mut warnings = BTreeSet::new();
+    let mut documents = Vec::new();
+
+    let entries = WalkDir::new(input)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && is_markdown_file(e.path(), config));
+    for entry in entries {
+        let Some(content) = read_markdown_source(entry.path(), config, &mut warnings)? else {
+            continue;
+        };
+        documents.push((entry.path().strip_prefix(input)?.to_path_buf(), content));
+    }
+
+    collect_documents(documents, config, drafts, warnings)
+}
+
+/// Groups markdown documents, keyed by their path relative to the book root,
+/// into pages and sections. Pages whose frontmatter sets `draft: true` are
+/// dropped here, before anything links to them, unless `drafts` is set.
+fn collect_documents(
+    mut documents: Vec<(PathBuf, String)>,
+    config: &BookConfig,
+    drafts: bool,
+    warnings: BTreeSet<BuildWarning>,
+) -> Result<Collection> {
+    let mut all_pages = Vec::new();
+    let mut section_map: BTreeMap<String, Vec<PageInfo>> = BTreeMap::new();
+    let mut root_pages: Vec<PageInfo> = Vec::new();
+
+    let filter = SourceFilter::new(config);
+    documents.retain(|(rel_path, _)| filter.includes(rel_path));
+    // Sort documents by path to ensure consistent ordering
+    documents.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let has_root_index = documents.iter().any(|(rel_path, _)| {
+        rel_path.parent() == Some(Path::new("")) && rel_path.file_stem() == Some("index".as_ref())
+    });
+
+    // Keyed case-insensitively: `Notes.html` and `notes.html` are one file on some filesystems
+    let mut output_paths: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut sources = Vec::new();
+    let mut post_dates = BTreeMap::new();
+    for (rel_path, content) in documents {
+        let frontmatter = if source_config(config, &rel_path).markdown.frontmatter {
+            parse_frontmatter(&content)
+                .with_context(|| format!("Invalid frontmatter in {}", rel_path.display()))?
+        } else {
+            Frontmatter::new()
+        };
+        if !drafts && frontmatter.get("draft").and_then(|value| value.as_bool()) == Some(true) {
+            continue;
+        }
+        let parent_dir = rel_path.parent().and_then(|p| p.to_str()).unwrap_or("");
+        let html_path = page_html_path(&rel_path, has_root_index, config.output.html.pretty_urls);
+        let output_key = html_path.to_string_lossy().to_lowercase();
+        if let Some(existing) = output_paths.get(&output_key) {
+            anyhow::bail!(
+                "{} and {} would both be written to {}",
+                existing.display(),
+                rel_path.display(),
+                html_path.display()
+            );
+        }
+        output_paths.insert(output_key, rel_path.clone());
+
+        let page_info = PageInfo {
+            title: extract_title(&content, config.markdown.title_heading_level).unwrap_or_else(
+                || {
+                    rel_path.file_stem().map_or_else(
+                        || "Untitled".to_string(),
+                        |s| s.to_string_lossy().into_owned(),
+                    )
+                },
+            ),
+            path: page_url(&html_path, trailing_slash(config)),
+        };
+
+        all_pages.push(page_info.clone());
+        if is_blog_post(&rel_path, config) {
+            post_dates.insert(page_info.path.clone(), post_date(&frontmatter));
+        }
+
+        if parent_dir.is_empty() {
+            root_pages.push(page_info);
+        } else {
+            section_map
+                .entry(parent_dir.to_string())
+                .or_default()
+                .push(page_info);
+        }
+
+        sources.push(SourceFile {
+            rel_path,
+            html_path,
+            content,
+            frontmatter,
+        });
+    }
+
+    // Blog posts are listed newest first, undated ones last, after the
+    // directory's own index page
+    for pages in section_map.values_mut() {
+        pages.sort_by_key(|page| {
+            post_dates
+                .get(&page.path)
+                .map(|date| std::cmp::Reverse(*date))
+        });
+    }
+
+    Ok(Collection {
+        all_pages,
+        sections: build_sections(root_pages, section_map, config),
+        sources,
+        warnings,
+    })
+}
+
+fn report_warnings(warnings: &BTreeSet<BuildWarning>) {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
+
+/// Anchor id for a page inside a combined document, e.g. `/guide/setup.html` -> `guide-setup`
+fn chapter_id(path: &str) -> String {
+    path.trim_start_matches('/')
+        .trim_end_matches(".html")
+        .replace('/', "-")
+}
+
+/// Renders the whole book as one self-contained `index.html`: stylesheets,
+/// the theme's variables and the scripts its diagrams need are inlined,
+/// local images become data URIs and links between pages become links to
+/// the chapter anchors.
+fn render_single_file(
+    tera: &Tera,
+    config: &BookConfig,
+    input: Option<&Path>,
+    sources: &[SourceFile],
+    mut chapters: Vec<PrintChapter>,
+    syntax_css: &str,
+) -> Result<String> {
+    let chapter_ids: BTreeMap<PathBuf, String> = chapters
+        .iter()
+        .zip(sources)
+        .map(|(chapter, source)| (source.html_path.clone(), chapter.id.clone()))
+        .collect();
+
+    for (chapter, source) in chapters.iter_mut().zip(sources) {
+        let page_dir = source.rel_path.parent().unwrap_or(Path::new(""));
+
+        let html = rewrite_attribute(&chapter.content, "src", |src| {
+            if is_external_url(src) {
+                return None;
+            }
+            let asset = input?.join(resolve_link(page_dir, src));
+            let bytes = fs::read(asset).ok()?;
+            Some(data_uri(Path::new(src), &bytes))
+        });
+        chapter.content = rewrite_attribute(&html, "href", |href| {
+            if is_external_url(href) {
+                return None;
+            }
+            let target = resolve_link(page_dir, href.split('#').next().unwrap_or(href));
+            chapter_ids
+                .get(&target)
+                .or_else(|| chapter_ids.get(&target.join("index.html")))
+                .map(|id| format!("#{id}"))
+        });
+    }
+
+    // The templates directory's copy of an asset, else the bundled one
+    let asset = |name: &str, bundled: &str| -> Result<String> {
+        let path = format!("{}/{name}", config.paths.templates);
+        if Path::new(&path).exists() {
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {path}"))
+        } else {
+            Ok(bundled.to_string())
+        }
+    };
+    let theme = asset("css/theme.css", include_str!("templates/css/theme.css"))?;
+    let styles = asset("css/styles.css", include_str!("templates/css/styles.css"))?;
+
+    let mut scripts = Vec::new();
+    if chapters
+        .iter()
+        .any(|chapter| chapter.content.contains("class=\"language-mermaid\""))
+    {
+        scripts.push(asset(
+            "js/mermaid.min.js",
+            include_str!("templates/js/mermaid.min.js"),
+        )?);
+        scripts.push(asset(
+            "js/mermaid-init.js",
+            include_str!("templates/js/mermaid-init.js"),
+        )?);
+    }
+    scripts.retain(|script| !script.contains("</script"));
+
+    let mut context = TeraContext::new();
+    context.insert("config", &config);
+    context.insert("chapters", &chapters);
+    context.insert("styles", &format!("{theme}\n{styles}\n{syntax_css}"));
+    context.insert("scripts", &scripts);
+
+    render_template(tera, "single", &context).context("Failed to render single-file book")
+}
+
+/// Points links at a directory, like `api/` or `api`, to the `index.html` or
+/// `README.html` page inside it, warning when the directory has neither.
+/// With a trailing-slash policy, links to an `index.html` page point at its
+/// directory instead, spelled as the policy says.
+fn link_directory_indexes(
+    html: &str,
+    file: &Path,
+    html_paths: &BTreeSet<PathBuf>,
+    trailing_slash: Option<TrailingSlash>,
+    warnings: &mut BTreeSet<BuildWarning>,
+) -> String {
+    let page_dir = file.parent().unwrap_or(Path::new(""));
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find("href=\"") {
+        let url_start = pos + 6;
+        let Some(url_len) = rest[url_start..].find('"') else {
+            break;
+        };
+        let url = &rest[url_start..url_start + url_len];
+        result.push_str(&rest[..url_start]);
+        rest = &rest[url_start + url_len..];
+
+        let (target, suffix) = url.split_at(url.find(['#', '?']).unwrap_or(url.len()));
+        let dir = resolve_link(page_dir, target);
+        let is_directory = !is_external_url(target)
+            && (target.ends_with('/')
+                || (Path::new(target).extension().is_none()
+                    && html_paths.iter().any(|path| path.starts_with(&dir))));
+        if !is_directory {
+            let links_to_index = !is_external_url(target)
+                && Path::new(target).file_name() == Some("index.html".as_ref())
+                && html_paths.contains(&dir);
+            match trailing_slash.filter(|_| links_to_index) {
+                Some(policy) => {
+                    let target = &target[..target.len() - "index.html".len()];
+                    result.push_str(&directory_url(target, policy));
+                    result.push_str(suffix);
+                }
+                None => result.push_str(url),
+            }
+            continue;
+        }
+
+        let index = html_paths.iter().find(|path| {
+            path.parent() == Some(dir.as_path())
+                && path
+                    .file_stem()
+                    .is_some_and(|stem| stem == "index" || stem.eq_ignore_ascii_case("readme"))
+        });
+        match (index.and_then(|index| index.file_name()), trailing_slash) {
+            (Some(index), Some(policy)) if index == "index.html" => {
+                result.push_str(&directory_url(target, policy));
+                result.push_str(suffix);
+            }
+            (Some(index), _) => {
+                let separator = if target.ends_with('/') { "" } else { "/" };
+                result.push_str(&format!(
+                    "{target}{separator}{}{suffix}",
+                    index.to_string_lossy()
+                ));
+            }
+            (None, _) => {
+                warnings.insert(BuildWarning::MissingDirectoryIndex {
+                    file: file.to_path_buf(),
+                    link: url.to_string(),
+                });
+                result.push_str(url);
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// How links to a directory's index page are spelled: as
+/// `output.html.trailing-slash` says, else with a slash under pretty URLs
+fn trailing_slash(config: &BookConfig) -> Option<TrailingSlash> {
+    let html = &config.output.html;
+    html.trailing_slash
+        .or(html.pretty_urls.then_some(TrailingSlash::Always))
+}
+
+/// Points a page's links at where `output.html.pretty-urls` wrote things.
+/// Links to a moved page (`setup.html`) go to its directory (`setup/`), and
+/// a page written one directory deeper than its source gets `../` on its
+/// relative links and images, so they reach the same files as before.
+fn pretty_url_links(
+    html: &str,
+    source: &SourceFile,
+    html_paths: &BTreeSet<PathBuf>,
+    config: &BookConfig,
+) -> String {
+    let Some(policy) = trailing_slash(config).filter(|_| config.output.html.pretty_urls) else {
+        return html.to_string();
+    };
+    let page_dir = source.rel_path.parent().unwrap_or(Path::new(""));
+    let moved = source.html_path.parent() != source.rel_path.parent();
+    let relocate = |url: &str, is_link: bool| {
+        if is_external_url(url) {
+            return None;
+        }
+        let (target, suffix) = url.split_at(url.find(['#', '?']).unwrap_or(url.len()));
+        let mut new_target = target.to_string();
+        if let Some(page) = target.strip_suffix(".html").filter(|_| is_link) {
+            let resolved = resolve_link(page_dir, target);
+            if !html_paths.contains(&resolved)
+                && html_paths.contains(&resolved.with_extension("").join("index.html"))
+            {
+                new_target = directory_url(&format!("{page}/"), policy);
+            }
+        }
+        if moved && !target.starts_with('/') {
+            new_target.insert_str(0, "../");
+        }
+        (new_target != target).then(|| format!("{new_target}{suffix}"))
+    };
+    let html = rewrite_attribute(html, "href", |href| relocate(href, true));
+    rewrite_attribute(&html, "src", |src| relocate(src, false))
+}
+
+/// A link to a directory spelled per the trailing-slash policy; the
+/// directory the link is relative to becomes `./`, the book root `/`
+fn directory_url(dir: &str, policy: TrailingSlash) -> String {
+    let trimmed = dir.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return if dir.starts_with('/') { "/" } else { "./" }.to_string();
+    }
+    match policy {
+        TrailingSlash::Always => format!("{trimmed}/"),
+        TrailingSlash::Never => trimmed.to_string(),
+    }
+}
+
+/// Returns true for URLs that point outside the book (or are already inline)
+fn is_external_url(url: &str) -> bool {
+    url.is_empty()
+        || url.starts_with('#')
+        || url.starts_with("//")
+        || url.contains("://")
+        || url.starts_with("mailto:")
+        || url.starts_with("data:")
+}
+
+/// Resolves a link found on a page in `page_dir` to a path relative to the
+/// book root. Absolute links (`/img/a.png`) are taken from the root.
+fn resolve_link(page_dir: &Path, link: &str) -> PathBuf {
+    let joined = match link.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => page_dir.join(link),
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::Normal(part) => resolved.push(part),
+            _ => {}
+        }
+    }
+    resolved
+}
+
+/// Copies the local images pages reference, given as (page, `src`) pairs,
+/// from the source tree to the same place in the output, so relative links
+/// like `diagram.png` or `../shared/img.png` keep resolving
+fn copy_page_images(
+    input: &Path,
+    output: &Path,
+    images: &[(PathBuf, String)],
+    warnings: &mut BTreeSet<BuildWarning>,
+    out: OutputFs,
+) -> Result<()> {
+    let mut copied = BTreeSet::new();
+
+    for (rel_path, src) in images {
+        let page_dir = rel_path.parent().unwrap_or(Path::new(""));
+        let image = resolve_link(page_dir, src);
+        if copied.contains(&image) {
+            continue;
+        }
+
+        let source = input.join(&image);
+        if !source.is_file() {
+            warnings.insert(BuildWarning::MissingImage {
+                file: rel_path.clone(),
+                image: src.clone(),
+            });
+            continue;
+        }
+
+        let dest = output.join(&image);
+        if let Some(parent) = dest.parent() {
+            out.create_dir_all(parent)?;
+        }
+        out.copy(&source, &dest)
+            .with_context(|| format!("Failed to copy image: {}", source.display()))?;
+        copied.insert(image);
+    }
+
+    Ok(())
+}
+
+/// The `src` of every `<img>` tag in `html`
+fn image_sources(html: &str) -> impl Iterator<Item = &str> {
+    html.match_indices("<img ").filter_map(|(pos, _)| {
+        let tag = &html[pos..pos + html[pos..].find('>')?];
+        attribute_value(tag, "src")
+    })
+}
+
+/// Adds `loading="lazy"` to every image and, for local PNG, GIF and JPEG
+/// files, the intrinsic `width`/`height` so the page doesn't shift as they load
+fn optimize_images(html: &str, input_dir: Option<&Path>, page_dir: &Path) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(pos) = rest.find("<img ") {
+        let Some(tag_len) = rest[pos..].find('>') else {
+            break;
+        };
+        let tag = &rest[pos..pos + tag_len];
+
+        result.push_str(&rest[..pos + "<img".len()]);
+        if !tag.contains(" loading=") {
+            result.push_str(" loading=\"lazy\"");
+        }
+        if !tag.contains(" width=") && !tag.contains(" height=") {
+            let dimensions = attribute_value(tag, "src")
+                .filter(|src| !is_external_url(src))
+                .and_then(|src| fs::read(input_dir?.join(resolve_link(page_dir, src))).ok())
+                .and_then(|bytes| image_dimensions(&bytes));
+            if let Some((width, height)) = dimensions {
+                result.push_str(&format!(" width=\"{width}\" height=\"{height}\""));
+            }
+        }
+        rest = &rest[pos + "<img".len()..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// The value of a double-quoted attribute within a single tag
+fn attribute_value<'a>(tag: &'a str, attr: &str) -> Option<&'a str> {
+    let needle = format!(" {attr}=\"");
+    let start = tag.find(&needle)? + needle.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+/// Reads the pixel dimensions from a PNG, GIF or JPEG header
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        let width = u16::from_le_bytes(bytes.get(6..8)?.try_into().ok()?);
+        let height = u16::from_le_bytes(bytes.get(8..10)?.try_into().ok()?);
+        return Some((width.into(), height.into()));
+    }
+
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Walk the segments up to the start-of-frame marker
+        let mut pos = 2;
+        while *bytes.get(pos)? == 0xFF {
+            let marker = *bytes.get(pos + 1)?;
+            if matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) {
+                let height = be16(pos + 5)?;
+                let width = be16(pos + 7)?;
+                return Some((width.into(), height.into()));
+            }
+            pos += 2 + usize::from(be16(pos + 2)?);
+        }
+    }
+
+    None
+}
+
+/// Inlines the local stylesheets, scripts and images a page references that
+/// are smaller than `limit` bytes: a `<link rel="stylesheet">` becomes a
+/// `<style>`, a `<script src>` carries its code, and an `<img>` gets a data
+/// URI. `read` loads an asset by its path relative to the book root.
+///
+/// Stylesheets using `url()` or `@import` and scripts with `import`s stay
+/// linked, since their relative references would break inside the page.
+fn inline_small_assets(
+    html: &str,
+    page: &Path,
+    limit: u64,
+    read: impl Fn(&Path) -> Option<Vec<u8>>,
+) -> String {
+    let page_dir = page.parent().unwrap_or(Path::new(""));
+    let load = |url: &str| {
+        if is_external_url(url) {
+            return None;
+        }
+        let target = &url[..url.find(['#', '?']).unwrap_or(url.len())];
+        let bytes = read(&resolve_link(page_dir, target))?;
+        (u64::try_from(bytes.len()).ok()? < limit).then_some(bytes)
+    };
+    let text = |url: &str| String::from_utf8(load(url)?).ok();
+
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find('<') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let Some(tag_len) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_len];
+
+        if tag.starts_with("<link ") && attribute_value(tag, "rel") == Some("stylesheet") {
+            let css = attribute_value(tag, "href")
+                .and_then(text)
+                .filter(|css| !css.contains("url(") && !css.contains("@import"));
+            if let Some(css) = css.filter(|css| !css.contains("</style")) {
+                result.push_str(&format!("<style>{css}</style>"));
+                rest = &rest[tag_len + 1..];
+                continue;
+            }
+        } else if tag.starts_with("<script ") && rest[tag_len..].starts_with("></script>") {
+            let script = attribute_value(tag, "src").and_then(text).filter(|js| {
+                !js.contains("import ") && !js.contains("import(") && !js.contains("import.meta")
+            });
+            if let Some(js) = script.filter(|js| !js.contains("</script")) {
+                let src = attribute_value(tag, "src").unwrap_or_default();
+                let attributes = tag.replacen(&format!(" src=\"{src}\""), "", 1);
+                result.push_str(&format!("{attributes}>{js}</script>"));
+                rest = &rest[tag_len + "></script>".len()..];
+                continue;
+            }
+        } else if tag.starts_with("<img ") {
+            let src = attribute_value(tag, "src").unwrap_or_default();
+            if let Some(bytes) = load(src) {
+                result.push_str(&tag.replacen(
+                    &format!(" src=\"{src}\""),
+                    &format!(" src=\"{}\"", data_uri(Path::new(src), &bytes)),
+                    1,
+                ));
+                rest = &rest[tag_len..];
+                continue;
+            }
+        }
+        result.push_str(&rest[..1]);
+        rest = &rest[1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Encodes a file as a `data:` URI, guessing the MIME type from its extension
+fn data_uri(path: &Path, bytes: &[u8]) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:{};base64,{}",
+        mime_type(path),
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+/// The MIME type of an asset, guessed from its extension
+fn mime_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Rewrites the value of every double-quoted `attr="..."` attribute in `html`
+/// for which `rewrite` returns `Some`
+fn rewrite_attribute(
+    html: &str,
+    attr: &str,
+    mut rewrite: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let needle = format!(" {attr}=\"");
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(pos) = rest.find(&needle) {
+        let value_start = pos + needle.len();
+        let Some(value_len) = rest[value_start..].find('"') else {
+            break;
+        };
+        let value = &rest[value_start..value_start + value_len];
+
+        result.push_str(&rest[..value_start]);
+        match rewrite(value) {
+            Some(new_value) => result.push_str(&new_value),
+            None => result.push_str(value),
+        }
+        rest = &rest[value_start + value_len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Reads a markdown source file, stripping a leading UTF-8 byte order mark.
+///
+/// Returns `Ok(None)` for files that are not valid UTF-8 so the caller can skip
+/// them, recording a warning, unless `markdown.fail_on_invalid_utf8` is set.
+fn read_markdown_source(
+    path: &Path,
+    config: &BookConfig,
+    warnings: &mut BTreeSet<BuildWarning>,
+) -> Result<Option<String>> {
+    let bytes = fs::read(path).map_err(|source| BuildError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(&bytes);
+
+    match std::str::from_utf8(bytes) {
+        Ok(content) => Ok(Some(content.to_string())),
+        Err(e) if config.markdown.fail_on_invalid_utf8 => {
+            Err(anyhow::anyhow!("Invalid UTF-8 in {}: {e}", path.display()))
+        }
+        Err(e) => {
+            warnings.insert(BuildWarning::InvalidUtf8 {
+                file: path.to_path_buf(),
+                message: e.to_string(),
+            });
+            Ok(None)
+        }
+    }
+}
+
+/// Groups collected pages into sidebar sections: root pages first, under
+/// `book.root_section_title` (default "Guide") or untitled when
+/// `book.flatten_root_section` is set, then one section per directory.
+fn build_sections(
+    root_pages: Vec<PageInfo>,
+    section_map: BTreeMap<String, Vec<PageInfo>>,
+    config: &BookConfig,
+) -> Vec<Section> {
+    let mut sections = Vec::new();
+
+    // Add root pages first if they exist
+    if !root_pages.is_empty() {
+        let title = if config.book.flatten_root_section {
+            None
+        } else {
+            Some(
+                config
+                    .book
+                    .root_section_title
+                    .clone()
+                    .unwrap_or_else(|| "Guide".to_string()),
+            )
+        };
+        sections.push(Section {
+            title,
+            pages: root_pages,
+            is_active: false,
+        });
+    }
+
+    // Add other sections
+    for (title, pages) in section_map {
+        sections.push(Section {
+            title: Some(title),
+            pages,
+            is_active: false,
+        });
+    }
+
+    sections
+}
+
+/// Returns a copy of `sections` with the section containing `current_path` marked active
+fn mark_active_section(sections: &[Section], current_path: &str) -> Vec<Section> {
+    let page_path = format!("/{}", current_path.trim_start_matches('/'));
+    sections
+        .iter()
+        .map(|section| Section {
+            is_active: section.pages.iter().any(|page| page.path == page_path),
+            ..section.clone()
+        })
+        .collect()
+}
+
+/// Builds the canonical URL for an output path, or `None` if `book.base_url` is unset
+fn canonical_url(config: &BookConfig, current_path: &str) -> Option<String> {
+    let base_url = config.book.base_url.as_deref()?.trim_end_matches('/');
+    if base_url.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{}/{}",
+        base_url,
+        current_path.trim_start_matches('/')
+    ))
+}
+
+/// Parses a leading YAML (`---`) or TOML (`+++`) frontmatter block.
+/// Returns an empty map when the page has none.
+fn parse_frontmatter(markdown: &str) -> Result<Frontmatter> {
+    let Some((fence, body)) = ["---", "+++"].into_iter().find_map(|fence| {
+        let rest = markdown.strip_prefix(fence)?;
+        let body = rest
+            .strip_prefix("\r\n")
+            .or_else(|| rest.strip_prefix('\n'))?;
+        Some((fence, body))
+    }) else {
+        return Ok(Frontmatter::new());
+    };
+
+    let mut end = None;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        if line.trim_end() == fence {
+            end = Some(offset);
+            break;
+        }
+        offset += line.len();
+    }
+    // An unclosed fence is not frontmatter, just content
+    let Some(end) = end else {
+        return Ok(Frontmatter::new());
+    };
+
+    let raw = &body[..end];
+    if raw.trim().is_empty() {
+        return Ok(Frontmatter::new());
+    }
+
+    let value = if fence == "---" {
+        serde_yaml::from_str(raw).context("Invalid YAML frontmatter")?
+    } else {
+        toml_to_json(toml::from_str(raw).context("Invalid TOML frontmatter")?)
+    };
+
+    match value {
+        serde_json::Value::Object(map) => Ok(map),
+        serde_json::Value::Null => Ok(Frontmatter::new()),
+        _ => Err(anyhow::anyhow!("Frontmatter must be a table of keys")),
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => s.into(),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(items) => items.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// The markdown-rs parse and compile options for the configured format,
+/// frontmatter, footnote, task list and raw-HTML settings
+pub(crate) fn build_markdown_options(config: &BookConfig) -> markdown::Options {
+    let parse_options = match config.markdown.format {
+        MarkdownFormat::Mdx => markdown::ParseOptions::mdx(),
+        MarkdownFormat::Gfm => markdown::ParseOptions::gfm(),
+        MarkdownFormat::Markdown => markdown::ParseOptions::default(),
+    };
+
+    let compile_options = if matches!(config.markdown.format, MarkdownFormat::Gfm) {
+        markdown::CompileOptions::gfm()
+    } else {
+        markdown::CompileOptions::default()
+    };
+
+    let mut options = markdown::Options {
+        parse: parse_options,
+        compile: compile_options,
+    };
+
+    // Modify constructs for HTML and frontmatter
+    options.parse.constructs.frontmatter = config.markdown.frontmatter;
+    options.parse.constructs.gfm_footnote_definition = config.markdown.footnotes;
+    options.parse.constructs.gfm_label_start_footnote = config.markdown.footnotes;
+    // GFM always has task lists; other formats opt in
+    options.parse.constructs.gfm_task_list_item |= config.markdown.tasklists;
+    options.parse.constructs.html_flow = config.output.html.allow_html;
+    options.parse.constructs.html_text = config.output.html.allow_html;
+    options.compile.allow_dangerous_html = config.output.html.allow_html;
+    options.compile.allow_dangerous_protocol = config.output.html.allow_html;
+
+    options
+}
+
+/// Extracts the readable text of a markdown document, one line per block, as
+/// used for search, `llms-full.txt` and the JSON export. Link and image text
+/// is kept; raw HTML and frontmatter are dropped, and code blocks and inline
+/// code too unless `include_code` is set.
+///
+/// # Errors
+///
+/// Returns an error if the markdown cannot be parsed with the configured options
+pub fn markdown_to_plaintext(
+    markdown: &str,
+    config: &BookConfig,
+    include_code: bool,
+) -> Result<String> {
+    let ast = to_mdast(markdown, &build_markdown_options(config).parse)
+        .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+
+    let mut text = String::new();
+    collect_text(&ast, &mut text, include_code);
+    Ok(text.trim_end().to_string())
+}
+
+fn collect_text(node: &Node, text: &mut String, include_code: bool) {
+    match node {
+        Node::Text(t) => text.push_str(&t.value),
+        Node::Image(image) => text.push_str(&image.alt),
+        Node::InlineCode(c) if include_code => text.push_str(&c.value),
+        Node::Code(c) if include_code => text.push_str(&c.value),
+        Node::InlineCode(_) | Node::Code(_) => {}
+        Node::Break(_) => text.push('\n'),
+        Node::Html(_) | Node::Yaml(_) | Node::Toml(_) => {}
+        _ => {
+            for child in node.children().into_iter().flatten() {
+                collect_text(child, text, include_code);
+            }
+        }
+    }
+
+    match node {
+        Node::TableCell(_) => text.push(' '),
+        Node::Paragraph(_)
+        | Node::Heading(_)
+        | Node::Code(_)
+        | Node::ListItem(_)
+        | Node::TableRow(_)
+            if !text.is_empty() && !text.ends_with('\n') =>
+        {
+            text.push('\n')
+        }
+        _ => {}
+    }
+}
+
+/// Replaces straight quotes, `--`/`---` and `...` with their typographic
+/// forms. Only text nodes are rewritten, so code, raw HTML and URLs keep their
+/// ASCII punctuation; a backslash-escaped character is left alone.
+fn smart_punctuation(content: &str, parse_options: &markdown::ParseOptions) -> Result<String> {
+    fn collect_text_ranges(node: &Node, ranges: &mut Vec<(usize, usize)>) {
+        match node {
+            Node::Text(text) => {
+                if let Some(pos) = &text.position {
+                    ranges.push((pos.start.offset, pos.end.offset));
+                }
+            }
+            // Autolinks show their URL as the link text
+            Node::Link(link) if matches!(link.children.as_slice(), [Node::Text(text)] if text.value == link.url) =>
+                {}
+            _ => {
+                for child in node.children().into_iter().flatten() {
+                    collect_text_ranges(child, ranges);
+                }
+            }
+        }
+    }
+
+    let ast = to_mdast(content, parse_options)
+        .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+    let mut ranges = Vec::new();
+    collect_text_ranges(&ast, &mut ranges);
+
+    let mut result = String::with_capacity(content.len());
+    let mut last_pos = 0;
+    for (start, end) in ranges {
+        result.push_str(&content[last_pos..start]);
+        let previous = content[..start].chars().next_back();
+        let next = content[end..].chars().next();
+        result.push_str(&smarten_text(&content[start..end], previous, next));
+        last_pos = end;
+    }
+    result.push_str(&content[last_pos..]);
+    Ok(result)
+}
+
+/// Applies the typographic substitutions to one run of prose. `previous` and
+/// `following` are the source characters around the run, used to pick the
+/// direction of quotes at its edges.
+fn smarten_text(text: &str, mut previous: Option<char>, following: Option<char>) -> String {
+    fn opens_quote(previous: Option<char>, next: Option<char>) -> bool {
+        match previous {
+            None => true,
+            Some(p)
+                if p.is_whitespace() || "([{-\u{2013}\u{2014}\"'\u{201C}\u{2018}".contains(p) =>
+            {
+                true
+            }
+            // Emphasis markers: `*"quoted"*` opens, `**bold**"` closes
+            Some('*' | '_' | '~') => next.is_some_and(char::is_alphanumeric),
+            Some(_) => false,
+        }
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                result.push(c);
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                    previous = Some(escaped);
+                }
+                continue;
+            }
+            '"' | '\'' => {
+                let next = chars.peek().copied().or(following);
+                let opening = opens_quote(previous, next);
+                result.push(match (c, opening) {
+                    ('"', true) => '\u{201C}',
+                    ('"', false) => '\u{201D}',
+                    (_, true) => '\u{2018}',
+                    (_, false) => '\u{2019}',
+                });
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    result.push('\u{2014}');
+                } else {
+                    result.push('\u{2013}');
+                }
+            }
+            '.' if chars.clone().take(2).eq(['.', '.']) => {
+                chars.next();
+                chars.next();
+                result.push('\u{2026}');
+            }
+            _ => result.push(c),
+        }
+        previous = Some(c);
+    }
+    result
+}
+
+/// `output.html.redirect` plus a redirect to each page from every old path
+/// listed in its frontmatter `aliases`
+fn page_redirects(
+    config: &BookConfig,
+    all_pages: &[PageInfo],
+    sources: &[SourceFile],
+) -> Result<BTreeMap<String, String>> {
+    let mut redirects = config.output.html.redirect.clone();
+    let taken = redirects
+        .keys()
+        .map(|from| redirect_path(from))
+        .collect::<Result<BTreeSet<_>>>()?;
+    let mut aliases: BTreeMap<PathBuf, &Path> = BTreeMap::new();
+    let mut claimed = Vec::new();
+    for (page, source) in all_pages.iter().zip(sources) {
+        let Some(list) = source.frontmatter.get("aliases") else {
+            continue;
+        };
+        let list = list.as_array().with_context(|| {
+            format!(
+                "Invalid frontmatter in {}: `aliases` must be a list of paths",
+                source.rel_path.display()
+            )
+        })?;
+        let target = canonical_url(config, &page.path)
+            .unwrap_or_else(|| format!("/{}", page.path.trim_start_matches('/')));
+        for alias in list {
+            let from = alias.as_str().with_context(|| {
+                format!(
+                    "Invalid frontmatter in {}: `aliases` must be a list of paths",
+                    source.rel_path.display()
+                )
+            })?;
+            let key = redirect_path(from)
+                .with_context(|| format!("Invalid alias in {}", source.rel_path.display()))?;
+            if taken.contains(&key) {
+                anyhow::bail!(
+                    "Alias `{from}` in {} is already in output.html.redirect",
+                    source.rel_path.display()
+                );
+            }
+            if let Some(other) = aliases.get(&key) {
+                anyhow::bail!(
+                    "Alias `{from}` is claimed by both {} and {}",
+                    other.display(),
+                    source.rel_path.display()
+                );
+            }
+            aliases.insert(key, &source.rel_path);
+            claimed.push((from.to_string(), target.clone()));
+        }
+    }
+    for source in sources {
+        if let Some(owner) = aliases.get(&source.html_path) {
+            anyhow::bail!(
+                "Alias `{}` in {} would overwrite the page built from {}",
+                source.html_path.display(),
+                owner.display(),
+                source.rel_path.display()
+            );
+        }
+    }
+    redirects.extend(claimed);
+    Ok(redirects)
+}
+
+/// Renders redirects from old paths to new URLs as meta-refresh stub pages
+/// and/or a Netlify-style `_redirects` file, per `output.html.redirect-format`
+///
+/// Stubs may not overwrite any of the `generated` files, nor `404.html`,
+/// which hosts serve for missing pages.
+fn render_redirects(
+    tera: &Tera,
+    config: &BookConfig,
+    redirects: &BTreeMap<String, String>,
+    generated: &[(PathBuf, String)],
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    if redirects.is_empty() {
+        return Ok(files);
+    }
+    let format = config.output.html.redirect_format;
+
+    if matches!(format, RedirectFormat::Meta | RedirectFormat::Both) {
+        for (from, to) in redirects {
+            let path = redirect_path(from)?;
+            if path == Path::new("404.html") || generated.iter().any(|(file, _)| *file == path) {
+                anyhow::bail!(
+                    "Redirect from `{from}` would overwrite the generated {}",
+                    path.display()
+                );
+            }
+            let mut context = TeraContext::new();
+            context.insert("config", &config);
+            // Only quotes need escaping; Tera's `escape` would mangle the slashes
+            context.insert("target", &html_escape::encode_double_quoted_attribute(to));
+            let rendered = render_template(tera, "redirect", &context)
+                .with_context(|| format!("Failed to render redirect: {from}"))?;
+            files.push((path, rendered));
+        }
+    }
+
+    if matches!(format, RedirectFormat::Netlify | RedirectFormat::Both) {
+        let rules: String = redirects
+            .iter()
+            .map(|(from, to)| format!("/{} {to} 301\n", from.trim_start_matches('/')))
+            .collect();
+        files.push((PathBuf::from("_redirects"), rules));
+    }
+
+    Ok(files)
+}
+
+/// Where the stub for a redirect from `from` is written, relative to the
+/// output directory; a directory such as `/old/` gets an `index.html`.
+/// `from` is a path within the book, so it may not be absolute or climb out
+/// with `..`.
+fn redirect_path(from: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::from(from.trim_start_matches('/'));
+    if from.ends_with('/') {
+        path.push("index.html");
+    }
+    let inside = path
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+    if path.as_os_str().is_empty() || !inside {
+        anyhow::bail!("Invalid redirect from `{from}`: must be a path inside the book");
+    }
+    Ok(path)
+}
+
+/// The offline service worker, precaching every page built. Its cache is
+/// named after the pages' contents, so each change drops the old copies.
+fn service_worker(tera: &Tera, files: &[(PathBuf, String)]) -> Result<String> {
+    let mut version = Sha256::new();
+    let mut pages = Vec::new();
+    for (path, contents) in files {
+        if path.extension().is_some_and(|ext| ext == "html") {
+            version.update(path.to_string_lossy().as_bytes());
+            version.update(content_hash(contents.as_bytes()));
+            // Relative to the worker, so it works wherever the book is mounted
+            pages.push(path.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    let mut context = TeraContext::new();
+    let version: String = version.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    context.insert("version", &version);
+    context.insert("pages", &pages);
+    render_template(tera, "service-worker", &context).context("Failed to render service worker")
+}
+
+/// A [web app manifest](https://developer.mozilla.org/en-US/docs/Web/Manifest)
+/// making the book installable, with the logo as its icon. URLs are relative
+/// to the manifest, so it works wherever the book is mounted. A PNG, GIF or
+/// JPEG logo found in `input` or the templates directory is given its pixel
+/// size; only an SVG one fits `any`.
+fn web_manifest(config: &BookConfig, input: Option<&Path>) -> Result<String> {
+    let book = &config.book;
+    let mut manifest = serde_json::json!({
+        "name": book.title,
+        "short_name": book.title,
+        "start_url": "./",
+        "scope": "./",
+        "display": "standalone",
+    });
+    if let Some(description) = &book.description {
+        manifest["description"] = description.as_str().into();
+    }
+    if let Some(color) = &config.output.html.theme_color {
+        manifest["theme_color"] = color.as_str().into();
+    }
+    if !book.logo.is_empty() {
+        let logo = Path::new(&book.logo);
+        let external = is_external_url(&book.logo);
+        let src = if external {
+            book.logo.as_str()
+        } else {
+            book.logo.trim_start_matches('/')
+        };
+        let mut icon = serde_json::json!({
+            "src": src,
+            "type": mime_type(logo),
+        });
+        if logo
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+        {
+            icon["sizes"] = "any".into();
+        } else if !external {
+            let templates = Path::new(&config.paths.templates);
+            let dimensions = input
+                .into_iter()
+                .chain([templates])
+                .find_map(|dir| fs::read(dir.join(src)).ok())
+                .and_then(|bytes| image_dimensions(&bytes));
+            if let Some((width, height)) = dimensions {
+                icon["sizes"] = format!("{width}x{height}").into();
+            }
+        }
+        manifest["icons"] = serde_json::json!([icon]);
+    }
+    Ok(serde_json::to_string_pretty(&manifest)? + "\n")
+}
+
+/// A `robots.txt` applying the configured rules to every crawler. With no
+/// rules it allows everything.
+fn robots_txt(robots: &RobotsConfig) -> String {
+    let mut txt = String::from("User-agent: *\n");
+    for path in &robots.allow {
+        txt.push_str(&format!("Allow: {path}\n"));
+    }
+    for path in &robots.disallow {
+        txt.push_str(&format!("Disallow: {path}\n"));
+    }
+    if robots.allow.is_empty() && robots.disallow.is_empty() {
+        txt.push_str("Disallow:\n");
+    }
+    txt
+}
+
+/// A page's absolute URL under `book.base_url`, or its path from the site
+/// root when no base URL is set
+fn absolute_url(config: &BookConfig, path: &str) -> String {
+    canonical_url(config, path).unwrap_or_else(|| path.to_string())
+}
+
+/// An [`llms.txt`](https://llmstxt.org) index: the book's title and
+/// description, then a list of links per section, with each page's
+/// frontmatter `description` when it has one
+fn llms_txt(
+    config: &BookConfig,
+    sections: &[Section],
+    descriptions: &BTreeMap<&str, &str>,
+) -> String {
+    let mut txt = format!("# {}\n", config.book.title);
+    if let Some(description) = &config.book.description {
+        txt.push_str(&format!("\n> {description}\n"));
+    }
+    for section in sections {
+        txt.push_str(&format!(
+            "\n## {}\n\n",
+            section.title.as_deref().unwrap_or("Pages")
+        ));
+        for page in &section.pages {
+            txt.push_str(&format!(
+                "- [{}]({})",
+                escape_markdown(&page.title),
+                absolute_url(config, &page.path)
+            ));
+            if let Some(description) = descriptions.get(page.path.as_str()) {
+                txt.push_str(&format!(": {description}"));
+            }
+            txt.push('\n');
+        }
+    }
+    txt
+}
+
+/// `llms-full.txt`: the readable text of every page, in book order, each
+/// under its title and URL
+fn llms_full_txt(config: &BookConfig, pages: &[(PageInfo, String)]) -> String {
+    let mut txt = format!("# {}\n", config.book.title);
+    for (page, text) in pages {
+        txt.push_str(&format!(
+            "\n---\n\n# {}\n\nURL: {}\n\n{text}\n",
+            page.title,
+            absolute_url(config, &page.path)
+        ));
+    }
+    txt
+}
+
+/// Whether the page's frontmatter opts it out of the search index, with
+/// `search: false` or `noindex: true`
+fn excluded_from_search(frontmatter: &Frontmatter) -> bool {
+    frontmatter.get("search").and_then(|value| value.as_bool()) == Some(false)
+        || frontmatter.get("noindex").and_then(|value| value.as_bool()) == Some(true)
+}
+
+/// The template a page renders with: its frontmatter `template`, naming any
+/// registered template such as a `landing.html.tera` partial, or `page`
+fn page_template<'a>(tera: &Tera, frontmatter: &'a Frontmatter, page: &Path) -> Result<&'a str> {
+    let Some(template) = frontmatter.get("template") else {
+        return Ok("page");
+    };
+    let template = template
+        .as_str()
+        .with_context(|| format!("`template` in {} must be a template name", page.display()))?;
+    if !tera.get_template_names().any(|name| name == template) {
+        anyhow::bail!(
+            "Unknown template `{template}` selected by {}",
+            page.display()
+        );
+    }
+    Ok(template)
+}
+
+/// The page's frontmatter `description`, falling back to `book.description`
+fn page_description<'a>(frontmatter: &'a Frontmatter, config: &'a BookConfig) -> Option<&'a str> {
+    frontmatter
+        .get("description")
+        .and_then(|value| value.as_str())
+        .or(config.book.description.as_deref())
+}
+
+/// `output.html.page-footer` rendered for one page, with its placeholders filled in
+fn page_footer(
+    config: &BookConfig,
+    html_paths: &BTreeSet<PathBuf>,
+    year: &str,
+    title: &str,
+) -> Result<Option<String>> {
+    let Some(footer) = &config.output.html.page_footer else {
+        return Ok(None);
+    };
+    let markdown = footer.replace("{year}", year).replace("{title}", title);
+    let html = to_html_with_options(&markdown, &build_markdown_options(config))
+        .map_err(|e| anyhow::anyhow!("Failed to render output.html.page-footer: {e}"))?;
+    let html = postprocess_html(&html, Path::new(""), html_paths, config);
+
+    // Every page shares the footer, so its links can't be relative to one
+    let policy = trailing_slash(config);
+    let root_absolute = |url: &str| {
+        if is_external_url(url) || url.starts_with(['/', '#']) {
+            return None;
+        }
+        let (target, suffix) = url.split_at(url.find(['#', '?']).unwrap_or(url.len()));
+        let target = resolve_link(Path::new(""), target);
+        let pretty = target.with_extension("").join("index.html");
+        let url = if html_paths.contains(&target) {
+            page_url(&target, policy)
+        } else if config.output.html.pretty_urls && html_paths.contains(&pretty) {
+            page_url(&pretty, policy)
+        } else {
+            format!("/{}", target.display())
+        };
+        Some(format!("{url}{suffix}"))
+    };
+    let html = rewrite_attribute(&html, "href", root_absolute);
+    Ok(Some(rewrite_attribute(&html, "src", root_absolute)))
+}
+
+/// The landing page's cards, grouped like the navigation. Pages that failed
+/// to render under `--keep-going` have no summary.
+fn landing_cards(
+    sections: &[Section],
+    all_pages: &[PageInfo],
+    sources: &[SourceFile],
+    summaries: &BTreeMap<String, String>,
+) -> Vec<CardSection> {
+    let sources_by_path: BTreeMap<&str, &SourceFile> = all_pages
+        .iter()
+        .zip(sources)
+        .map(|(page, source)| (page.path.as_str(), source))
+        .collect();
+    let card = |page: &PageInfo| {
+        let source = sources_by_path.get(page.path.as_str());
+        let field = |key: &str| {
+            source
+                .and_then(|source| source.frontmatter.get(key))
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        Card {
+            title: page.title.clone(),
+            path: page.path.clone(),
+            summary: summaries.get(&page.path).cloned().unwrap_or_default(),
+            icon: field("icon"),
+            image: field("image").map(|image| match source {
+                Some(source) if !is_external_url(&image) && !image.starts_with('/') => {
+                    let dir = source.html_path.parent().unwrap_or(Path::new(""));
+                    format!("/{}", dir.join(image).display())
+                }
+                _ => image,
+            }),
+        }
+    };
+
+    sections
+        .iter()
+        .map(|section| CardSection {
+            title: section.title.clone(),
+            pages: section.pages.iter().map(card).collect(),
+        })
+        .collect()
+}
+
+/// A page's URL from the book root. Under a trailing-slash policy an
+/// `index.html` page is addressed by its directory: `/guide/` or `/guide`.
+fn page_url(html_path: &Path, trailing_slash: Option<TrailingSlash>) -> String {
+    let path = format!("/{}", html_path.display());
+    match (trailing_slash, path.strip_suffix("index.html")) {
+        (Some(policy), Some(dir)) if dir.ends_with('/') => directory_url(dir, policy),
+        _ => path,
+    }
+}
+
+/// A URL path normalized to start and end with a slash: `/`, or `/docs/`
+/// for `docs`
+pub(crate) fn url_root(path: &str) -> String {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if segments.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}/", segments.join("/"))
+    }
+}
+
+/// Moves a page's root-relative `href` and `src` URLs, and a redirect stub's
+/// target, under `root` for a book served below the site root
+fn prefix_root_urls(html: &str, root: &str) -> String {
+    let prefix = |url: &str| {
+        let path = url
+            .strip_prefix('/')
+            .filter(|path| !path.starts_with('/'))?;
+        Some(format!("{root}{path}"))
+    };
+    let html = rewrite_attribute(html, "href", prefix);
+    let html = rewrite_attribute(&html, "src", prefix);
+    rewrite_attribute(&html, "content", |content| {
+        let target = content.strip_prefix("0; URL=")?;
+        Some(format!("0; URL={}", prefix(target)?))
+    })
+}
+
+/// Output path of a page relative to the book root. A root `README.md`
+/// becomes `index.html`, as in mdBook, unless the book also has an `index.md`.
+/// With `pretty_urls`, pages other than `index` and `README` get a directory
+/// of their own: `guide/setup.md` becomes `guide/setup/index.html`.
+fn page_html_path(rel_path: &Path, has_root_index: bool, pretty_urls: bool) -> PathBuf {
+    let is_readme = rel_path
+        .file_stem()
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("readme"));
+    let is_root_readme = is_readme && rel_path.parent() == Some(Path::new(""));
+
+    if is_root_readme && !has_root_index {
+        PathBuf::from("index.html")
+    } else if pretty_urls && !is_readme && rel_path.file_stem() != Some("index".as_ref()) {
+        rel_path.with_extension("").join("index.html")
+    } else {
+        rel_path.with_extension("html")
+    }
+}
+
+/// The text of the first ATX heading at `level` (`## ` for 2)
+fn extract_title(markdown: &str, level: u8) -> Option<String> {
+    let prefix = format!("{} ", "#".repeat(usize::from(level)));
+    markdown
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(|title| title.trim().to_string())
+}
+
+/// The markdown a page is rendered from: its source, minus a leading title
+/// heading when `output.html.strip-title-heading` leaves the title to the template.
+///
+/// Only a heading that is the page's first block and matches `title` is removed.
+fn page_markdown<'a>(source: &'a SourceFile, title: &str, config: &BookConfig) -> Cow<'a, str> {
+    let content = source.content.as_str();
+    if !config.output.html.strip_title_heading {
+        return Cow::Borrowed(content);
+    }
+    let Ok(Node::Root(root)) = to_mdast(content, &build_markdown_options(config).parse) else {
+        return Cow::Borrowed(content);
+    };
+
+    let first_block = root
+        .children
+        .iter()
+        .find(|node| !matches!(node, Node::Yaml(_) | Node::Toml(_)));
+    let Some(Node::Heading(heading)) = first_block else {
+        return Cow::Borrowed(content);
+    };
+    let Some(position) = &heading.position else {
+        return Cow::Borrowed(content);
+    };
+
+    let level = config.markdown.title_heading_level;
+    let heading_source = &content[position.start.offset..position.end.offset];
+    if heading.depth != level || extract_title(heading_source, level).as_deref() != Some(title) {
+        return Cow::Borrowed(content);
+    }
+
+    Cow::Owned(format!(
+        "{}{}",
+        &content[..position.start.offset],
+        &content[position.end.offset..]
+    ))
+}
+
+/// Replaces `{{#toc}}` directives with a linked list of every page in reading
+/// order, grouped by section; `{{#toc section="guide"}}` lists one section
+fn expand_toc<'a>(markdown: Cow<'a, str>, sections: &[Section]) -> Result<Cow<'a, str>> {
+    const DIRECTIVE: &str = "{{#toc";
+    if !markdown.contains(DIRECTIVE) {
+        return Ok(markdown);
+    }
+
+    let mut expanded = String::with_capacity(markdown.len());
+    let mut rest = &*markdown;
+    while let Some(start) = rest.find(DIRECTIVE) {
+        let args_start = start + DIRECTIVE.len();
+        let Some(args_len) = rest[args_start..].find("}}") else {
+            anyhow::bail!("Unterminated {{{{#toc}}}} directive");
+        };
+        let args = rest[args_start..args_start + args_len].trim();
+
+        let mut list = String::new();
+        if args.is_empty() {
+            for section in sections {
+                let indent = if let Some(title) = &section.title {
+                    list.push_str(&format!("- {}\n", escape_markdown(title)));
+                    "  "
+                } else {
+                    ""
+                };
+                push_toc_pages(&mut list, &section.pages, indent);
+            }
+        } else {
+            let name = args
+                .strip_prefix("section=")
+                .and_then(|value| value.strip_prefix('"')?.strip_suffix('"'))
+                .with_context(|| format!("Invalid {{{{#toc}}}} arguments `{args}`"))?;
+            let section = sections
+                .iter()
+                .find(|section| section.title.as_deref() == Some(name))
+                .with_context(|| format!("Unknown section `{name}` in {{{{#toc}}}}"))?;
+            push_toc_pages(&mut list, &section.pages, "");
+        }
+
+        // The list must start its own block wherever the directive sits
+        expanded.push_str(&rest[..start]);
+        expanded.push_str("\n\n");
+        expanded.push_str(&list);
+        expanded.push('\n');
+        rest = &rest[args_start + args_len + 2..];
+    }
+    expanded.push_str(rest);
+
+    Ok(Cow::Owned(expanded))
+}
+
+fn push_toc_pages(list: &mut String, pages: &[PageInfo], indent: &str) {
+    for page in pages {
+        list.push_str(&format!(
+            "{indent}- [{}](<{}>)\n",
+            escape_markdown(&page.title),
+            page.path
+        ));
+    }
+}
+
+/// Backslash-escapes markdown punctuation so `text` renders literally
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn copy_static_assets(
+    output_dir: &str,
+    templates_dir: &str,
+    config: &BookConfig,
+    site_root: &str,
+    out: OutputFs,
+) -> Result<()> {
+    // Create components directory
+    out.create_dir_all(format!("{}/components", output_dir))?;
+
+    // Copy CSS directory
+    let css_source = format!("{}/css", templates_dir);
+    let css_dest = format!("{}/css/", output_dir);
+    out.create_dir_all(&css_dest)?;
+    if std::path::Path::new(&css_source).exists() {
+        for entry in WalkDir::new(&css_source) {
+            let entry = entry?;
+            let dest_path = css_dest.clone()
+                + entry
+                    .path()
+                    .strip_prefix(&css_source)?
+                    .to_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Invalid UTF-8 in CSS path: {:?}", entry.path())
+                    })?;
+            if entry.file_type().is_file() {
+                out.copy(entry.path(), dest_path)?;
+            }
+        }
+    }
+
+    // Copy JS directory
+    let js_source = format!("{}/js", templates_dir);
+    let js_dest = format!("{}/js/", output_dir);
+    out.create_dir_all(&js_dest)?;
+    if std::path::Path::new(&js_source).exists() {
+        for entry in WalkDir::new(&js_source) {
+            let entry = entry?;
+            let dest_path = js_dest.clone()
+                + entry
+                    .path()
+                    .strip_prefix(&js_source)?
+                    .to_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Invalid UTF-8 in JS path: {:?}", entry.path())
+                    })?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if entry.file_name() == "pagefind-search.js" {
+                let script = fs::read_to_string(entry.path())?;
+                out.write(
+                    dest_path,
+                    search_client_js(&script, site_root, &config.output.html.search.index_dir),
+                )?;
+            } else {
+                out.copy(entry.path(), dest_path)?;
+            }
+        }
+    }
+    out.write(
+        format!("{}/js/search-config.json", output_dir),
+        search_client_config(&config.output.html.search)?,
+    )
+    .context("Failed to write search config")?;
+    // Copy img directory from templates
+    let img_source = format!("{}/img", templates_dir);
+    let img_dest = format!("{}/img/", output_dir);
+    out.create_dir_all(&img_dest)?;
+    if std::path::Path::new(&img_source).exists() {
+        for entry in WalkDir::new(&img_source) {
+            let entry = entry?;
+            let dest_path = img_dest.clone()
+                + entry
+                    .path()
+                    .strip_prefix(&img_source)?
+                    .to_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Invalid UTF-8 in image path: {:?}", entry.path())
+                    })?;
+            if entry.file_type().is_file() {
+                out.copy(entry.path(), dest_path)
+                    .context(format!("Failed to copy img file: {:?}", entry.path()))?;
+            }
+        }
+    }
+
+    out.write(
+        format!("{}/components/doc-toc.js", output_dir),
+        include_str!("templates/components/doc-toc.js"),
+    )
+    .context("Failed to write TOC component")?;
+
+    out.write(
+        format!("{}/components/simple-block.js", output_dir),
+        include_str!("templates/components/simple-block.js"),
+    )
+    .context("Failed to write Simple Block component")?;
+
+    out.write(
+        format!("{}/components/search-modal.js", output_dir),
+        include_str!("templates/components/search-modal.js"),
+    )
+    .context("Failed to write Search Modal component")?;
+
+    out.write(
+        format!("{}/components/page-nav.js", output_dir),
+        include_str!("templates/components/page-nav.js"),
+    )
+    .context("Failed to write Page Nav component")?;
+
+    Ok(())
+}
+
+/// Points the search client's default bundle path at `search.index-dir`
+/// under `site_root`, where the build writes the pagefind index
+fn search_client_js(script: &str, site_root: &str, index_dir: &str) -> String {
+    let index_dir = index_dir.trim_matches('/');
+    script.replace(
+        "bundlePath: '/pagefind/'",
+        &format!("bundlePath: '{site_root}{index_dir}/'"),
+    )
+}
+
+/// The `[output.html.search]` settings the search client applies, keyed by
+/// its option names
+fn search_client_config(search: &SearchConfig) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&serde_json::json!({
+        "maxResults": search.limit_results,
+        "useBooleanAnd": search.use_boolean_and,
+        "excerptLength": search.excerpt_length,
+    }))?)
+}
+
+/// Common fence shorthands and the language syntect knows them by. The
+/// default syntax set has no TypeScript, so it highlights as JavaScript.
+#[cfg(feature = "syntax-highlighting")]
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "javascript"),
+    ("tsx", "javascript"),
+    ("typescript", "javascript"),
+    ("yml", "yaml"),
+    ("py", "python"),
+    ("python3", "python"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("zsh", "bash"),
+    ("golang", "go"),
+    ("c++", "cpp"),
+];
+
+/// Maps a fence language through `syntax.aliases`, then the built-in
+/// [`LANGUAGE_ALIASES`]; anything else is returned unchanged
+#[cfg(feature = "syntax-highlighting")]
+fn resolve_language_alias<'a>(language: &'a str, aliases: &'a BTreeMap<String, String>) -> &'a str {
+    aliases
+        .get(language)
+        .map(String::as_str)
+        .or_else(|| {
+            LANGUAGE_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == language)
+                .map(|(_, name)| *name)
+        })
+        .unwrap_or(language)
+}
+
+/// Fence languages that intentionally render as plain text
+#[cfg(feature = "syntax-highlighting")]
+const PLAIN_TEXT_LANGUAGES: &[&str] = &["text", "plain", "plaintext", "txt"];
+
+/// Returns true if `process_code_block` can highlight (or deliberately
+/// special-cases) the given fence language
+#[cfg(feature = "syntax-highlighting")]
+fn is_known_language(language: &str, ss: &SyntaxSet) -> bool {
+    matches!(language, "mermaid" | "diff" | "console" | "shell-session")
+        || PLAIN_TEXT_LANGUAGES.contains(&language)
+        || ss.find_syntax_by_extension(language).is_some()
+        || ss.find_syntax_by_name(language).is_some()
+        || ss.find_syntax_by_token(language).is_some()
+}
+
+/// Renders code blocks of one fence language in place of the built-in
+/// highlighting, e.g. to turn `graphviz` source into an SVG
+#[cfg(feature = "syntax-highlighting")]
+pub trait CodeBlockRenderer: Send + Sync {
+    /// Returns the block's HTML, or `None` to fall back to syntax highlighting
+    fn render(&self, code: &str, info: &FenceInfo) -> Option<Result<String>>;
+}
+
+/// The [`CodeBlockRenderer`]s a build uses, by fence language. Set on the
+/// `syntax.renderers` of the config a book is built with.
+#[cfg(feature = "syntax-highlighting")]
+#[derive(Clone, Default)]
+pub struct CodeBlockRenderers(BTreeMap<String, Arc<dyn CodeBlockRenderer>>);
+
+#[cfg(feature = "syntax-highlighting")]
+impl CodeBlockRenderers {
+    /// Registers `renderer` for code blocks whose fence language is
+    /// `language`, replacing any renderer registered for it before
+    pub fn register(
+        &mut self,
+        language: impl Into<String>,
+        renderer: impl CodeBlockRenderer + 'static,
+    ) {
+        self.0.insert(language.into(), Arc::new(renderer));
+    }
+
+    fn get(&self, language: &str) -> Option<&dyn CodeBlockRenderer> {
+        self.0.get(language).map(Arc::as_ref)
+    }
+}
+
+#[cfg(feature = "syntax-highlighting")]
+impl std::fmt::Debug for CodeBlockRenderers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+#[cfg(feature = "syntax-highlighting")]
+/// Renders a code block, trying a registered [`CodeBlockRenderer`] for its
+/// language first. Lines numbered in `info.highlight` (from 1) get the
+/// `highlighted` class; numbers past the end are ignored.
+fn process_code_block(
+    code: &str,
+    info: &FenceInfo,
+    ss: &SyntaxSet,
+    syntax: &SyntaxConfig,
+) -> Result<String> {
+    let language = info.language.as_deref();
+    let style = syntax.style;
+    if let Some(renderer) = language.and_then(|language| syntax.renderers.get(language)) {
+        if let Some(rendered) = renderer.render(code, info) {
+            return rendered;
+        }
+    }
+
+    let highlight = &info.highlight;
+    let syntax = match language {
+        Some("rust") => {
+            let syntax = ss
+                .find_syntax_by_extension("rs")
+                .ok_or_else(|| anyhow::anyhow!("Rust syntax not found"))?;
+            // Check if code block has editable tag
+            if code.contains("<--editable-->") {
+                let code_with_comment = format!("{}\n// <--editable-->", code);
+                process_rust_code(&code_with_comment, syntax, highlight, ss, style)?
+            } else {
+                process_rust_code(code, syntax, highlight, ss, style)?
+            }
+        }
+        Some("mermaid") => {
+            // For markdown, preserve the content exactly as is
+            format!(
+                "<pre class=\"code\"><code class=\"language-mermaid\">{}</code></pre>",
+                html_escape::encode_text(code)
+            )
+        }
+        Some("diff") => process_diff_code(code),
+        Some("console" | "shell-session") => process_console_code(code),
+        Some("sh" | "shell" | "bash") if code.lines().any(|line| line.starts_with("$ ")) => {
+            process_console_code(code)
+        }
+        Some(lang) => {
+            let syntax = ss
+                .find_syntax_by_extension(lang)
+                .or_else(|| ss.find_syntax_by_name(lang))
+                .or_else(|| ss.find_syntax_by_token(lang))
+                .or_else(|| Some(ss.find_syntax_plain_text()))
+                .ok_or_else(|| anyhow::anyhow!("Syntax not found for language: {:?}", lang))?;
+            process_generic_code(code, syntax, highlight, ss, style)?
+        }
+        None => {
+            let syntax = ss.find_syntax_plain_text();
+            process_generic_code(code, syntax, highlight, ss, style)?
+        }
+    };
+    Ok(syntax)
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn process_rust_code(
+    code: &str,
+    syntax: &syntect::parsing::SyntaxReference,
+    highlight: &[RangeInclusive<usize>],
+    ss: &SyntaxSet,
+    style: HighlightStyle,
+) -> Result<String> {
+    let html = wrap_lines(&highlight_code(code, syntax, ss, style)?, highlight);
+    Ok(format!(
+        "<pre class=\"code rust\"><code>{}</code></pre>",
+        html
+    ))
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn process_generic_code(
+    code: &str,
+    syntax: &syntect::parsing::SyntaxReference,
+    highlight: &[RangeInclusive<usize>],
+    ss: &SyntaxSet,
+    style: HighlightStyle,
+) -> Result<String> {
+    let html = wrap_lines(&highlight_code(code, syntax, ss, style)?, highlight);
+    Ok(format!("<pre class=\"code\"><code>{}</code></pre>", html))
+}
+
+/// The theme behind both `css/syntax.css` and inline-styled code
+#[cfg(feature = "syntax-highlighting")]
+fn highlight_theme() -> &'static syntect::highlighting::Theme {
+    static THEME: OnceLock<syntect::highlighting::Theme> = OnceLock::new();
+    // TODO: Make this configurable
+    THEME.get_or_init(|| ThemeSet::load_defaults().themes["Solarized (light)"].clone())
+}
+
+/// Highlights `code` as spans carrying either CSS classes or inline styles
+#[cfg(feature = "syntax-highlighting")]
+fn highlight_code(
+    code: &str,
+    syntax: &syntect::parsing::SyntaxReference,
+    ss: &SyntaxSet,
+    style: HighlightStyle,
+) -> Result<String> {
+    match style {
+        HighlightStyle::Classed => {
+            let mut html_generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, ss, ClassStyle::Spaced);
+            for line in LinesWithEndings::from(code) {
+                html_generator
+                    .parse_html_for_line_which_includes_newline(line)
+                    .map_err(|e| anyhow::anyhow!("HTML generation error: {:?}", e))?;
+            }
+            Ok(html_generator.finalize())
+        }
+        HighlightStyle::Inline => {
+            let mut highlighter = HighlightLines::new(syntax, highlight_theme());
+            let mut html = String::with_capacity(code.len() * 4);
+            for line in LinesWithEndings::from(code) {
+                let regions = highlighter
+                    .highlight_line(line, ss)
+                    .map_err(|e| anyhow::anyhow!("HTML generation error: {:?}", e))?;
+                html.push_str(
+                    &styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                        .map_err(|e| anyhow::anyhow!("HTML generation error: {:?}", e))?,
+                );
+            }
+            Ok(html)
+        }
+    }
+}
+
+/// Marks added and removed lines of a unified diff with `diff-add` and
+/// `diff-del`; `+++`/`---` file headers and context lines stay plain
+#[cfg(feature = "syntax-highlighting")]
+fn process_diff_code(code: &str) -> String {
+    let mut html = String::with_capacity(code.len() * 2);
+    for line in code.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let class = if text.starts_with("+++") || text.starts_with("---") {
+            None
+        } else if text.starts_with('+') {
+            Some("diff-add")
+        } else if text.starts_with('-') {
+            Some("diff-del")
+        } else {
+            None
+        };
+
+        let text = html_escape::encode_text(text);
+        match class {
+            Some(class) => html.push_str(&format!("<span class=\"{class}\">{text}</span>")),
+            None => html.push_str(&text),
+        }
+        html.push_str(newline);
+    }
+    format!("<pre class=\"code diff\"><code class=\"language-diff\">{html}</code></pre>")
+}
+
+/// Prompt markers that start a command line in a terminal session
+#[cfg(feature = "syntax-highlighting")]
+const CONSOLE_PROMPTS: &[&str] = &["$ ", "# ", "> "];
+
+/// Renders a terminal session, tagging `command` and `output` lines. The
+/// `<pre>` carries the commands alone, without prompts, as its `data-copy`
+/// payload for the copy button.
+#[cfg(feature = "syntax-highlighting")]
+fn process_console_code(code: &str) -> String {
+    let mut html = String::with_capacity(code.len() * 2);
+    let mut commands = Vec::new();
+    for line in code.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        match CONSOLE_PROMPTS
+            .iter()
+            .find(|prompt| text.starts_with(*prompt))
+        {
+            Some(prompt) => {
+                let command = &text[prompt.len()..];
+                commands.push(command);
+                html.push_str(&format!(
+                    "<span class=\"command\"><span class=\"prompt\">{}</span>{}</span>",
+                    html_escape::encode_text(prompt),
+                    html_escape::encode_text(command)
+                ));
+            }
+            None if text.is_empty() => {}
+            None => html.push_str(&format!(
+                "<span class=\"output\">{}</span>",
+                html_escape::encode_text(text)
+            )),
+        }
+        html.push_str(newline);
+    }
+    format!(
+        "<pre class=\"code console\" data-copy=\"{}\"><code class=\"language-console\">{html}</code></pre>",
+        html_escape::encode_double_quoted_attribute(&commands.join("\n"))
+    )
+}
+
+/// Wraps each line of highlighted code in `<span class="line">`, adding the
+/// `highlighted` class to the line numbers in `highlight`. Spans that run
+/// across lines are closed and reopened so each line is balanced. Code with
+/// nothing to highlight is returned unchanged.
+#[cfg(feature = "syntax-highlighting")]
+fn wrap_lines(html: &str, highlight: &[RangeInclusive<usize>]) -> String {
+    if highlight.is_empty() {
+        return html.to_string();
+    }
+
+    let mut result = String::with_capacity(html.len() * 2);
+    let mut open: Vec<&str> = Vec::new();
+    let mut lines = html.split_inclusive('\n').peekable();
+    let mut number = 0;
+    while let Some(line) = lines.next() {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        // The generator closes its spans after the final newline; each line
+        // already closed its own
+        if lines.peek().is_none()
+            && newline.is_empty()
+            && text.trim_start_matches("</span>").is_empty()
+        {
+            break;
+        }
+        number += 1;
+
+        let class = if highlight.iter().any(|range| range.contains(&number)) {
+            "line highlighted"
+        } else {
+            "line"
+        };
+        result.push_str(&format!("<span class=\"{class}\">"));
+        result.extend(open.iter().copied());
+        result.push_str(text);
+
+        let mut rest = text;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..]
+                .find('>')
+                .map_or(rest.len(), |end| start + end + 1);
+            let tag = &rest[start..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else {
+                open.push(tag);
+            }
+            rest = &rest[end..];
+        }
+
+        result.push_str(&"</span>".repeat(open.len() + 1));
+        result.push_str(newline);
+    }
+    result
+}
+
+/// Applies the HTML-level rewrites that run on every rendered page. Links
+/// are resolved from `page`, one of the book's `html_paths`.
+fn postprocess_html(
+    html: &str,
+    page: &Path,
+    html_paths: &BTreeSet<PathBuf>,
+    config: &BookConfig,
+) -> String {
+    let extensions = &config.markdown.extensions;
+    let mut html = convert_md_links_to_html(html, page, html_paths, extensions);
+
+    let output = &config.output.html;
+    if output.external_links_new_tab || output.external_link_icon {
+        html = mark_external_links(
+            &html,
+            output.external_links_new_tab,
+            output.external_link_icon,
+        );
+    }
+
+    html
+}
+
+/// Rewrites `<a>` tags whose `href` is an absolute `http(s)` URL: `new_tab`
+/// opens them in a new tab without handing the new page a reference back, and
+/// `icon` adds the `external-link` class styled with an indicator. Relative
+/// and anchor links, and tags already setting a `target`, are left alone.
+fn mark_external_links(html: &str, new_tab: bool, icon: bool) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(pos) = rest.find("<a ") {
+        let Some(len) = rest[pos..].find('>') else {
+            break;
+        };
+        let tag = &rest[pos..pos + len];
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + len..];
+
+        let external = attribute_value(tag, "href")
+            .is_some_and(|href| href.starts_with("https://") || href.starts_with("http://"));
+        if !external || attribute_value(tag, "target").is_some() {
+            result.push_str(tag);
+            continue;
+        }
+
+        let mut tag = tag.to_string();
+        if icon {
+            match tag.find(" class=\"") {
+                Some(class) => tag.insert_str(class + " class=\"".len(), "external-link "),
+                None => tag.push_str(" class=\"external-link\""),
+            }
+        }
+        if new_tab {
+            tag.push_str(" target=\"_blank\" rel=\"noopener noreferrer\"");
+        }
+        result.push_str(&tag);
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Stands in for the start of a markdown table in the source, like
+/// [`HEADING_NUMBER_MARKER`], so [`wrap_tables`] can tell it from tables
+/// written as raw HTML
+const TABLE_MARKER: char = '\u{FDD1}';
+
+/// Marks each table node of the mdast with [`TABLE_MARKER`] at the start of
+/// its first non-empty cell. A table without any content is left unmarked.
+fn mark_tables(content: &str, parse_options: &markdown::ParseOptions) -> Result<String> {
+    fn collect_tables(node: &Node, starts: &mut Vec<usize>) {
+        if let Node::Table(table) = node {
+            let start = table
+                .children
+                .iter()
+                .flat_map(|row| row.children().into_iter().flatten())
+                .find_map(|cell| cell.children()?.first()?.position())
+                .map(|position| position.start.offset);
+            starts.extend(start);
+            return;
+        }
+        for child in node.children().into_iter().flatten() {
+            collect_tables(child, starts);
+        }
+    }
+
+    let ast = to_mdast(content, parse_options)
+        .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+    let mut starts = Vec::new();
+    collect_tables(&ast, &mut starts);
+
+    let mut marked = content.to_string();
+    for start in starts.into_iter().rev() {
+        marked.insert(start, TABLE_MARKER);
+    }
+    Ok(marked)
+}
+
+/// Wraps each table marked by [`mark_tables`] in `<div class="table-wrapper">`
+/// so wide tables scroll instead of overflowing. Tables written as raw HTML
+/// carry no marker and are left alone, and a wrapped table ends at the
+/// `</table>` balancing its opening tag, whatever tables its cells hold.
+fn wrap_tables(html: &str) -> String {
+    /// The length of the table `html` starts with, through its closing tag
+    fn table_len(html: &str) -> Option<usize> {
+        let mut depth = 0;
+        let mut pos = 0;
+        loop {
+            let close = pos + html[pos..].find("</table>")?;
+            match html[pos..close].find("<table") {
+                Some(open) => {
+                    depth += 1;
+                    pos += open + "<table".len();
+                }
+                None => {
+                    depth -= 1;
+                    pos = close + "</table>".len();
+                    if depth == 0 {
+                        return Some(pos);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(marker) = rest.find(TABLE_MARKER) {
+        let table = rest[..marker]
+            .rfind("<table>")
+            .and_then(|start| Some((start, table_len(&rest[start..])?)));
+        let Some((start, len)) = table else {
+            result.push_str(&rest[..marker]);
+            rest = &rest[marker + TABLE_MARKER.len_utf8()..];
+            continue;
+        };
+
+        result.push_str(&rest[..start]);
+        result.push_str("<div class=\"table-wrapper\">");
+        result.push_str(&rest[start..start + len].replace(TABLE_MARKER, ""));
+        result.push_str("</div>");
+        rest = &rest[start + len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Stands in for a heading's section number in the markdown source until
+/// the page is rendered. A noncharacter, so markdown-rs passes it through as
+/// text and no page would contain it.
+const HEADING_NUMBER_MARKER: char = '\u{FDD0}';
+
+/// Marks the start of each heading up to `max_level` with
+/// [`HEADING_NUMBER_MARKER`], returning the marked source and the
+/// hierarchical section numbers (1, 1.1, 1.2, 2, ...) the markers stand for.
+/// Numbers come from the mdast heading nodes, so headings in raw HTML or code
+/// are left alone. Leading unused levels are skipped, so a page starting at
+/// `##` is numbered from 1 rather than 0.1.
+fn mark_heading_numbers(
+    content: &str,
+    parse_options: &markdown::ParseOptions,
+    max_level: u8,
+) -> Result<(String, Vec<String>)> {
+    fn collect_headings(node: &Node, headings: &mut Vec<(u8, Option<usize>)>) {
+        if let Node::Heading(heading) = node {
+            let start = heading
+                .children
+                .first()
+                .and_then(Node::position)
+                .map(|position| position.start.offset);
+            headings.push((heading.depth, start));
+        }
+        for child in node.children().into_iter().flatten() {
+            collect_headings(child, headings);
+        }
+    }
+
+    let ast = to_mdast(content, parse_options)
+        .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+    let mut headings = Vec::new();
+    collect_headings(&ast, &mut headings);
+
+    let mut counters = [0usize; 6];
+    let mut marks = Vec::new();
+    for (depth, start) in headings {
+        let level = usize::from(depth.clamp(1, 6));
+        counters[level - 1] += 1;
+        counters[level..].iter_mut().for_each(|c| *c = 0);
+
+        if let Some(start) = start.filter(|_| depth <= max_level) {
+            let number = counters[..level]
+                .iter()
+                .skip_while(|c| **c == 0)
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(".");
+            marks.push((start, number));
+        }
+    }
+
+    let mut marked = content.to_string();
+    for (start, _) in marks.iter().rev() {
+        marked.insert(*start, HEADING_NUMBER_MARKER);
+    }
+    Ok((
+        marked,
+        marks.into_iter().map(|(_, number)| number).collect(),
+    ))
+}
+
+/// Replaces the markers left by [`mark_heading_numbers`] in rendered HTML
+/// with their section numbers
+fn insert_heading_numbers(html: &str, numbers: &[String]) -> String {
+    let mut parts = html.split(HEADING_NUMBER_MARKER);
+    let mut result = parts.next().unwrap_or_default().to_string();
+    let mut numbers = numbers.iter();
+    for part in parts {
+        if let Some(number) = numbers.next() {
+            result.push_str(&format!("<span class=\"heading-number\">{number}</span> "));
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// Converts internal links to markdown sources into links to the pages
+/// built from them, so `page.md` becomes `page.html` and a root `README.md`
+/// becomes `index.html`, for every extension in `markdown.extensions`.
+/// Targets are resolved from `page`'s directory and mapped with
+/// [`page_html_path`], following the root `README` to wherever `html_paths`
+/// says it was built. External links (http://, https://, mailto:, etc.) are
+/// not modified.
+fn convert_md_links_to_html(
+    html: &str,
+    page: &Path,
+    html_paths: &BTreeSet<PathBuf>,
+    extensions: &[String],
+) -> String {
+    let page_dir = page.parent().unwrap_or(Path::new(""));
+    let convert = |url: &str| {
+        if is_external_url(url) {
+            return None;
+        }
+        let (target, suffix) = url.split_at(url.find(['#', '?']).unwrap_or(url.len()));
+        let extension = Path::new(target).extension()?.to_str()?;
+        if !extensions.iter().any(|ext| ext == extension) {
+            return None;
+        }
+        let resolved = resolve_link(page_dir, target);
+        let built = page_html_path(&resolved, true, false);
+        let built = if html_paths.contains(&built) {
+            built
+        } else {
+            page_html_path(&resolved, false, false)
+        };
+        let file_name = built.file_name()?.to_str()?;
+        let dir = &target[..target.rfind('/').map_or(0, |slash| slash + 1)];
+        Some(format!("{dir}{file_name}{suffix}"))
+    };
+
+    let mut result = html.to_string();
+    for quote in ['"', '\''] {
+        let needle = format!("href={quote}");
+        let mut converted = String::with_capacity(result.len());
+        let mut rest = result.as_str();
+        while let Some(pos) = rest.find(&needle) {
+            let url_start = pos + needle.len();
+            let Some(url_len) = rest[url_start..].find(quote) else {
+                break;
+            };
+            let url = &rest[url_start..url_start + url_len];
+            converted.push_str(&rest[..url_start]);
+            converted.push_str(&convert(url).unwrap_or_else(|| url.to_string()));
+            rest = &rest[url_start + url_len..];
+        }
+        converted.push_str(rest);
+        result = converted;
+    }
+    result
+}
+
+#[cfg(feature = "syntax-highlighting")]
+fn process_markdown_with_highlighting(
+    content: &str,
+    ss: &SyntaxSet,
+    config: &BookConfig,
+    file: &Path,
+    warnings: &mut BTreeSet<BuildWarning>,
+) -> Result<String> {
+    let options = build_markdown_options(config);
+
+    let smartened;
+    let content = if config.markdown.smart_punctuation {
+        smartened = smart_punctuation(content, &options.parse)?;
+        smartened.as_str()
+    } else {
+        content
+    };
+
+    let numbering = &config.output.html.number_headings;
+    let (numbered, numbers);
+    let content = if numbering.enabled {
+        (numbered, numbers) = mark_heading_numbers(content, &options.parse, numbering.max_level)?;
+        numbered.as_str()
+    } else {
+        numbers = Vec::new();
+        content
+    };
+
+    let tables_marked;
+    let content = if options.parse.constructs.gfm_table {
+        tables_marked = mark_tables(content, &options.parse)?;
+        tables_marked.as_str()
+    } else {
+        content
+    };
+
+    let ast = to_mdast(content, &options.parse)
+        .map_err(|e| anyhow::anyhow!("Markdown parsing error: {:?}", e))?;
+
+    fn collect_code_blocks<'a>(node: &'a Node, blocks: &mut Vec<&'a markdown::mdast::Code>) {
+        match node {
+            Node::Code(code) => blocks.push(code),
+            _ => {
+                for child in node.children().into_iter().flatten() {
+                    collect_code_blocks(child, blocks);
+                }
+            }
+        }
+    }
+
+    let mut code_blocks = Vec::new();
+    collect_code_blocks(&ast, &mut code_blocks);
+
+    // Render the document in one pass, so footnotes and link definitions
+    // resolve across code blocks, then swap in the highlighted blocks
+    let mut html = to_html_with_options(content, &options)
+        .map_err(|e| anyhow::anyhow!("Markdown conversion error: {:?}", e))?;
+
+    for code in code_blocks {
+        let mut info = FenceInfo::parse(code.lang.as_deref(), code.meta.as_deref());
+        if info.language.is_none() {
+            info.language = config.markdown.default_code_language.clone();
+        }
+        if let Some(lang) = info.language.take() {
+            info.language = Some(resolve_language_alias(&lang, &config.syntax.aliases).to_string());
+        }
+        if let Some(lang) = info.language.as_deref() {
+            if !is_known_language(lang, ss) && config.syntax.renderers.get(lang).is_none() {
+                warnings.insert(BuildWarning::UnknownLanguage {
+                    file: file.to_path_buf(),
+                    language: lang.to_string(),
+                });
+            }
+        }
+        let lines = code.value.lines().count();
+        let past_end = info
+            .highlight
+            .iter()
+            .filter(|range| *range.end() > lines)
+            .map(|range| (*range.start()).max(lines + 1))
+            .min();
+        if let Some(line) = past_end {
+            warnings.insert(BuildWarning::HighlightLineOutOfRange {
+                file: file.to_path_buf(),
+                line,
+                lines,
+            });
+        }
+
+        // Blocks already swapped no longer match, so the first hit is this one
+        let plain = plain_code_block_html(code);
+        if let Some(start) = html.find(&plain) {
+            let mut highlighted = process_code_block(&code.value, &info, ss, &config.syntax)?;
+            if let Some(title) = info.attributes.get("title") {
+                highlighted = format!(
+                    "<figure class=\"code-block\"><figcaption class=\"code-title\">{}</figcaption>{highlighted}</figure>",
+                    html_escape::encode_text(title)
+                );
+            }
+            html.replace_range(start..start + plain.len(), &highlighted);
+        }
+    }
+
+    Ok(wrap_tables(&insert_heading_numbers(&html, &numbers)))
+}
+
+#[cfg(feature = "syntax-highlighting")]
+/// A parsed code fence info string, e.g. ```` ```rust,editable title="main.rs" ````
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FenceInfo {
+    pub language: Option<String>,
+    /// `key=value` pairs; values may be double-quoted to include spaces
+    pub attributes: BTreeMap<String, String>,
+    /// Bare words such as `editable` or `ignore`
+    pub flags: BTreeSet<String>,
+    /// Line ranges from a `{3-5,8}` spec, counting from 1; a single line is
+    /// a one-line range. Kept as ranges so a huge end costs nothing.
+    pub highlight: Vec<RangeInclusive<usize>>,
+}
+
+#[cfg(feature = "syntax-highlighting")]
+impl FenceInfo {
+    /// Parses the info string as markdown-rs splits it: `lang` is the first
+    /// word and `meta` the rest. Words may be separated by commas or spaces.
+    pub fn parse(lang: Option<&str>, meta: Option<&str>) -> Self {
+        let info = [lang, meta]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut tokens = Vec::new();
+        let mut token = String::new();
+        let mut in_quotes = false;
+        let mut in_braces = false;
+        for c in info.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                '{' if !in_quotes => {
+                    in_braces = true;
+                    token.push(c);
+                }
+                '}' if !in_quotes => {
+                    in_braces = false;
+                    token.push(c);
+                }
+                ',' | ' ' | '\t' if !in_quotes && !in_braces => {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                _ => token.push(c),
+            }
+        }
+        tokens.push(token);
+
+        let mut parsed = Self::default();
+        for (index, token) in tokens.into_iter().filter(|t| !t.is_empty()).enumerate() {
+            if let Some(spec) = token.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+                parsed.highlight.extend(parse_line_ranges(spec));
+            } else if let Some((key, value)) = token.split_once('=') {
+                parsed.attributes.insert(key.to_string(), value.to_string());
+            } else if index == 0 {
+                parsed.language = Some(token);
+            } else {
+                parsed.flags.insert(token);
+            }
+        }
+        parsed
+    }
+
+    /// Whether the `{...}` spec highlights `line`, counting from 1
+    pub fn highlights(&self, line: usize) -> bool {
+        self.highlight.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// Parses a comma-separated list of line numbers and ranges like `3-5,8`,
+/// skipping anything malformed or empty
+#[cfg(feature = "syntax-highlighting")]
+fn parse_line_ranges(spec: &str) -> Vec<RangeInclusive<usize>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter_map(|part| {
+            let (start, end) = part.split_once('-').unwrap_or((part, part));
+            let start = start.trim().parse::<usize>().ok()?.max(1);
+            let end = end.trim().parse::<usize>().ok()?;
+            (start <= end).then_some(start..=end)
+        })
+        .collect()
+}
+
+/// The HTML markdown-rs emits for a code block without highlighting
+#[cfg(feature = "syntax-highlighting")]
+fn plain_code_block_html(code: &markdown::mdast::Code) -> String {
+    fn encode(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('"', "&quot;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    let class = code
+        .lang
+        .as_deref()
+        .map(|lang| format!(" class=\"language-{}\"", encode(lang)))
+        .unwrap_or_default();
+    let line_ending = if code.value.is_empty() { "" } else { "\n" };
+    format!(
+        "<pre><code{class}>{}{line_ending}</code></pre>",
+        encode(&code.value)
+    )
+}
+
+#[cfg(not(feature = "syntax-highlighting"))]
+fn process_markdown_basic(content: &str, config: &BookConfig) -> Result<String> {
+    let options = build_markdown_options(config);
+
+    let smartened;
+    let content = if config.markdown.smart_punctuation {
+        smartened = smart_punctuation(content, &options.parse)?;
+        smartened.as_str()
+    } else {
+        content
+    };
+
+    let numbering = &config.output.html.number_headings;
+    let (numbered, numbers);
+    let content = if numbering.enabled {
+        (numbered, numbers) = mark_heading_numbers(content, &options.parse, numbering.max_level)?;
+        numbered.as_str()
+    } else {
+        numbers = Vec::new();
+        content
+    };
+
+    let tables_marked;
+    let content = if options.parse.constructs.gfm_table {
+        tables_marked = mark_tables(content, &options.parse)?;
+        tables_marked.as_str()
+    } else {
+        content
+    };
+
+    let html = to_html_with_options(content, &options)
+        .map_err(|e| anyhow::anyhow!("Markdown conversion error: {:?}", e))?;
+    Ok(wrap_tables(&insert_heading_numbers(&html, &numbers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BannerConfig, BookConfig, ConfigOverride, MarkdownOverride};
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Get project root directory (CARGO_MANIFEST_DIR) for absolute path resolution
+    fn project_root() -> std::path::PathBuf {
+        std::path::PathBuf::from(
+            std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
+        )
+    }
+
+    /// Args for a plain build of `input` into `output`
+    fn build_args(input: &Path, output: &Path) -> Args {
+        Args {
+            input: Some(input.to_string_lossy().to_string()),
+            output: Some(output.to_string_lossy().to_string()),
+            config: None,
+            #[cfg(feature = "watcher")]
+            watch: false,
+            #[cfg(feature = "watcher")]
+            watch_paths: Vec::new(),
+            #[cfg(feature = "server")]
+            serve: false,
+            #[cfg(feature = "server")]
+            port: 3000,
+            #[cfg(feature = "server")]
+            mount: None,
+            #[cfg(feature = "server")]
+            open: false,
+            single_file: false,
+            dry_run: false,
+            deterministic: false,
+            strict: false,
+            keep_going: false,
+            drafts: false,
+            lint: false,
+            clean: false,
+            print_config: None,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+            cache_dir: Some(output.with_extension("cache").to_string_lossy().to_string()),
+            default_cache_dir: false,
+            format: OutputFormat::Html,
+        }
+    }
+
+    #[test]
+    fn test_should_include() {
+        let mut config = BookConfig::default();
+        assert!(should_include(Path::new("drafts/idea.md"), &config));
+        assert!(!should_include(Path::new("notes.txt"), &config));
+
+        config.build.ignore = vec![
+            "drafts".to_string(),
+            "!drafts/published.md".to_string(),
+            "**/*.wip.md".to_string(),
+        ];
+        assert!(!should_include(Path::new("drafts/idea.md"), &config));
+        assert!(!should_include(Path::new("drafts/deep/idea.md"), &config));
+        assert!(should_include(Path::new("drafts/published.md"), &config));
+        assert!(!should_include(Path::new("guide/setup.wip.md"), &config));
+        assert!(should_include(Path::new("guide/setup.md"), &config));
+
+        // The last matching pattern wins
+        config.build.ignore.push("drafts/published.md".to_string());
+        assert!(!should_include(Path::new("drafts/published.md"), &config));
+
+        // Explicit includes beat ignores, unless negated themselves
+        config.build.include = vec!["drafts/*.md".to_string(), "!drafts/secret.md".to_string()];
+        assert!(should_include(Path::new("drafts/idea.md"), &config));
+        assert!(should_include(Path::new("drafts/published.md"), &config));
+        assert!(!should_include(Path::new("drafts/secret.md"), &config));
+        assert!(!should_include(Path::new("drafts/deep/idea.md"), &config));
+
+        // Includes never admit what isn't markdown
+        config.build.include.push("**".to_string());
+        assert!(!should_include(Path::new("drafts/notes.txt"), &config));
+    }
+
+    #[test]
+    fn test_drafts_only_with_flag() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
+        fs::write(
+            input_dir.join("wip.md"),
+            "---\ndraft: true\n---\n# Work in progress",
+        )?;
+        fs::write(input_dir.join("done.md"), "---\ndraft: false\n---\n# Done")?;
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+
+        let output_dir = temp_dir.path().join("book");
+        build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false)?;
+        assert!(!output_dir.join("wip.html").exists());
+        assert!(output_dir.join("done.html").exists());
+        let intro = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(!intro.contains("Work in progress"));
+        let pages: Vec<String> = collect_pages(&input_dir, &config)?
+            .into_iter()
+            .flat_map(|section| section.pages)
+            .map(|page| page.title)
+            .collect();
+        assert!(!pages.contains(&"Work in progress".to_string()));
+
+        let output_dir = temp_dir.path().join("preview");
+        let mut args = build_args(&input_dir, &output_dir);
+        args.drafts = true;
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(output_dir.join("wip.html").exists());
+        let intro = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(intro.contains("Work in progress"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignored_sources_are_not_built() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.build.ignore = vec!["drafts".to_string()];
+        config.build.include = vec!["drafts/ready.md".to_string()];
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro".to_string()),
+            (PathBuf::from("drafts/idea.md"), "# Idea".to_string()),
+            (PathBuf::from("drafts/ready.md"), "# Ready".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(files.contains_key(Path::new("intro.html")));
+        assert!(files.contains_key(Path::new("drafts/ready.html")));
+        assert!(!files.contains_key(Path::new("drafts/idea.html")));
+
+        config.build.ignore = vec!["[".to_string()];
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("Invalid build.ignore pattern `[`"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_markdown_file() {
+        let mut config = BookConfig::default();
+        assert!(is_markdown_file(Path::new("a.md"), &config));
+        assert!(is_markdown_file(Path::new("a.MARKDOWN"), &config));
+        assert!(is_markdown_file(Path::new("a.mdx"), &config));
+        assert!(!is_markdown_file(Path::new("a.txt"), &config));
+
+        config.markdown.extensions = vec!["txt".to_string()];
+        assert!(is_markdown_file(Path::new("a.txt"), &config));
+        assert!(!is_markdown_file(Path::new("a.md"), &config));
+    }
+
+    #[test]
+    fn test_extract_title_h1() {
+        let markdown = "# Main Title\n\nSome content here.";
+        let title = extract_title(markdown, 1);
+        assert_eq!(title, Some("Main Title".to_string()));
+    }
+
+    #[test]
+    fn test_extract_title_h2() {
+        let markdown = "Some text\n\n## Section Title\n\nContent";
+        let title = extract_title(markdown, 1);
+        // extract_title only looks for H1 headings, not H2
+        assert_eq!(title, None);
+    }
+
+    #[test]
+    fn test_extract_title_level() {
+        let markdown = "# Site\n\n## Section Title\n\n### Detail\n\n## Later";
+        assert_eq!(
+            extract_title(markdown, 2),
+            Some("Section Title".to_string())
+        );
+        assert_eq!(extract_title("### Detail", 2), None);
+    }
+
+    #[test]
+    fn test_strip_title_heading() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.output.html.strip_title_heading = true;
+        let sources = vec![
+            (
+                PathBuf::from("intro.md"),
+                "# Intro\n\nWelcome.\n\n# Another heading".to_string(),
+            ),
+            (
+                PathBuf::from("later.md"),
+                "Text first.\n\n# Later".to_string(),
+            ),
+        ];
+
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+
+        let intro = &files[Path::new("intro.html")];
+        let body = &intro[intro.find("<article").unwrap()..intro.find("</article>").unwrap()];
+        assert!(!body.contains("<h1>Intro</h1>"));
+        assert!(body.contains("<h1>Another heading</h1>"));
+        assert!(body.contains("Welcome."));
+        // The title still names the page everywhere else
+        assert!(intro.contains("<title>Intro"));
+        assert!(files[Path::new("later.html")].contains("Intro\n"));
+        // Not the first block, so kept
+        assert!(files[Path::new("later.html")].contains("<h1>Later</h1>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_overrides() -> Result<()> {
+        let table = "| a | b |\n|---|---|\n| 1 | 2 |\n";
+        let sources = vec![
+            (PathBuf::from("intro.md"), format!("# Intro\n\n{table}")),
+            (
+                PathBuf::from("api/client.md"),
+                format!("# Client\n\n{table}"),
+            ),
+            (
+                PathBuf::from("api/v2/server.md"),
+                format!("# Server\n\n{table}"),
+            ),
+        ];
+        let mut config = BookConfig::default();
+        config.overrides.push(ConfigOverride {
+            glob: "api/**".to_string(),
+            markdown: MarkdownOverride {
+                format: Some(MarkdownFormat::Gfm),
+                ..MarkdownOverride::default()
+            },
+            ..ConfigOverride::default()
+        });
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+
+        assert!(!files[Path::new("intro.html")].contains("<table>"));
+        assert!(files[Path::new("api/client.html")].contains("<table>"));
+        assert!(files[Path::new("api/v2/server.html")].contains("<table>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toc_directive() -> Result<()> {
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro\n\n{{#toc}}".to_string()),
+            (
+                PathBuf::from("contents.md"),
+                "# Contents\n\nChapter one: {{#toc section=\"chapter1\"}}".to_string(),
+            ),
+            (PathBuf::from("chapter1/a.md"), "# Part *A*".to_string()),
+            (PathBuf::from("chapter1/b.md"), "# Part B".to_string()),
+            (PathBuf::from("chapter2/c.md"), "# Part C".to_string()),
+        ];
+        let config = BookConfig::default();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+
+        let intro = &files[Path::new("intro.html")];
+        let body = &intro[intro.find("<article").unwrap()..intro.find("</article>").unwrap()];
+        assert!(body
+            .contains("<ul>\n<li>Guide\n<ul>\n<li><a href=\"/contents.html\">Contents</a></li>"));
+        assert!(
+            body.contains("<li>chapter1\n<ul>\n<li><a href=\"/chapter1/a.html\">Part *A*</a></li>")
+        );
+        assert!(body.contains("<li><a href=\"/chapter1/b.html\">Part B</a></li>"));
+        assert!(body.contains("<a href=\"/chapter2/c.html\">Part C</a>"));
+        assert!(!body.contains("{{#toc"));
+
+        let contents = &files[Path::new("contents.html")];
+        assert!(contents.contains("<p>Chapter one:</p>\n<ul>\n<li><a href=\"/chapter1/a.html\">"));
+        let list = &contents[contents.find("Chapter one:").unwrap()..];
+        let list = &list[..list.find("</ul>").unwrap()];
+        assert!(list.contains("/chapter1/b.html"));
+        assert!(!list.contains("/chapter2/c.html"));
+
+        let mut sources = sources;
+        sources[0].1 = "# Intro\n\n{{#toc section=\"missing\"}}".to_string();
+        let error = build_from_sources(sources, &config).unwrap_err();
+        assert!(format!("{error:#}").contains("Unknown section `missing`"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_title_heading_level() {
+        let mut config = BookConfig::default();
+        config.markdown.title_heading_level = 2;
+        config.output.html.strip_title_heading = true;
+        let source = SourceFile {
+            rel_path: PathBuf::from("page.md"),
+            html_path: PathBuf::from("page.html"),
+            content: "## Page Title\n\nBody.\n\n## Details\n".to_string(),
+            frontmatter: Frontmatter::new(),
+        };
+
+        assert_eq!(
+            page_markdown(&source, "Page Title", &config),
+            "\n\nBody.\n\n## Details\n"
+        );
+        assert!(page_markdown(&source, "Other", &config).starts_with("## Page Title"));
+
+        config.output.html.strip_title_heading = false;
+        assert!(page_markdown(&source, "Page Title", &config).starts_with("## Page Title"));
+    }
+
+    #[test]
+    fn test_extract_title_no_heading() {
+        let markdown = "Just some regular text without headings.";
+        let title = extract_title(markdown, 1);
+        assert_eq!(title, None);
+    }
+
+    #[test]
+    fn test_extract_title_complex_markup() {
+        let markdown = "# Title with **bold** and *italic*";
+        let title = extract_title(markdown, 1);
+        assert_eq!(title, Some("Title with **bold** and *italic*".to_string()));
+    }
+
+    #[test]
+    fn test_extract_title_first_heading_wins() {
+        let markdown = "# First Title\n\n## Second Title\n\n# Third Title";
+        let title = extract_title(markdown, 1);
+        assert_eq!(title, Some("First Title".to_string()));
+    }
+
+    #[test]
+    fn test_read_markdown_source_strips_bom() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("bom.md");
+        fs::write(&path, b"\xEF\xBB\xBF# BOM Title\n\nBody")?;
+
+        let content =
+            read_markdown_source(&path, &BookConfig::default(), &mut BTreeSet::new())?.unwrap();
+        assert_eq!(extract_title(&content, 1), Some("BOM Title".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_markdown_source_invalid_utf8() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("latin1.md");
+        // "# Caf\xe9" in latin-1
+        fs::write(&path, b"# Caf\xE9\n")?;
+
+        let mut config = BookConfig::default();
+        let mut warnings = BTreeSet::new();
+        assert!(read_markdown_source(&path, &config, &mut warnings)?.is_none());
+        assert!(matches!(
+            warnings.first(),
+            Some(BuildWarning::InvalidUtf8 { file, .. }) if file == &path
+        ));
+
+        config.markdown.fail_on_invalid_utf8 = true;
+        let err = read_markdown_source(&path, &config, &mut warnings).unwrap_err();
+        assert!(err.to_string().contains("latin1.md"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_md_links_to_html() {
+        let extensions = BookConfig::default().markdown.extensions;
+        let convert_md_links_to_html = |html| {
+            convert_md_links_to_html(html, Path::new("intro.md"), &BTreeSet::new(), &extensions)
+        };
+
+        // Test basic .md to .html conversion
+        let html = r#"<a href="page.md">Link</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(result, r#"<a href="page.html">Link</a>"#);
+
+        // Test nested path
+        let html = r#"<a href="dir/subdir/page.md">Link</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(result, r#"<a href="dir/subdir/page.html">Link</a>"#);
+
+        // Test multiple links
+        let html = r#"<a href="page1.md">Link1</a> and <a href="page2.md">Link2</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(
+            result,
+            r#"<a href="page1.html">Link1</a> and <a href="page2.html">Link2</a>"#
+        );
+
+        // Test that external links are NOT converted
+        let html = r#"<a href="https://example.com/page.md">External</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(
+            result,
+            r#"<a href="https://example.com/page.md">External</a>"#
+        );
+
+        // Test http:// links are not converted
+        let html = r#"<a href="http://example.com/page.md">External</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(
+            result,
+            r#"<a href="http://example.com/page.md">External</a>"#
+        );
+
+        // Test that .html links are not modified
+        let html = r#"<a href="page.html">Link</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(result, r#"<a href="page.html">Link</a>"#);
+
+        // Test mixed internal and external links
+        let html = r#"<a href="local.md">Local</a> and <a href="https://ext.com/file.md">Ext</a>"#;
+        let result = convert_md_links_to_html(html);
+        assert_eq!(
+            result,
+            r#"<a href="local.html">Local</a> and <a href="https://ext.com/file.md">Ext</a>"#
+        );
+
+        // Every configured extension, keeping any fragment
+        let html = r#"<a href="guide.markdown#setup">A</a>"#;
+        assert_eq!(
+            convert_md_links_to_html(html),
+            r#"<a href="guide.html#setup">A</a>"#
+        );
+        let html = r#"<a href='dir/page.mdx?x=1'>B</a>"#;
+        assert_eq!(
+            convert_md_links_to_html(html),
+            r#"<a href='dir/page.html?x=1'>B</a>"#
+        );
+        let html = r#"<a href="notes.txt">Text</a>"#;
+        assert_eq!(convert_md_links_to_html(html), html);
+    }
+
+    #[test]
+    fn test_convert_md_links_through_page_html_path() {
+        let extensions = BookConfig::default().markdown.extensions;
+        let html_paths = BTreeSet::from([
+            PathBuf::from("index.html"),
+            PathBuf::from("guide/README.html"),
+        ]);
+        let page = Path::new("guide/setup.md");
+        let convert = |html| convert_md_links_to_html(html, page, &html_paths, &extensions);
+
+        // The root README is the landing page; others keep their name
+        assert_eq!(
+            convert(r#"<a href="../README.md">"#),
+            r#"<a href="../index.html">"#
+        );
+        assert_eq!(
+            convert(r#"<a href="/README.md#top">"#),
+            r#"<a href="/index.html#top">"#
+        );
+        assert_eq!(
+            convert(r#"<a href="README.md">"#),
+            r#"<a href="README.html">"#
+        );
+
+        // Unless the book has an index page of its own
+        let html_paths =
+            BTreeSet::from([PathBuf::from("index.html"), PathBuf::from("README.html")]);
+        let html = convert_md_links_to_html(
+            r#"<a href="../README.markdown">"#,
+            page,
+            &html_paths,
+            &extensions,
+        );
+        assert_eq!(html, r#"<a href="../README.html">"#);
+    }
+
+    #[test]
+    fn test_number_headings() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.output.html.number_headings.enabled = true;
+        config.output.html.number_headings.max_level = 2;
+        let markdown = "# Bar\n\nx\n\n## Foo\n\nBaz\n---\n\n### Deep\n\n# Next\n";
+        let numbered = render_markdown(markdown, &config)?;
+        assert!(numbered.contains(r#"<h1><span class="heading-number">1</span> Bar</h1>"#));
+        assert!(numbered.contains(r#"<h2><span class="heading-number">1.1</span> Foo</h2>"#));
+        assert!(numbered.contains(r#"<h2><span class="heading-number">1.2</span> Baz</h2>"#));
+        assert!(numbered.contains("<h3>Deep</h3>"));
+        assert!(numbered.contains(r#"<h1><span class="heading-number">2</span> Next</h1>"#));
+
+        // Headings written as raw HTML or inside code are not markdown headings
+        config.output.html.allow_html = true;
+        config.output.html.number_headings.max_level = 3;
+        let markdown = "<h2>Raw</h2>\n\n```\n## Code\n```\n\n## *A*\n";
+        let numbered = render_markdown(markdown, &config)?;
+        assert!(numbered.contains("<h2>Raw</h2>"));
+        assert!(numbered.contains(r#"<h2><span class="heading-number">1</span> <em>A</em></h2>"#));
+        assert_eq!(numbered.matches("heading-number").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_links_new_tab() -> Result<()> {
+        let markdown = "[Docs](https://example.com/docs) [Setup](guide/setup.md) [Top](#top)";
+        let mut config = BookConfig::default();
+        let html = render_markdown(markdown, &config)?;
+        assert!(!html.contains("target="));
+
+        config.output.html.external_links_new_tab = true;
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains(
+            r#"<a href="https://example.com/docs" target="_blank" rel="noopener noreferrer">Docs</a>"#
+        ));
+        assert!(html.contains(r#"<a href="guide/setup.html">Setup</a>"#));
+        assert!(html.contains(r##"<a href="#top">Top</a>"##));
+        assert!(!html.contains("external-link"));
+
+        config.output.html.external_link_icon = true;
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains(
+            r#"<a href="https://example.com/docs" class="external-link" target="_blank" rel="noopener noreferrer">"#
+        ));
+        assert_eq!(html.matches("external-link").count(), 1);
+
+        // Links that already pick a target, or a class, are respected
+        assert_eq!(
+            mark_external_links(
+                r#"<a href="http://a.test" target="_self">A</a>"#,
+                true,
+                true
+            ),
+            r#"<a href="http://a.test" target="_self">A</a>"#
+        );
+        assert_eq!(
+            mark_external_links(
+                r#"<a class="button" href="http://a.test">A</a>"#,
+                false,
+                true
+            ),
+            r#"<a class="external-link button" href="http://a.test">A</a>"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_headings_disabled() -> Result<()> {
+        let mut config = BookConfig::default();
+        let markdown = "# Bar\n\n## Foo\n";
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains("<h1>Bar</h1>\n<h2>Foo</h2>"));
+        assert!(!html.contains("heading-number"));
+
+        config.output.html.number_headings.enabled = true;
+        assert!(render_markdown(markdown, &config)?.contains("1.1</span> Foo"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_markdown_uses_config() -> Result<()> {
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n\nSee [next](next.md).";
+
+        let html = render_markdown(markdown, &BookConfig::default())?;
+        assert!(!html.contains("<table>"));
+        assert!(html.contains(r#"href="next.html""#));
+
+        let mut config = BookConfig::default();
+        config.markdown.format = MarkdownFormat::Gfm;
+        assert!(render_markdown(markdown, &config)?.contains("<table>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_dirs_from_config() -> Result<()> {
+        use clap::Parser;
+
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("docs"))?;
+        fs::write(root.join("docs/guide.md"), "# Guide")?;
+        let book_toml = root.join("book.toml");
+        fs::write(
+            &book_toml,
+            format!(
+                "[build]\nsrc_dir = {:?}\nbuild-dir = {:?}\n",
+                root.join("docs"),
+                root.join("site")
+            ),
+        )?;
+        let config = crate::config::load_config(Some(book_toml.to_str().unwrap()))?;
+        let cache_dir = root.join("cache");
+        let parse = |extra: &[&str]| {
+            let mut argv = vec!["md-book", "--cache-dir", cache_dir.to_str().unwrap()];
+            argv.extend(extra);
+            Args::try_parse_from(argv)
+        };
+
+        build_sync_impl_sync(&parse(&[])?, &config, false)?;
+        assert!(root.join("site/guide.html").exists());
+
+        // The command line still wins
+        let elsewhere = root.join("elsewhere");
+        build_sync_impl_sync(
+            &parse(&["-o", elsewhere.to_str().unwrap()])?,
+            &config,
+            false,
+        )?;
+        assert!(elsewhere.join("guide.html").exists());
+
+        // Without either, the mdBook layout is assumed
+        assert_eq!(parse(&[])?.output_dir(&BookConfig::default()), "book");
+
+        // Neither the command line nor book.toml points at an existing book
+        let mut missing = BookConfig::default();
+        missing.build.src_dir = Some(root.join("missing").display().to_string());
+        let err = build_sync_impl_sync(&parse(&[])?, &missing, false).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("pass --input or set build.src_dir"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_default_values() {
+        use clap::Parser;
+
+        // Test that we can parse minimal required args
+        let args = Args::try_parse_from(["md-book", "-i", "input", "-o", "output"]).unwrap();
+        assert_eq!(args.input.as_deref(), Some("input"));
+        assert_eq!(args.output.as_deref(), Some("output"));
+        assert_eq!(args.config, None);
+        assert!(!args.single_file);
+        assert!(!args.dry_run);
+        assert!(!args.deterministic);
+        assert!(!args.strict);
+        assert_eq!(args.format, OutputFormat::Html);
+
+        #[cfg(feature = "watcher")]
+        assert!(!args.watch);
+
+        #[cfg(feature = "server")]
+        {
+            assert!(!args.serve);
+            assert_eq!(args.port, 3000);
+        }
+    }
+
+    #[test]
+    fn test_dump_config() -> Result<()> {
+        use clap::Parser;
+
+        let args = Args::try_parse_from(["md-book", "-i", "in", "-o", "out", "--print-config"])?;
+        assert_eq!(args.print_config, Some(ConfigFormat::Toml));
+        let args =
+            Args::try_parse_from(["md-book", "-i", "in", "-o", "out", "--print-config", "json"])?;
+        assert_eq!(args.print_config, Some(ConfigFormat::Json));
+
+        let mut config = BookConfig::default();
+        config.book.title = "Dumped".to_string();
+        let toml = dump_config(&config, ConfigFormat::Toml)?;
+        assert!(toml.contains("[book]\n"));
+        assert!(toml.contains("title = 'Dumped'"));
+        assert!(toml.contains("[output.html]"));
+        assert!(toml.contains("date-format = '%Y-%m-%d'"));
+        let reloaded: BookConfig = toml::from_str(&toml)?;
+        assert_eq!(reloaded.book.title, "Dumped");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&dump_config(&config, ConfigFormat::Json)?)?;
+        assert_eq!(json["book"]["title"], "Dumped");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_args_with_server_options() {
+        use clap::Parser;
+
+        let args = Args::try_parse_from([
+            "md-book", "-i", "input", "-o", "output", "--serve", "--port", "8080",
+        ])
+        .unwrap();
+
+        assert!(args.serve);
+        assert_eq!(args.port, 8080);
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_default() -> Result<()> {
+        let config = BookConfig::default();
+        let markdown = "# Hello World\n\nThis is **bold** text.";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        assert!(html.contains("<h1>Hello World</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_gfm() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.format = MarkdownFormat::Gfm;
+
+        let markdown = "# GFM Test\n\n~~strikethrough~~\n\n- [ ] Task item";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        assert!(html.contains("<h1>GFM Test</h1>"));
+        assert!(html.contains("strikethrough"));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_mdx() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.format = MarkdownFormat::Mdx;
+
+        let markdown = "# MDX Test\n\nThis is **bold** text.";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        assert!(html.contains("<h1>MDX Test</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_with_html_allowed() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.output.html.allow_html = true;
+
+        let markdown = "# Test\n\n<div>Raw HTML</div>";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        assert!(html.contains("<div>Raw HTML</div>"));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_with_html_disallowed() -> Result<()> {
+        let config = BookConfig::default();
+
+        let markdown = "# Test\n\n<div>Raw HTML</div>";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        // HTML should be escaped or stripped when not allowed
+        assert!(!html.contains("<div>Raw HTML</div>"));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    #[test]
+    fn test_process_markdown_basic_with_frontmatter() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+
+        let markdown = "---\ntitle: Test\n---\n\n# Hello World";
+
+        let html = process_markdown_basic(markdown, &config)?;
+
+        assert!(html.contains("<h1>Hello World</h1>"));
+        // Frontmatter should be processed/removed from output
+        assert!(!html.contains("---"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "MathJax support not implemented yet"]
+    fn test_process_markdown_with_mathjax() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.output.html.mathjax_support = true;
+
+        let markdown = "# Math Test\n\n$$E = mc^2$$";
+
+        // Test with basic markdown processing (will work regardless of syntax highlighting feature)
+        let html = markdown::to_html(markdown);
+
+        // When implemented, should contain MathJax markup
+        assert!(html.contains("E = mc^2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_data_serialization() -> Result<()> {
+        let page_data = PageData {
+            title: "Test Page".to_string(),
+            content: "<h1>Test</h1>".to_string(),
+            summary: String::new(),
+            sections: vec![Section {
+                title: Some("Section 1".to_string()),
+                pages: vec![PageInfo {
+                    title: "Page 1".to_string(),
+                    path: "/page1".to_string(),
+                }],
+                is_active: false,
+            }],
+            previous: Some(PageInfo {
+                title: "Previous".to_string(),
+                path: "/prev".to_string(),
+            }),
+            next: None,
+        };
+
+        let serialized = serde_json::to_string(&page_data)?;
+        assert!(serialized.contains("Test Page"));
+        assert!(serialized.contains("Section 1"));
+        assert!(serialized.contains("/page1"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_process_code_block_rust() -> Result<()> {
+        use syntect::parsing::SyntaxSet;
+
+        let ss = SyntaxSet::load_defaults_newlines();
+        let code = "fn main() {\n    println!(\"Hello, world!\");\n}";
+
+        let highlighted = process_code_block(
+            code,
+            &FenceInfo::parse(Some("rust"), None),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+
+        assert!(highlighted.contains("<pre"));
+        // Syntax highlighting behavior may vary, just check basic structure
+        assert!(!highlighted.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_default_code_language() -> Result<()> {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let mut config = BookConfig::default();
+        config.markdown.default_code_language = Some("rust".to_string());
+        let markdown =
+            "```\nfn main() {}\n```\n\n```text\nfn main() {}\n```\n\n```python\na = 1\n```";
+
+        let html = process_markdown_with_highlighting(
+            markdown,
+            &ss,
+            &config,
+            Path::new("page.md"),
+            &mut BTreeSet::new(),
+        )?;
+        let blocks: Vec<_> = html.split("<pre").skip(1).collect();
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[0].contains("source rust"));
+        assert!(!blocks[1].contains("source rust"));
+        assert!(blocks[1].contains("text plain"));
+        assert!(blocks[2].contains("source python"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_process_code_block_no_language() -> Result<()> {
+        use syntect::parsing::SyntaxSet;
+
+        let ss = SyntaxSet::load_defaults_newlines();
+        let code = "some plain text code";
+
+        let highlighted =
+            process_code_block(code, &FenceInfo::default(), &ss, &SyntaxConfig::default())?;
+
+        assert!(highlighted.contains("<pre"));
+        assert!(highlighted.contains("some plain text code"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_unknown_code_language_reported() -> Result<()> {
+        use syntect::parsing::SyntaxSet;
+
+        let ss = SyntaxSet::load_defaults_newlines();
+        let markdown = "# Code\n\n```rsut\nfn main() {}\n```\n\n```rsut\nlet x = 1;\n```\n\n```rust\nfn ok() {}\n```\n\n```text\nplain\n```\n";
+        let mut warnings = BTreeSet::new();
+
+        let html = process_markdown_with_highlighting(
+            markdown,
+            &ss,
+            &BookConfig::default(),
+            Path::new("code.md"),
+            &mut warnings,
+        )?;
+
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![BuildWarning::UnknownLanguage {
+                file: PathBuf::from("code.md"),
+                language: "rsut".to_string(),
+            }]
+        );
+        assert!(html.contains("fn main() {}"));
+        assert!(html.contains("let x = 1;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_static_assets() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("output");
+        // Use absolute path to avoid issues when other tests change working directory
+        let templates_dir = project_root().join("src/templates");
+
+        fs::create_dir_all(&output_dir)?;
+
+        let config = BookConfig::default();
+        copy_static_assets(
+            output_dir.to_str().unwrap(),
+            templates_dir.to_str().unwrap(),
+            &config,
+            "/",
+            OutputFs::default(),
+        )?;
+
+        // Check that some assets were copied (if templates exist)
+        let _has_assets = output_dir.join("css").exists()
+            || output_dir.join("js").exists()
+            || output_dir.join("img").exists();
+
+        // This test passes even if no assets exist, just checking the function doesn't crash
+        assert!(output_dir.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_client_uses_index_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("output");
+        let templates_dir = project_root().join("src/templates");
+
+        let mut config = BookConfig::default();
+        config.output.html.search.index_dir = "search/index".to_string();
+        copy_static_assets(
+            output_dir.to_str().unwrap(),
+            templates_dir.to_str().unwrap(),
+            &config,
+            "/",
+            OutputFs::default(),
+        )?;
+
+        let script = fs::read_to_string(output_dir.join("js/pagefind-search.js"))?;
+        assert!(script.contains("bundlePath: '/search/index/'"));
+        assert!(!script.contains("'/pagefind/'"));
+        assert_eq!(
+            search_client_js("bundlePath: '/pagefind/'", "/docs/", "pagefind"),
+            "bundlePath: '/docs/pagefind/'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_site_root_prefixes_urls() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup")?;
+
+        // The book lives under the path of its base URL
+        let mut config = BookConfig::default();
+        config.book.base_url = Some("https://example.com/docs/".to_string());
+        let mut args = build_args(&input_dir, &output_dir);
+        build_sync_impl_sync(&args, &config, false)?;
+        let setup = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(setup.contains(r#"<link rel="stylesheet" href="/docs/css/styles.css">"#));
+        assert!(setup.contains(r#"<script src="/docs/js/code-copy.js""#));
+        assert!(setup.contains(r#"href="/docs/intro.html""#));
+        assert!(!setup.contains(r#"href="/css/"#));
+
+        // So does the web app manifest
+        config.output.html.pwa = true;
+        build_sync_impl_sync(&args, &config, false)?;
+        let setup = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(setup.contains(r#"<link rel="manifest" href="/docs/manifest.webmanifest">"#));
+        config.output.html.pwa = false;
+
+        // `--mount` wins, as that is where the server puts it
+        #[cfg(feature = "server")]
+        {
+            args.mount = Some("preview".to_string());
+            build_sync_impl_sync(&args, &config, false)?;
+            let setup = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+            assert!(setup.contains(r#"href="/preview/css/styles.css""#));
+        }
+
+        // Served from the root, nothing changes
+        config.book.base_url = Some("https://example.com".to_string());
+        args = build_args(&input_dir, &output_dir);
+        build_sync_impl_sync(&args, &config, false)?;
+        let setup = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(setup.contains(r#"href="/css/styles.css""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_client_config() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("output");
+        let templates_dir = project_root().join("src/templates");
+
+        let mut config = BookConfig::default();
+        config.output.html.search.limit_results = 7;
+        config.output.html.search.use_boolean_and = true;
+        config.output.html.search.excerpt_length = 12;
+        copy_static_assets(
+            output_dir.to_str().unwrap(),
+            templates_dir.to_str().unwrap(),
+            &config,
+            "/",
+            OutputFs::default(),
+        )?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+            output_dir.join("js/search-config.json"),
+        )?)?;
+        assert_eq!(json["maxResults"], 7);
+        assert_eq!(json["useBooleanAnd"], true);
+        assert_eq!(json["excerptLength"], 12);
+
+        let script = fs::read_to_string(output_dir.join("js/pagefind-search.js"))?;
+        assert!(script.contains("search-config.json"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_static_assets_nonexistent_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+        let templates_dir = "nonexistent_templates";
+
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let config = BookConfig::default();
+        let result = copy_static_assets(
+            output_dir.to_str().unwrap(),
+            templates_dir,
+            &config,
+            "/",
+            OutputFs::default(),
+        );
+
+        // Should not fail even if templates dir doesn't exist
+        assert!(result.is_ok());
+    }
+
+    // WASM-specific tests
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    fn test_wasm_process_markdown() {
+        use crate::wasm_process_markdown;
+
+        let markdown = "# WASM Test\n\nThis is **bold** text for WASM.";
+        let html = wasm_process_markdown(markdown);
+
+        assert!(html.contains("<h1>WASM Test</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+
+        // WASM should handle basic markdown correctly
+        assert!(!html.is_empty());
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    fn test_wasm_process_markdown_empty() {
+        use crate::wasm_process_markdown;
+
+        let html = wasm_process_markdown("");
+        assert!(html.is_empty() || html == "<p></p>\n");
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    fn test_wasm_process_markdown_code_blocks() {
+        use crate::wasm_process_markdown;
+
+        let markdown = "```rust\nfn main() {\n    println!(\"Hello, WASM!\");\n}\n```";
+        let html = wasm_process_markdown(markdown);
+
+        // WASM should handle code blocks (even without syntax highlighting)
+        assert!(html.contains("<pre>") || html.contains("<code>"));
+        assert!(html.contains("fn main"));
+        assert!(html.contains("Hello, WASM!"));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    fn test_wasm_render_gfm_table() {
+        use crate::wasm_render;
+
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n";
+        let html = wasm_render(markdown, r#"{"markdown": {"format": "gfm"}}"#).unwrap();
+        assert!(html.contains("<table>"));
+
+        assert!(wasm_render(markdown, "not json").is_err());
+    }
+
+    #[test]
+    fn test_custom_partials() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(
+            temp_dir.path().join("announcement.html.tera"),
+            "<div class=\"announcement\">{{ config.book.title }} 2.0 is out</div>",
+        )?;
+        fs::write(
+            temp_dir.path().join("page.html.tera"),
+            "{% include \"announcement\" %}<main>{{ page.content }}</main>",
+        )?;
+        fs::write(temp_dir.path().join("notes.txt"), "{% if %}")?;
+
+        let mut config = BookConfig::default();
+        config.book.title = "Handbook".to_string();
+        config.paths.templates = temp_dir.path().to_string_lossy().into_owned();
+        let files: BTreeMap<_, _> = build_from_sources(
+            vec![(PathBuf::from("intro.md"), "# Intro".to_string())],
+            &config,
+        )?
+        .into_iter()
+        .collect();
+
+        assert!(files[Path::new("intro.html")].starts_with(
+            "<div class=\"announcement\">Handbook 2.0 is out</div><main><h1>Intro</h1>"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_syntax_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let template = temp_dir.path().join("page.html.tera");
+        fs::write(
+            &template,
+            "<main>\n{% if page %}\n{{ page.content page.title }}\n{% endif %}\n</main>",
+        )?;
+
+        let mut config = BookConfig::default();
+        config.paths.templates = temp_dir.path().to_string_lossy().into_owned();
+        let error = BuildError::from(load_templates(&config).unwrap_err());
+        let BuildError::TemplateSyntax {
+            path,
+            line,
+            snippet,
+            ..
+        } = &error
+        else {
+            panic!("expected a syntax error, got {error:?}");
+        };
+        assert_eq!(path, &template);
+        assert_eq!(*line, 3);
+        assert!(snippet.contains("{{ page.content page.title }}"));
+
+        let message = error.to_string();
+        assert!(message.contains(&template.display().to_string()));
+        assert!(message.contains("line 3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frontmatter_template() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(
+            temp_dir.path().join("landing.html.tera"),
+            "<div class=\"landing-hero\">{{ page.content }}</div>",
+        )?;
+
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        config.paths.templates = temp_dir.path().to_string_lossy().into_owned();
+        let files: BTreeMap<_, _> = build_from_sources(
+            vec![
+                (
+                    PathBuf::from("welcome.md"),
+                    "---\ntemplate: landing\n---\n# Welcome".to_string(),
+                ),
+                (PathBuf::from("guide.md"), "# Guide".to_string()),
+            ],
+            &config,
+        )?
+        .into_iter()
+        .collect();
+        assert!(files[Path::new("welcome.html")]
+            .contains("<div class=\"landing-hero\"><h1>Welcome</h1>"));
+        assert!(!files[Path::new("guide.html")].contains("landing-hero"));
+
+        let error = build_from_sources(
+            vec![(
+                PathBuf::from("about.md"),
+                "---\ntemplate: splash\n---\n# About".to_string(),
+            )],
+            &config,
+        )
+        .unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("Unknown template `splash` selected by about.md"));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_try_build_reports_template_errors() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let templates_dir = temp_dir.path().join("templates");
+        fs::create_dir_all(&input_dir)?;
+        fs::create_dir_all(&templates_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
+
+        let args = build_args(&input_dir, &temp_dir.path().join("book"));
+        let mut config = BookConfig::default();
+        config.paths.templates = templates_dir.to_string_lossy().into_owned();
+
+        // Fails to parse
+        fs::write(templates_dir.join("page.html.tera"), "{% if %}")?;
+        match try_build(&args, &config, false).await {
+            Err(BuildError::TemplateSyntax { path, line, .. }) => {
+                assert_eq!(path, templates_dir.join("page.html.tera"));
+                assert_eq!(line, 1);
+            }
+            other => panic!("expected a template syntax error, got {other:?}"),
+        }
+
+        // Fails to render
+        fs::remove_file(templates_dir.join("page.html.tera"))?;
+        fs::write(templates_dir.join("index.html.tera"), "{{ missing.value }}")?;
+        match try_build(&args, &config, false).await {
+            Err(BuildError::Template { name, .. }) => assert_eq!(name, "index"),
+            other => panic!("expected a template error, got {other:?}"),
+        }
+
+        // The CLI entry point keeps the context
+        let error = build(&args, &config, false).await.unwrap_err();
+        assert!(format!("{error:#}").contains("Failed to render index page"));
+
+        Ok(())
+    }
+
+    // Integration-style test for build function
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_simple_book() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::create_dir_all(&output_dir)?;
+
+        // Create simple markdown file
+        fs::write(input_dir.join("test.md"), "# Test Page\n\nThis is a test.")?;
+
+        let args = Args {
+            input: Some(input_dir.to_string_lossy().to_string()),
+            output: Some(output_dir.to_string_lossy().to_string()),
+            config: None,
+            #[cfg(feature = "watcher")]
+            watch: false,
+            #[cfg(feature = "watcher")]
+            watch_paths: Vec::new(),
+            #[cfg(feature = "server")]
+            serve: false,
+            #[cfg(feature = "server")]
+            port: 3000,
+            #[cfg(feature = "server")]
+            mount: None,
+            #[cfg(feature = "server")]
+            open: false,
+            single_file: false,
+            dry_run: false,
+            deterministic: false,
+            strict: false,
+            keep_going: false,
+            drafts: false,
+            lint: false,
+            clean: false,
+            print_config: None,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+            cache_dir: Some(temp_dir.path().join("cache").to_string_lossy().to_string()),
+            default_cache_dir: false,
+            format: OutputFormat::Html,
+        };
+
+        let config = BookConfig::default();
+        build(&args, &config, false).await?;
+
+        // Verify output was created
+        assert!(output_dir.exists());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_skips_invalid_utf8_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("good.md"), "# Good\n")?;
+        fs::write(input_dir.join("latin1.md"), b"# Caf\xE9\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        build(&args, &BookConfig::default(), false).await?;
+
+        assert!(output_dir.join("good.html").exists());
+        assert!(!output_dir.join("latin1.html").exists());
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_alternate_extensions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("guide.markdown"), "# Guide\n")?;
+        fs::write(input_dir.join("page.mdx"), "# Page\n\n{1 + 1}\n")?;
+        fs::write(input_dir.join("notes.txt"), "# Not markdown\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        build(&args, &BookConfig::default(), false).await?;
+
+        assert!(output_dir.join("guide.html").exists());
+        assert!(!output_dir.join("notes.html").exists());
+
+        // The MDX expression is consumed by the MDX parser rather than shown as text
+        let page = fs::read_to_string(output_dir.join("page.html"))?;
+        assert!(page.contains("Page"));
+        assert!(!page.contains("{1 + 1}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        let mut config = BookConfig::default();
+        assert_eq!(canonical_url(&config, "guide/intro.html"), None);
+
+        config.book.base_url = Some(String::new());
+        assert_eq!(canonical_url(&config, "guide/intro.html"), None);
+
+        config.book.base_url = Some("https://docs.example.com/".to_string());
+        assert_eq!(
+            canonical_url(&config, "guide/intro.html"),
+            Some("https://docs.example.com/guide/intro.html".to_string())
+        );
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_canonical_link() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("guide/intro.md"), "# Intro\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        let mut config = BookConfig::default();
+        config.book.base_url = Some("https://docs.example.com".to_string());
+        build(&args, &config, false).await?;
+
+        let page = fs::read_to_string(output_dir.join("guide/intro.html"))?;
+        assert!(page.contains(
+            r#"<link rel="canonical" href="https://docs.example.com/guide/intro.html">"#
+        ));
+
+        build(&args, &BookConfig::default(), false).await?;
+        let page = fs::read_to_string(output_dir.join("guide/intro.html"))?;
+        assert!(!page.contains(r#"rel="canonical""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_sections_root_title() {
+        let root = vec![PageInfo {
+            title: "Intro".to_string(),
+            path: "/intro.html".to_string(),
+        }];
+        let mut config = BookConfig::default();
+
+        let sections = build_sections(root.clone(), BTreeMap::new(), &config);
+        assert_eq!(sections[0].title.as_deref(), Some("Guide"));
+
+        config.book.root_section_title = Some("Overview".to_string());
+        let sections = build_sections(root.clone(), BTreeMap::new(), &config);
+        assert_eq!(sections[0].title.as_deref(), Some("Overview"));
+
+        config.book.flatten_root_section = true;
+        let sections = build_sections(root, BTreeMap::new(), &config);
+        assert_eq!(sections[0].title, None);
+        assert_eq!(sections[0].pages[0].title, "Intro");
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_root_section_title_in_sidebar() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        let mut config = BookConfig::default();
+        config.book.root_section_title = Some("Overview".to_string());
+        build(&args, &config, false).await?;
+
+        let page = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(page.contains(r#"<h2 class="sidebar-section-title">Overview</h2>"#));
+        assert!(!page.contains(">Guide<"));
+
+        config.book.flatten_root_section = true;
+        build(&args, &config, false).await?;
+
+        let page = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(!page.contains("sidebar-section-title"));
+        assert!(page.contains(r#"href="/intro.html""#));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_marks_active_sidebar_section() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("api"))?;
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("api/client.md"), "# Client\n")?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        build(&args, &BookConfig::default(), false).await?;
+
+        let page = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(page
+            .contains(r#"<details class="sidebar-section is-active" data-section="guide" open>"#));
+        assert!(page.contains(r#"<details class="sidebar-section" data-section="api">"#));
+
+        // Titles are escaped, so the attribute and its saved state key hold
+        fs::create_dir_all(input_dir.join("Q&A \"live\""))?;
+        fs::write(input_dir.join("Q&A \"live\"/ask.md"), "# Ask\n")?;
+        build(&args, &BookConfig::default(), false).await?;
+        let page = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(page.contains(r#"data-section="Q&amp;A &quot;live&quot;">"#));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_sidebar_config() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        let mut config = BookConfig::default();
+        config.output.html.sidebar.width = Some("360px".to_string());
+        build(&args, &config, false).await?;
+
+        let page = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(page.contains(r#"<div class="sidebar">"#));
+        assert!(page.contains("--sidebar-width: 360px;"));
+
+        config.output.html.sidebar.enabled = false;
+        build(&args, &config, false).await?;
+
+        let page = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(!page.contains(r#"<div class="sidebar">"#));
+        assert!(page.contains(r#"<div class="container no-sidebar">"#));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_print_page() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("part"))?;
+        fs::write(
+            input_dir.join("a-intro.md"),
+            "# Intro\n\nFirst chapter body.",
+        )?;
+        fs::write(
+            input_dir.join("part/b-usage.md"),
+            "# Usage\n\nSecond chapter body.",
+        )?;
+
+        let args = build_args(&input_dir, &output_dir);
+        build(&args, &BookConfig::default(), false).await?;
+        assert!(!output_dir.join("print.html").exists());
+
+        let mut config = BookConfig::default();
+        config.output.html.print_page = true;
+        build(&args, &config, false).await?;
+
+        let print = fs::read_to_string(output_dir.join("print.html"))?;
+        let first = print.find("First chapter body.").unwrap();
+        let second = print.find("Second chapter body.").unwrap();
+        assert!(first < second);
+        assert!(print.contains(r#"id="part-b-usage""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_typography_settings() -> Result<()> {
+        let sources = vec![(PathBuf::from("intro.md"), "# Intro".to_string())];
+        let mut config = BookConfig::default();
+
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(!files[Path::new("intro.html")].contains("--content-max-width"));
+
+        config.output.html.content_max_width = Some("60rem".to_string());
+        config.output.html.code_font_family = Some("'Fira Code', monospace".to_string());
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        for page in ["intro.html", "index.html"] {
+            let html = &files[Path::new(page)];
+            assert!(html.contains("<style>:root { --content-max-width: 60rem; --code-font-family: 'Fira Code', monospace; }</style>"), "{page}");
+            assert!(!html.contains("--font-family:"), "{page}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_markdown_options() {
+        let mut config = BookConfig::default();
+        let options = build_markdown_options(&config);
+        assert!(!options.parse.constructs.frontmatter);
+        assert!(!options.parse.constructs.html_flow);
+        assert!(!options.parse.constructs.html_text);
+        assert!(!options.parse.constructs.gfm_table);
+        assert!(!options.compile.allow_dangerous_html);
+
+        config.markdown.format = MarkdownFormat::Gfm;
+        config.markdown.frontmatter = true;
+        config.output.html.allow_html = true;
+        let options = build_markdown_options(&config);
+        assert!(options.parse.constructs.frontmatter);
+        assert!(options.parse.constructs.html_flow);
+        assert!(options.parse.constructs.html_text);
+        assert!(options.parse.constructs.gfm_table);
+        assert!(options.compile.allow_dangerous_html);
+        assert!(options.compile.allow_dangerous_protocol);
+    }
+
+    #[test]
+    fn test_tables_are_wrapped() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.format = MarkdownFormat::Gfm;
+        config.output.html.allow_html = true;
+
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n\n<table><tr><td>raw</td></tr></table>\n";
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains("<div class=\"table-wrapper\"><table>\n<thead>"));
+        assert_eq!(html.matches("table-wrapper").count(), 1);
+        assert!(html.contains("<table><tr><td>raw</td></tr></table>"));
+
+        // Running the step again doesn't double-wrap
+        assert_eq!(wrap_tables(&html), html);
+
+        // Only markdown tables are wrapped, each around all of itself
+        let markdown = "<table>\n<thead><tr><th>raw</th></tr></thead></table>\n\n```md\n| a |\n|---|\n```\n\n| <table><tr><td>in</td></tr></table> | b |\n|---|---|\n| | 2 |\n";
+        let html = render_markdown(markdown, &config)?;
+        assert_eq!(html.matches("table-wrapper").count(), 1);
+        assert!(html.starts_with("<table>\n<thead><tr><th>raw</th>"));
+        assert!(html.contains("<div class=\"table-wrapper\"><table>\n<thead>\n<tr>\n<th><table><tr><td>in</td></tr></table></th>"));
+        assert!(html.trim_end().ends_with("</tbody>\n</table></div>"));
+        assert!(!html.contains(TABLE_MARKER));
+        Ok(())
+    }
+
+    #[test]
+    fn test_smart_punctuation() -> Result<()> {
+        let markdown = "He said \"hi\" -- it's *\"quoted\"* --- wait...\n\nRun `echo \"hi\" -- ...` or \\\"escaped\\\".\n\n```sh\necho \"x\" -- '...'\n```\n";
+        let mut config = BookConfig::default();
+        config.markdown.smart_punctuation = true;
+
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains(
+            "He said \u{201C}hi\u{201D} \u{2013} it\u{2019}s <em>\u{201C}quoted\u{201D}</em> \u{2014} wait\u{2026}"
+        ));
+        // Code spans, code blocks and escaped quotes keep ASCII punctuation
+        assert!(html.contains("<code>echo &quot;hi&quot; -- ...</code>"));
+        assert!(html.contains("&quot;escaped&quot;"));
+        assert!(html.contains("</span>...<span"));
+        assert!(!html.contains('\u{2018}'));
+
+        config.markdown.smart_punctuation = false;
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains("He said &quot;hi&quot; -- it's"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_smarten_text_quote_direction() {
+        assert_eq!(
+            smarten_text("\"a\" 'b'", None, None),
+            "\u{201C}a\u{201D} \u{2018}b\u{2019}"
+        );
+        // Quotes at the edge of a run look at the surrounding characters
+        assert_eq!(smarten_text("\"", Some('*'), None), "\u{201D}");
+        assert_eq!(smarten_text("\"", Some(' '), Some('x')), "\u{201C}");
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_highlighting_keeps_surrounding_structure() -> Result<()> {
+        let markdown = "- step one\n\n  ```rust\n  let x = 1;\n  ```\n\n  still step one\n- step two\n\n> quoted\n> ```\n> inside\n> ```\n> after\n";
+        let html = render_markdown(markdown, &BookConfig::default())?;
+
+        // The highlighted block stays inside its list item, followed by the rest of it
+        assert!(html.contains(
+            "<li>\n<p>step one</p>\n<pre class=\"code rust\"><code><span class=\"source rust\">"
+        ));
+        assert!(html.contains("</code></pre>\n<p>still step one</p>\n</li>"));
+        assert_eq!(html.matches("<ul>").count(), 1);
+
+        // The blockquote survives the fence interrupting it
+        assert_eq!(html.matches("<blockquote>").count(), 1);
+        assert!(html.contains("<p>after</p>\n</blockquote>"));
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_code_info_parse() {
+        let info = FenceInfo::parse(
+            Some("rust,editable"),
+            Some(r#"title="src/main.rs" ignore hl=3"#),
+        );
+        assert_eq!(info.language.as_deref(), Some("rust"));
+        assert_eq!(info.attributes["title"], "src/main.rs");
+        assert_eq!(info.attributes["hl"], "3");
+        assert_eq!(
+            info.flags,
+            BTreeSet::from(["editable".to_string(), "ignore".to_string()])
+        );
+
+        let quoted = FenceInfo::parse(Some("sh"), Some(r#"title="Install it""#));
+        assert_eq!(quoted.attributes["title"], "Install it");
+
+        assert_eq!(FenceInfo::parse(None, None), FenceInfo::default());
+
+        let lines = FenceInfo::parse(Some("rust"), Some("{3-5,8} title=\"a.rs\""));
+        assert_eq!(lines.language.as_deref(), Some("rust"));
+        assert_eq!(lines.highlight, [3..=5, 8..=8]);
+        assert!(lines.highlights(4) && !lines.highlights(6));
+
+        // A huge range stays a range instead of expanding line by line
+        let huge = FenceInfo::parse(Some("rust"), Some("{2-18446744073709551615,0,5-3}"));
+        assert_eq!(huge.highlight.len(), 1);
+        assert_eq!(huge.highlight[0], 2..=usize::MAX);
+        assert_eq!(lines.attributes["title"], "a.rs");
+        assert!(lines.flags.is_empty());
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_custom_code_block_renderer() -> Result<()> {
+        struct FooRenderer;
+
+        impl CodeBlockRenderer for FooRenderer {
+            fn render(&self, code: &str, info: &FenceInfo) -> Option<Result<String>> {
+                if info.flags.contains("skip") {
+                    return None;
+                }
+                Some(Ok(format!(
+                    "<div class=\"foo\">{}</div>",
+                    code.trim().to_uppercase()
+                )))
+            }
+        }
+
+        let mut config = BookConfig::default();
+        config.syntax.renderers.register("foo", FooRenderer);
+
+        let html = render_markdown("```foo\nhello\n```\n", &config)?;
+        assert!(html.contains("<div class=\"foo\">HELLO</div>"));
+        assert!(!html.contains("<pre"));
+
+        let html = render_markdown("```foo skip\nhello\n```\n", &config)?;
+        assert!(html.contains("<pre class=\"code\">"));
+        assert!(html.contains("hello"));
+
+        let mut warnings = BTreeSet::new();
+        process_markdown_with_highlighting(
+            "```foo\nhello\n```\n",
+            &SyntaxSet::load_defaults_newlines(),
+            &config,
+            Path::new("page.md"),
+            &mut warnings,
+        )?;
+        assert!(warnings.is_empty());
+
+        // Only builds given the renderer use it
+        let html = render_markdown("```foo\nhello\n```\n", &BookConfig::default())?;
+        assert!(!html.contains("HELLO"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_process_code_block_console() -> Result<()> {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let code = "$ cargo install md-book\n    Installed md-book\n$ md-book -i src -o \"book\"\n";
+
+        let html = process_code_block(
+            code,
+            &FenceInfo::parse(Some("console"), None),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+
+        let copy = "data-copy=\"cargo install md-book\nmd-book -i src -o &quot;book&quot;\"";
+        assert!(html.contains(copy));
+        assert!(html.contains(
+            "<span class=\"command\"><span class=\"prompt\">$ </span>cargo install md-book</span>"
+        ));
+        assert!(html.contains("<span class=\"output\">    Installed md-book</span>"));
+
+        assert!(is_known_language("console", &ss));
+
+        // A shell script without prompts is highlighted as usual
+        let script = "# install\ncargo install md-book\n";
+        let html = process_code_block(
+            script,
+            &FenceInfo::parse(Some("sh"), None),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+        assert!(!html.contains("data-copy"));
+
+        let html = process_code_block(
+            code,
+            &FenceInfo::parse(Some("shell"), None),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+        assert!(html.contains(copy));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_process_code_block_diff() -> Result<()> {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let code = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1,2 +1,2 @@\n fn keep() {}\n-fn old() -> Vec<u8> {}\n+fn new() {}\n";
+
+        let html = process_code_block(
+            code,
+            &FenceInfo::parse(Some("diff"), None),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+
+        assert!(html.contains("<span class=\"diff-del\">-fn old() -&gt; Vec&lt;u8&gt; {}</span>"));
+        assert!(html.contains("<span class=\"diff-add\">+fn new() {}</span>"));
+        assert!(html.contains("\n fn keep() {}\n"));
+        assert!(html.contains("--- a/lib.rs\n+++ b/lib.rs\n"));
+        assert_eq!(html.matches("diff-add").count(), 1);
+        assert_eq!(html.matches("diff-del").count(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_highlight_line_range() -> Result<()> {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let code = "/* one\ntwo */\nlet a = 3;\nlet b = 4;\nlet c = 5;\nlet d = 6;\n";
+
+        let html = process_code_block(
+            code,
+            &FenceInfo::parse(Some("rust"), Some("{3-5,9}")),
+            &ss,
+            &SyntaxConfig::default(),
+        )?;
+
+        let lines: Vec<_> = html
+            .match_indices("<span class=\"line")
+            .map(|(pos, _)| html[pos..].starts_with("<span class=\"line highlighted\""))
+            .collect();
+        assert_eq!(lines, [false, false, true, true, true, false]);
+        assert_eq!(
+            html.matches("<span").count(),
+            html.matches("</span>").count()
+        );
+
+        let markdown = "```js {2-3}\nlet a;\nlet b;\n```\n";
+        let mut warnings = BTreeSet::new();
+        let html = process_markdown_with_highlighting(
+            markdown,
+            &ss,
+            &BookConfig::default(),
+            Path::new("page.md"),
+            &mut warnings,
+        )?;
+        assert!(html.contains("line highlighted"));
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![BuildWarning::HighlightLineOutOfRange {
+                file: PathBuf::from("page.md"),
+                line: 3,
+                lines: 2,
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_code_block_title_caption() -> Result<()> {
+        let markdown = "```rust,editable title=\"main.rs\"\nfn main() {}\n```\n";
+        let html = render_markdown(markdown, &BookConfig::default())?;
+
+        assert!(html.contains(
+            r#"<figure class="code-block"><figcaption class="code-title">main.rs</figcaption><pre class="code rust">"#
+        ));
+        assert!(html.trim_end().ends_with("</pre></figure>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnotes_in_default_markdown() -> Result<()> {
+        let markdown = "Claim.[^1]\n\n```rust\nfn main() {}\n```\n\n[^1]: The source.\n";
+        let config = BookConfig::default();
+        assert!(matches!(config.markdown.format, MarkdownFormat::Markdown));
+
+        let html = render_markdown(markdown, &config)?;
+        assert!(html.contains(r##"<sup><a href="#user-content-fn-1" id="user-content-fnref-1""##));
+        assert!(html.contains(r#"<li id="user-content-fn-1">"#));
+        assert!(html.contains(r##"href="#user-content-fnref-1""##));
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnotes_disabled() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.footnotes = false;
+
+        let html = render_markdown("Claim.[^1]\n\n[^1]: The source.\n", &config)?;
+        assert!(!html.contains("<sup>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tasklists_in_default_markdown() -> Result<()> {
+        let markdown =
+            "- [ ] todo\n- [x] done\n- [X] also done\n-   [ ]   spaced\n- [x]not a task\n";
+        let mut config = BookConfig::default();
+
+        let html = render_markdown(markdown, &config)?;
+        assert!(!html.contains("<input"));
+
+        config.markdown.tasklists = true;
+        let html = render_markdown(markdown, &config)?;
+        let unchecked = r#"<input type="checkbox" disabled="" /> "#;
+        let checked = r#"<input type="checkbox" disabled="" checked="" /> "#;
+        assert!(html.contains(&format!("<li>{unchecked}todo</li>")));
+        assert!(html.contains(&format!("<li>{checked}done</li>")));
+        assert!(html.contains(&format!("<li>{checked}also done</li>")));
+        // Extra spaces after the box are kept, as in GFM, and collapse when displayed
+        assert!(html.contains(&format!("<li>{unchecked}  spaced</li>")));
+        assert!(html.contains("<li>[x]not a task</li>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_frontmatter() -> Result<()> {
+        let yaml = parse_frontmatter("---\ntitle: Intro\ntags: [a, b]\n---\n# Intro\n")?;
+        assert_eq!(yaml["title"], "Intro");
+        assert_eq!(yaml["tags"], serde_json::json!(["a", "b"]));
+
+        let toml = parse_frontmatter("+++\ntitle = \"Intro\"\ndate = 2024-05-01\n+++\n# Intro\n")?;
+        assert_eq!(toml["title"], "Intro");
+        assert_eq!(toml["date"], "2024-05-01");
+
+        assert!(parse_frontmatter("# No frontmatter\n")?.is_empty());
+        assert!(parse_frontmatter("---\nnever closed\n")?.is_empty());
+        assert!(parse_frontmatter("---\n- a list\n---\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_text() -> Result<()> {
+        let markdown =
+            "# Title\n\nSome **bold** and `code`.\n\n- one\n- two\n\n```rust\nfn main() {}\n```\n";
+        let text = markdown_to_plaintext(markdown, &BookConfig::default(), true)?;
+        assert_eq!(text, "Title\nSome bold and code.\none\ntwo\nfn main() {}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_page_content() -> Result<()> {
+        let sources = vec![
+            (
+                PathBuf::from("index.md"),
+                "# Home\n\nIntro.\n\n<!-- more -->\n\nThe rest.".to_string(),
+            ),
+            (PathBuf::from("guide.md"), "# Guide".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources, &BookConfig::default())?
+            .into_iter()
+            .collect();
+        let index = &files[Path::new("index.html")];
+        assert!(index.contains("<p>Intro.</p>"));
+        assert!(index.contains("<p>The rest.</p>"));
+        assert!(!index.contains("more --"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_summary() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        config.output.html.excerpt_words = 5;
+        let sources = vec![
+            (
+                PathBuf::from("more.md"),
+                "# More\n\nFirst part.\n\n```md\n<!-- more -->\n```\n\nStill intro.\n<!--MORE-->\nThe rest.".to_string(),
+            ),
+            (
+                PathBuf::from("long.md"),
+                "# Long\n\nOne `two` three **four** five six seven.".to_string(),
+            ),
+            (
+                PathBuf::from("short.md"),
+                "# Short\n\nJust this.".to_string(),
+            ),
+            (
+                PathBuf::from("set.md"),
+                "---\nsummary: Chosen by hand\n---\n# Set\n\nIgnored.".to_string(),
+            ),
+        ];
+        let mut tera = load_templates(&config)?;
+        tera.add_raw_template("page", "{{ page.summary }}|{{ page.content }}")?;
+        let collection = collect_documents(sources, &config, false, BTreeSet::new())?;
+        let options = RenderOptions {
+            input: None,
+            format: OutputFormat::Html,
+            single_file: false,
+            watch_enabled: false,
+            deterministic: false,
+            keep_going: false,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+        };
+        let files: BTreeMap<_, _> = render_book(&tera, &config, collection, &options)?
+            .files
+            .into_iter()
+            .collect();
+        let page = |name: &str| files[Path::new(name)].split_once('|').unwrap();
+
+        // Cut at the marker, not at the copy inside the code block
+        let (summary, content) = page("more.html");
+        assert_eq!(summary, "First part. Still intro.");
+        assert!(!content.contains("MORE"));
+        assert!(content.contains("<p>Still intro.</p>\n<p>The rest.</p>"));
+        assert_eq!(page("long.html").0, "One three four five six…");
+        assert_eq!(page("short.html").0, "Just this.");
+        assert_eq!(page("set.html").0, "Chosen by hand");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_landing_page_cards() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        let sources = vec![
+            (
+                PathBuf::from("intro.md"),
+                "---\nicon: rocket\n---\n# Intro\n\nWhat the tool is for.".to_string(),
+            ),
+            (
+                PathBuf::from("guide/setup.md"),
+                "---\nsummary: Install <it> quickly\nimage: img/setup.png\n---\n# Setup\n\nLong steps."
+                    .to_string(),
+            ),
+        ];
+
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        let index = &files[Path::new("index.html")];
+        assert!(index.contains(r#"<p class="doc-card-summary">What the tool is for.</p>"#));
+        assert!(index.contains(r#"<p class="doc-card-summary">Install &lt;it&gt; quickly</p>"#));
+        assert!(index.contains(r#"<sl-icon name="rocket"></sl-icon>"#));
+        assert!(index.contains(r#"<img slot="image" src="/guide/img/setup.png" alt="">"#));
+        // Still grouped by section
+        let guide = index.find("<h2>guide</h2>").unwrap();
+        assert!(index.find("What the tool is for.").unwrap() < guide);
+        assert!(index.find("Install &lt;it&gt;").unwrap() > guide);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_text_without_code() -> Result<()> {
+        let markdown = "## Setup *guide*\n\n1. Read [the docs](https://example.com) first\n2. Run `make` then ![a diagram](d.png)\n   - nested\n\n```sh\nmake install\n```\n\nDone.";
+        let config = BookConfig::default();
+
+        let text = markdown_to_plaintext(markdown, &config, false)?;
+        assert_eq!(
+            text,
+            "Setup guide\nRead the docs first\nRun  then a diagram\nnested\nDone."
+        );
+        let text = markdown_to_plaintext(markdown, &config, true)?;
+        assert!(text.contains("Run make then a diagram\n"));
+        assert!(text.contains("\nmake install\n"));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_json_export() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("index.md"), "# Welcome\n\nHello.")?;
+        fs::write(
+            input_dir.join("guide/setup.md"),
+            "---\nsummary: How to install\n---\n# Setup\n\nInstall it.",
+        )?;
+
+        let mut config = BookConfig::default();
+        config.book.title = "Export Book".to_string();
+        config.markdown.frontmatter = true;
+
+        let mut args = build_args(&input_dir, &output_dir);
+        args.format = OutputFormat::Json;
+        build(&args, &config, false).await?;
+
+        let export: BookExport =
+            serde_json::from_str(&fs::read_to_string(output_dir.join("book.json"))?)?;
+        assert_eq!(export.book.title, "Export Book");
+        assert_eq!(export.pages.len(), 2);
+        assert!(!export.sections.is_empty());
+
+        let setup = export
+            .pages
+            .iter()
+            .find(|page| page.path == "/guide/setup.html")
+            .expect("setup page is exported");
+        assert_eq!(setup.title, "Setup");
+        assert_eq!(setup.frontmatter["summary"], "How to install");
+        assert!(setup.html.contains("<p>Install it.</p>"));
+        assert_eq!(setup.text, "Setup\nInstall it.");
+
+        // No HTML site is written alongside the export
+        assert!(!output_dir.join("index.html").exists());
+        assert!(!output_dir.join("css").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let dir = Path::new("guide");
+        assert_eq!(
+            resolve_link(dir, "setup.html"),
+            PathBuf::from("guide/setup.html")
+        );
+        assert_eq!(
+            resolve_link(dir, "../intro.html"),
+            PathBuf::from("intro.html")
+        );
+        assert_eq!(
+            resolve_link(dir, "./img/a.png"),
+            PathBuf::from("guide/img/a.png")
+        );
+        assert_eq!(resolve_link(dir, "/img/a.png"), PathBuf::from("img/a.png"));
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_build_copies_colocated_images() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("chapter1"))?;
+        fs::create_dir_all(input_dir.join("shared"))?;
+        fs::write(
+            input_dir.join("chapter1/section1.md"),
+            "# Section\n\n![](diagram.png)\n\n![](../shared/img.png)\n\n![](missing.png)\n",
+        )?;
+        fs::write(input_dir.join("chapter1/diagram.png"), "diagram")?;
+        fs::write(input_dir.join("shared/img.png"), "shared")?;
+
+        build(
+            &build_args(&input_dir, &output_dir),
+            &BookConfig::default(),
+            false,
+        )
+        .await?;
+
+        let html = fs::read_to_string(output_dir.join("chapter1/section1.html"))?;
+        assert!(html.contains(r#"src="diagram.png""#));
+        assert!(html.contains(r#"src="../shared/img.png""#));
+        assert_eq!(
+            fs::read_to_string(output_dir.join("chapter1/diagram.png"))?,
+            "diagram"
+        );
+        assert_eq!(
+            fs::read_to_string(output_dir.join("shared/img.png"))?,
+            "shared"
+        );
+        assert!(!output_dir.join("chapter1/missing.png").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_sources() {
+        let html = r#"<p><img src="a.png" alt="" /> <img alt="b" src="b.png"> <iframe src="c.html"></iframe></p>"#;
+        assert_eq!(image_sources(html).collect::<Vec<_>>(), ["a.png", "b.png"]);
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_meta_description() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("plain.md"), "# Plain\n\nText.")?;
+        fs::write(
+            input_dir.join("custom.md"),
+            "---\ndescription: All about \"custom\" pages\n---\n# Custom\n\nText.",
+        )?;
+
+        let mut config = BookConfig::default();
+        config.book.description = Some("A book about testing".to_string());
+        config.markdown.frontmatter = true;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        let plain = fs::read_to_string(output_dir.join("plain.html"))?;
+        assert!(plain.contains(r#"<meta name="description" content="A book about testing">"#));
+        assert!(
+            plain.contains(r#"<meta property="og:description" content="A book about testing">"#)
+        );
+
+        let custom = fs::read_to_string(output_dir.join("custom.html"))?;
+        assert!(custom
+            .contains(r#"<meta name="description" content="All about &quot;custom&quot; pages">"#));
+        assert!(!custom.contains("A book about testing"));
+
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains(r#"<meta name="description" content="A book about testing">"#));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_open_graph_tags() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup Guide\n\nText.")?;
+
+        let mut config = BookConfig::default();
+        config.book.base_url = Some("https://docs.example.com/".to_string());
+        config.book.logo = "/img/logo.svg".to_string();
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        let html = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(html.contains(r#"<meta property="og:title" content="Setup Guide">"#));
+        assert!(html.contains(
+            r#"<meta property="og:url" content="https://docs.example.com/guide/setup.html">"#
+        ));
+        assert!(html.contains(
+            r#"<meta property="og:image" content="https://docs.example.com/img/logo.svg">"#
+        ));
+        assert!(html.contains(r#"<meta name="twitter:card" content="summary">"#));
+
+        config.output.html.og_image = Some("https://cdn.example.com/preview.png".to_string());
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+        let html = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(html.contains(
+            r#"<meta property="og:image" content="https://cdn.example.com/preview.png">"#
+        ));
+        assert!(html.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+
+        config.output.html.open_graph = false;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+        let html = fs::read_to_string(output_dir.join("guide/setup.html"))?;
+        assert!(!html.contains("og:title"));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_redirects() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("new.md"), "# New\n\nText.")?;
+
+        let mut config = BookConfig::default();
+        config.output.html.redirect = BTreeMap::from([
+            ("/old.html".to_string(), "/new.html".to_string()),
+            (
+                "legacy/page.html".to_string(),
+                "https://example.com/moved.html".to_string(),
+            ),
+        ]);
+
+        // Meta-refresh stubs by default
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+        let stub = fs::read_to_string(output_dir.join("old.html"))?;
+        assert!(stub.contains(r#"<meta http-equiv="refresh" content="0; URL=/new.html">"#));
+        assert!(output_dir.join("legacy/page.html").exists());
+        assert!(!output_dir.join("_redirects").exists());
+
+        let output_dir = temp_dir.path().join("netlify");
+        config.output.html.redirect_format = RedirectFormat::Netlify;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+        let rules = fs::read_to_string(output_dir.join("_redirects"))?;
+        assert_eq!(
+            rules,
+            "/old.html /new.html 301\n/legacy/page.html https://example.com/moved.html 301\n"
+        );
+        assert!(!output_dir.join("old.html").exists());
+
+        // Stubs stay inside the book and never replace a generated page
+        config.output.html.redirect_format = RedirectFormat::Meta;
+        let sources = vec![(PathBuf::from("new.md"), "# New".to_string())];
+        for from in [
+            "../escape.html",
+            "/guide/../../escape.html",
+            "//",
+            "new.html",
+            "404.html",
+        ] {
+            config.output.html.redirect =
+                BTreeMap::from([(from.to_string(), "/new.html".to_string())]);
+            let err = build_from_sources(sources.clone(), &config).unwrap_err();
+            assert!(err.to_string().contains(from), "{from}: {err}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frontmatter_aliases() -> Result<()> {
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        config.output.html.redirect_format = RedirectFormat::Both;
+        let sources = vec![
+            (
+                PathBuf::from("guide/setup.md"),
+                "---\naliases: [\"/old-url.html\", \"legacy/path.html\"]\n---\n# Setup".to_string(),
+            ),
+            (PathBuf::from("intro.md"), "# Intro".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+
+        for stub in ["old-url.html", "legacy/path.html"] {
+            let stub = &files[Path::new(stub)];
+            assert!(stub.contains(r#"content="0; URL=/guide/setup.html""#));
+            assert!(stub.contains("location.hash"));
+        }
+        assert_eq!(
+            files[Path::new("_redirects")],
+            "/old-url.html /guide/setup.html 301\n/legacy/path.html /guide/setup.html 301\n"
+        );
+
+        // An alias may not shadow a page
+        let mut clash = sources.clone();
+        clash.push((
+            PathBuf::from("moved.md"),
+            "---\naliases: [intro.html]\n---\n# Moved".to_string(),
+        ));
+        let err = build_from_sources(clash, &config).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("would overwrite the page built from intro.md"));
+
+        // Directory-style aliases get an index page of their own
+        let mut sources = sources;
+        sources[1].1 = "---\naliases: [/old-intro/]\n---\n# Intro".to_string();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(files[Path::new("old-intro/index.html")].contains(r#"URL=/intro.html""#));
+        assert!(files[Path::new("_redirects")].contains("/old-intro/ /intro.html 301\n"));
+
+        // Aliases are checked like `output.html.redirect`, even against
+        // pages the book generates itself
+        config.output.html.print_page = true;
+        for alias in ["../outside.html", "/", "print.html"] {
+            sources[1].1 = format!("---\naliases: [\"{alias}\"]\n---\n# Intro");
+            let err = build_from_sources(sources.clone(), &config).unwrap_err();
+            assert!(format!("{err:#}").contains(alias), "{alias}: {err:#}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_html_path() {
+        assert_eq!(
+            page_html_path(Path::new("README.md"), false, false),
+            PathBuf::from("index.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("README.md"), true, false),
+            PathBuf::from("README.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("guide/README.md"), false, false),
+            PathBuf::from("guide/README.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("guide/setup.md"), false, false),
+            PathBuf::from("guide/setup.html")
+        );
+
+        assert_eq!(
+            page_html_path(Path::new("guide/setup.md"), false, true),
+            PathBuf::from("guide/setup/index.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("guide/README.md"), false, true),
+            PathBuf::from("guide/README.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("guide/index.md"), false, true),
+            PathBuf::from("guide/index.html")
+        );
+        assert_eq!(
+            page_html_path(Path::new("README.md"), false, true),
+            PathBuf::from("index.html")
+        );
+    }
+
+    #[test]
+    fn test_pwa_manifest() -> Result<()> {
+        let sources = vec![(PathBuf::from("intro.md"), "# Intro".to_string())];
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &BookConfig::default())?
+            .into_iter()
+            .collect();
+        assert!(!files.contains_key(Path::new("manifest.webmanifest")));
+        assert!(!files[Path::new("intro.html")].contains("theme-color"));
+
+        let mut config = BookConfig::default();
+        config.book.title = "Field Guide".to_string();
+        config.book.logo = "/img/logo.svg".to_string();
+        config.output.html.theme_color = Some("#1f6feb".to_string());
+        config.output.html.pwa = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&files[Path::new("manifest.webmanifest")])?;
+        assert_eq!(manifest["name"], "Field Guide");
+        assert_eq!(manifest["theme_color"], "#1f6feb");
+        assert_eq!(manifest["icons"][0]["src"], "img/logo.svg");
+        assert_eq!(manifest["icons"][0]["type"], "image/svg+xml");
+        assert_eq!(manifest["icons"][0]["sizes"], "any");
+        for page in ["intro.html", "index.html"] {
+            let html = &files[Path::new(page)];
+            assert!(html.contains(r##"<meta name="theme-color" content="#1f6feb">"##));
+            assert!(html.contains(r#"<link rel="manifest" href="/manifest.webmanifest">"#));
+        }
+
+        // A raster logo declares the size it has
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("img"))?;
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend([0, 0, 0, 192, 0, 0, 0, 96]);
+        fs::write(temp_dir.path().join("img/logo.png"), png)?;
+        config.book.logo = "/img/logo.png".to_string();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&web_manifest(&config, Some(temp_dir.path()))?)?;
+        assert_eq!(manifest["icons"][0]["src"], "img/logo.png");
+        assert_eq!(manifest["icons"][0]["sizes"], "192x96");
+        let manifest: serde_json::Value = serde_json::from_str(&web_manifest(&config, None)?)?;
+        assert!(manifest["icons"][0].get("sizes").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_offline_service_worker() -> Result<()> {
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro".to_string()),
+            (PathBuf::from("guide/setup.md"), "# Setup".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &BookConfig::default())?
+            .into_iter()
+            .collect();
+        assert!(!files.contains_key(Path::new("service-worker.js")));
+        assert!(!files[Path::new("intro.html")].contains("serviceWorker"));
+
+        let mut config = BookConfig::default();
+        config.output.html.offline = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        let worker = &files[Path::new("service-worker.js")];
+        assert!(worker.contains(r#"const PAGES = ["guide/setup.html","intro.html","index.html"];"#));
+        for page in ["intro.html", "guide/setup.html", "index.html"] {
+            assert!(files[Path::new(page)]
+                .contains(r#"navigator.serviceWorker.register("/service-worker.js")"#));
+        }
+
+        // Any change to a page renames the cache
+        let cache = |worker: &str| {
+            worker
+                .lines()
+                .find(|line| line.starts_with("const CACHE"))
+                .unwrap()
+                .to_string()
+        };
+        let mut edited = sources;
+        edited[0].1.push_str("\n\nMore.");
+        let files_after: BTreeMap<_, _> =
+            build_from_sources(edited, &config)?.into_iter().collect();
+        assert_ne!(
+            cache(worker),
+            cache(&files_after[Path::new("service-worker.js")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_small_assets() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        let templates = temp_dir.path().join("templates");
+        fs::create_dir_all(&input_dir)?;
+        fs::create_dir_all(templates.join("css"))?;
+        fs::write(templates.join("css/styles.css"), "body { color: red; }")?;
+        fs::write(templates.join("css/search.css"), "a {}\n".repeat(200))?;
+        fs::write(input_dir.join("intro.md"), "# Intro\n\n![Dot](dot.png)")?;
+        fs::write(input_dir.join("dot.png"), b"\x89PNG")?;
+
+        let mut config = BookConfig::default();
+        config.paths.templates = templates.display().to_string();
+        config.output.html.inline_assets_under = Some(512);
+        build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false)?;
+
+        let page = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(page.contains("<style>body { color: red; }</style>"));
+        assert!(!page.contains(r#"href="/css/styles.css""#));
+        assert!(page.contains(r#"<link rel="stylesheet" href="/css/search.css">"#));
+        assert!(page.contains(r#"src="data:image/png;base64,iVBORw==""#));
+        // Module scripts importing others keep their own URL
+        assert!(page.contains(r#"src="/js/search-init.js""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pretty_urls_relocate_links() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(
+            input_dir.join("index.md"),
+            "# Home\n\n[Setup](guide/setup.md)",
+        )?;
+        fs::write(
+            input_dir.join("guide/setup.md"),
+            "# Setup\n\n![Diagram](diagram.png)\n\n[Next](usage.html#steps) [Home](../index.md) [Web](https://example.com/a.html)",
+        )?;
+        fs::write(input_dir.join("guide/usage.md"), "# Usage")?;
+        fs::write(input_dir.join("guide/diagram.png"), b"png")?;
+
+        let mut config = BookConfig::default();
+        config.output.html.pretty_urls = true;
+        build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false)?;
+
+        let setup = fs::read_to_string(output_dir.join("guide/setup/index.html"))?;
+        // The image is copied beside the source and still found from the deeper page
+        assert!(setup.contains(r#"src="../diagram.png""#));
+        assert!(output_dir.join("guide/diagram.png").exists());
+        assert!(setup.contains(r#"href="../usage/#steps""#));
+        assert!(setup.contains(r#"href="../../""#));
+        assert!(setup.contains(r#"href="https://example.com/a.html""#));
+        // Navigation is addressed by directory
+        assert!(setup.contains(r#"href="/guide/usage/""#));
+
+        let home = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(home.contains(r#"href="guide/setup/""#));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_generate_index_disabled_keeps_readme_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("README.md"), "# Welcome\n\nStart here.")?;
+        fs::write(input_dir.join("chapter.md"), "# Chapter\n\nText.")?;
+
+        let mut config = BookConfig::default();
+        config.output.html.generate_index = false;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        // The README is rendered as a regular page at index.html, with no card grid
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("Start here."));
+        assert!(index.contains(r#"<article class="main-article" data-pagefind-body>"#));
+        assert!(!index.contains("card-grid"));
+        assert!(!output_dir.join("README.html").exists());
+
+        let chapter = fs::read_to_string(output_dir.join("chapter.html"))?;
+        assert!(chapter.contains(r#"href="/index.html""#));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_index_without_index_page_uses_book_title() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("chapter.md"), "# Chapter\n\nText.")?;
+
+        let mut config = BookConfig::default();
+        config.book.title = "Field Guide".to_string();
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        let html = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(html.contains("<title>Field Guide</title>"));
+        assert!(html.contains("<h1>Field Guide</h1>"));
+        assert!(!html.contains("Documentation"));
+
+        // Without a book title the generic heading remains
+        build(
+            &build_args(&input_dir, &output_dir),
+            &BookConfig::default(),
+            false,
+        )
+        .await?;
+        let html = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(html.contains("<h1>Documentation</h1>"));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_last_updated_from_mtime() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("chapter.md"), "# Chapter\n\nText.")?;
+        let modified = Timestamp::from_second(1_710_504_000)?;
+        fs::File::options()
+            .write(true)
+            .open(input_dir.join("chapter.md"))?
+            .set_modified(modified.into())?;
+
+        let mut config = BookConfig::default();
+        config.output.html.last_updated = LastUpdatedSource::Mtime;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        let html = fs::read_to_string(output_dir.join("chapter.html"))?;
+        let expected = modified.to_zoned(TimeZone::system()).strftime("%Y-%m-%d");
+        assert!(html.contains("Last updated:"));
+        assert!(html.contains(&format!(">{expected}</time>")));
+
+        config.output.html.last_updated = LastUpdatedSource::Off;
+        build(&build_args(&input_dir, &output_dir), &config, false).await?;
+
+        let html = fs::read_to_string(output_dir.join("chapter.html"))?;
+        assert!(!html.contains("Last updated:"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_updated_from_frontmatter() {
+        let mut frontmatter = Frontmatter::new();
+        frontmatter.insert("updated".to_string(), "2024-05-01".into());
+
+        let mut last_updated = LastUpdated::new(LastUpdatedSource::Frontmatter, None);
+        let date = last_updated
+            .for_page(Some(Path::new("page.md")), &frontmatter)
+            .expect("date from frontmatter");
+        let config = BookConfig::default();
+        assert_eq!(
+            DisplayDate::new(&date, &config).unwrap().display,
+            "2024-05-01"
+        );
 
-    let mut parts = Vec::new();
-    let mut last_pos = 0;
+        assert!(last_updated
+            .for_page(Some(Path::new("page.md")), &Frontmatter::new())
+            .is_none());
+    }
 
-    fn process_node(
-        node: &Node,
-        ss: &SyntaxSet,
-        content: &str,
-        parts: &mut Vec<String>,
-        last_pos: &mut usize,
-        config: &BookConfig,
-    ) -> Result<()> {
-        match node {
-            Node::Code(code) => {
-                if let Some(pos) = &code.position {
-                    if *last_pos < pos.start.offset {
-                        let text = &content[*last_pos..pos.start.offset];
-                        if !text.trim().is_empty() {
-                            let parse_options = match config.markdown.format {
-                                MarkdownFormat::Mdx => markdown::ParseOptions::mdx(),
-                                MarkdownFormat::Gfm => markdown::ParseOptions::gfm(),
-                                MarkdownFormat::Markdown => markdown::ParseOptions::default(),
-                            };
-
-                            let compile_options =
-                                if matches!(config.markdown.format, MarkdownFormat::Gfm) {
-                                    markdown::CompileOptions::gfm()
-                                } else {
-                                    markdown::CompileOptions::default()
-                                };
-
-                            let mut options = markdown::Options {
-                                parse: parse_options,
-                                compile: compile_options,
-                            };
-
-                            options.parse.constructs.frontmatter = config.markdown.frontmatter;
-                            options.parse.constructs.html_flow = config.output.html.allow_html;
-                            options.parse.constructs.html_text = config.output.html.allow_html;
-                            options.compile.allow_dangerous_html = config.output.html.allow_html;
-                            options.compile.allow_dangerous_protocol =
-                                config.output.html.allow_html;
-
-                            let temp_html = to_html_with_options(text, &options).map_err(|e| {
-                                anyhow::anyhow!("Markdown conversion error: {:?}", e)
-                            })?;
-                            parts.push(temp_html);
-                        }
-                    }
+    #[test]
+    fn test_display_date_format() -> Result<()> {
+        let date = jiff::civil::date(2024, 5, 1)
+            .at(9, 30, 0, 0)
+            .to_zoned(TimeZone::UTC)?;
+        let mut config = BookConfig::default();
 
-                    let highlighted = process_code_block(&code.value, code.lang.as_deref(), ss)?;
-                    parts.push(highlighted);
+        let iso = DisplayDate::new(&date, &config)?;
+        assert_eq!(iso.display, "2024-05-01");
+        assert_eq!(iso.datetime, "2024-05-01T09:30:00+00:00");
 
-                    *last_pos = pos.end.offset;
-                }
-            }
-            _ => {
-                if let Some(children) = node.children() {
-                    for child in children {
-                        process_node(child, ss, content, parts, last_pos, config)?;
-                    }
-                }
-            }
-        }
+        config.output.html.date_format = "%d %B %Y".to_string();
+        assert_eq!(DisplayDate::new(&date, &config)?.display, "01 May 2024");
         Ok(())
     }
 
-    process_node(&ast, ss, content, &mut parts, &mut last_pos, config)?;
+    #[test]
+    fn test_parse_date() {
+        let date = parse_date("2024-05-01T23:30:00+02:00").expect("RFC 3339 timestamp");
+        assert_eq!(date.timestamp().to_string(), "2024-05-01T21:30:00Z");
+        assert!(parse_date("2024-05-01").is_some());
+        assert!(parse_date("yesterday").is_none());
+    }
 
-    if last_pos < content.len() {
-        let remaining = &content[last_pos..];
-        if !remaining.trim().is_empty() {
-            let parse_options = match config.markdown.format {
-                MarkdownFormat::Mdx => markdown::ParseOptions::mdx(),
-                MarkdownFormat::Gfm => markdown::ParseOptions::gfm(),
-                MarkdownFormat::Markdown => markdown::ParseOptions::default(),
-            };
+    #[test]
+    fn test_optimize_images() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::create_dir_all(temp_dir.path().join("guide"))?;
+
+        // A PNG signature and IHDR chunk for a 640x480 image
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        fs::write(temp_dir.path().join("guide/diagram.png"), png)?;
+
+        let html = r#"<p><img src="diagram.png" alt="local" /><img src="https://example.com/remote.png" alt="remote" /><img src="missing.png" alt="missing" /></p>"#;
+        let result = optimize_images(html, Some(temp_dir.path()), Path::new("guide"));
+
+        assert!(result.contains(
+            r#"<img loading="lazy" width="640" height="480" src="diagram.png" alt="local" />"#
+        ));
+        assert!(result.contains(r#"<img loading="lazy" src="https://example.com/remote.png""#));
+        assert!(result.contains(r#"<img loading="lazy" src="missing.png""#));
+
+        // The generated index shows index.md just as optimized
+        let sources = vec![
+            (
+                PathBuf::from("index.md"),
+                "# Home\n\n![Cover](cover.png)".to_string(),
+            ),
+            (
+                PathBuf::from("guide.md"),
+                "# Guide\n\n![Map](map.png)".to_string(),
+            ),
+        ];
+        let mut config = BookConfig::default();
+        config.output.html.optimize_images = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(files[Path::new("guide.html")].contains(r#"<img loading="lazy" src="map.png""#));
+        assert!(files[Path::new("index.html")].contains(r#"<img loading="lazy" src="cover.png""#));
+        Ok(())
+    }
 
-            let compile_options = if matches!(config.markdown.format, MarkdownFormat::Gfm) {
-                markdown::CompileOptions::gfm()
-            } else {
-                markdown::CompileOptions::default()
-            };
+    #[test]
+    fn test_image_dimensions() {
+        let gif = b"GIF89a\x20\x00\x10\x00";
+        assert_eq!(image_dimensions(gif), Some((32, 16)));
+
+        // SOI, an APP0 segment, then a baseline SOF0 for 300x200
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00,
+            0xC8, 0x01, 0x2C,
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((300, 200)));
+
+        assert_eq!(image_dimensions(b"<svg></svg>"), None);
+    }
 
-            let mut options = markdown::Options {
-                parse: parse_options,
-                compile: compile_options,
-            };
+    #[test]
+    fn test_rewrite_attribute() {
+        let html = r#"<img src="a.png" data-src="b.png"><a href="x">x</a><img src="c.png">"#;
+        let result = rewrite_attribute(html, "src", |src| {
+            (src != "c.png").then(|| format!("new-{src}"))
+        });
+        assert_eq!(
+            result,
+            r#"<img src="new-a.png" data-src="b.png"><a href="x">x</a><img src="c.png">"#
+        );
+    }
 
-            options.parse.constructs.frontmatter = config.markdown.frontmatter;
-            options.parse.constructs.html_flow = config.output.html.allow_html;
-            options.parse.constructs.html_text = config.output.html.allow_html;
-            options.compile.allow_dangerous_html = config.output.html.allow_html;
-            options.compile.allow_dangerous_protocol = config.output.html.allow_html;
+    #[test]
+    fn test_build_from_sources() -> Result<()> {
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro\n\nWelcome.".to_string()),
+            (
+                PathBuf::from("guide/setup.md"),
+                "# Setup\n\nRun `make`.".to_string(),
+            ),
+        ];
+
+        let files: BTreeMap<_, _> = build_from_sources(sources, &BookConfig::default())?
+            .into_iter()
+            .collect();
+
+        assert!(files.contains_key(Path::new("intro.html")));
+        assert!(files.contains_key(Path::new("index.html")));
+        let setup = &files[Path::new("guide/setup.html")];
+        assert!(setup.contains("<h1>Setup</h1>"));
+        assert!(setup.contains("<code>make</code>"));
+        assert!(files[Path::new("intro.html")].contains("Welcome."));
 
-            parts.push(
-                to_html_with_options(remaining, &options)
-                    .map_err(|e| anyhow::anyhow!("Markdown conversion error: {:?}", e))?,
-            );
-        }
+        Ok(())
     }
 
-    Ok(parts.join(""))
-}
+    #[test]
+    fn test_duplicate_output_paths_rejected() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
 
-#[cfg(not(feature = "syntax-highlighting"))]
-fn process_markdown_basic(content: &str, config: &BookConfig) -> Result<String> {
-    let parse_options = match config.markdown.format {
-        MarkdownFormat::Mdx => markdown::ParseOptions::mdx(),
-        MarkdownFormat::Gfm => markdown::ParseOptions::gfm(),
-        MarkdownFormat::Markdown => markdown::ParseOptions::default(),
-    };
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup\n")?;
+        fs::write(input_dir.join("guide/setup.markdown"), "# Other setup\n")?;
+
+        let args = build_args(&input_dir, &output_dir);
+        let err = build_sync_impl_sync(&args, &BookConfig::default(), false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("guide/setup.markdown"), "{message}");
+        assert!(message.contains("guide/setup.md "), "{message}");
+        assert!(message.contains("guide/setup.html"), "{message}");
+        assert!(!output_dir.join("guide/setup.html").exists());
+
+        let sources = vec![
+            (PathBuf::from("Notes.md"), "# A\n".to_string()),
+            (PathBuf::from("notes.md"), "# B\n".to_string()),
+        ];
+        assert!(
+            collect_documents(sources, &BookConfig::default(), false, BTreeSet::new()).is_err()
+        );
 
-    let compile_options = if matches!(config.markdown.format, MarkdownFormat::Gfm) {
-        markdown::CompileOptions::gfm()
-    } else {
-        markdown::CompileOptions::default()
-    };
+        Ok(())
+    }
 
-    let mut options = markdown::Options {
-        parse: parse_options,
-        compile: compile_options,
-    };
+    #[test]
+    fn test_collect_pages() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
 
-    // Modify constructs for HTML and frontmatter
-    options.parse.constructs.frontmatter = config.markdown.frontmatter;
-    options.parse.constructs.html_flow = config.output.html.allow_html;
-    options.parse.constructs.html_text = config.output.html.allow_html;
-    options.compile.allow_dangerous_html = config.output.html.allow_html;
-    options.compile.allow_dangerous_protocol = config.output.html.allow_html;
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(input_dir.join("intro.md"), "# Introduction\n")?;
+        fs::write(input_dir.join("guide/install.md"), "# Installing\n")?;
+        fs::write(input_dir.join("guide/usage.md"), "# Usage\n")?;
 
-    to_html_with_options(content, &options)
-        .map_err(|e| anyhow::anyhow!("Markdown conversion error: {:?}", e))
-}
+        let sections = collect_pages(&input_dir, &BookConfig::default())?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::BookConfig;
-    use std::fs;
-    use tempfile::TempDir;
+        let titles: Vec<_> = sections.iter().map(|s| s.title.as_deref()).collect();
+        assert_eq!(titles, [Some("Guide"), Some("guide")]);
+        assert_eq!(sections[0].pages[0].path, "/intro.html");
+        let guide_paths: Vec<_> = sections[1].pages.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(guide_paths, ["/guide/install.html", "/guide/usage.html"]);
+        assert!(sections.iter().all(|s| !s.is_active));
 
-    // Get project root directory (CARGO_MANIFEST_DIR) for absolute path resolution
-    fn project_root() -> std::path::PathBuf {
-        std::path::PathBuf::from(
-            std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
-        )
+        Ok(())
     }
 
     #[test]
-    fn test_extract_title_h1() {
-        let markdown = "# Main Title\n\nSome content here.";
-        let title = extract_title(markdown);
-        assert_eq!(title, Some("Main Title".to_string()));
+    fn test_directory_links() -> Result<()> {
+        let documents = vec![
+            (
+                PathBuf::from("intro.md"),
+                "# Intro\n\n[API](api/) [API](api#auth) [Guide](guide/) \
+                 [Missing](missing/) [Deep](deep) [Home](/) [Setup](guide/setup.md)"
+                    .to_string(),
+            ),
+            (PathBuf::from("api/index.md"), "# API".to_string()),
+            (PathBuf::from("guide/README.md"), "# Guide".to_string()),
+            (
+                PathBuf::from("guide/setup.md"),
+                "# Setup\n\n[Up](../api)".to_string(),
+            ),
+            (PathBuf::from("deep/more/page.md"), "# Page".to_string()),
+        ];
+        let config = BookConfig::default();
+        let collection = collect_documents(documents, &config, false, BTreeSet::new())?;
+        let options = RenderOptions {
+            input: None,
+            format: OutputFormat::Html,
+            single_file: false,
+            watch_enabled: false,
+            deterministic: false,
+            keep_going: false,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+        };
+        let rendered = render_book(&load_templates(&config)?, &config, collection, &options)?;
+        let files: BTreeMap<_, _> = rendered.files.into_iter().collect();
+
+        let intro = &files[Path::new("intro.html")];
+        assert!(intro.contains("<a href=\"api/index.html\">"));
+        assert!(intro.contains("<a href=\"api/index.html#auth\">"));
+        assert!(intro.contains("<a href=\"guide/README.html\">"));
+        assert!(intro.contains("<a href=\"missing/\">"));
+        assert!(intro.contains("<a href=\"deep\">"));
+        assert!(intro.contains("<a href=\"/index.html\">"));
+        assert!(intro.contains("<a href=\"guide/setup.html\">"));
+        assert!(files[Path::new("guide/setup.html")].contains("<a href=\"../api/index.html\">"));
+
+        let missing: Vec<_> = rendered
+            .warnings
+            .iter()
+            .filter_map(|warning| match warning {
+                BuildWarning::MissingDirectoryIndex { file, link } => {
+                    Some((file.to_str().unwrap(), link.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, [("intro.md", "deep"), ("intro.md", "missing/")]);
+
+        Ok(())
     }
 
     #[test]
-    fn test_extract_title_h2() {
-        let markdown = "Some text\n\n## Section Title\n\nContent";
-        let title = extract_title(markdown);
-        // extract_title only looks for H1 headings, not H2
-        assert_eq!(title, None);
+    fn test_require_alt_text() -> Result<()> {
+        let documents = vec![(
+            PathBuf::from("intro.md"),
+            "# Intro\n\n![](diagram.png)\n\n![Described](photo.png)\n\n<img src=\"logo.png\">\n"
+                .to_string(),
+        )];
+        let options = RenderOptions {
+            input: None,
+            format: OutputFormat::Html,
+            single_file: false,
+            watch_enabled: false,
+            deterministic: false,
+            keep_going: false,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+        };
+        let render = |config: &BookConfig| {
+            let collection = collect_documents(documents.clone(), config, false, BTreeSet::new())?;
+            render_book(&load_templates(config)?, config, collection, &options)
+        };
+
+        let mut config = BookConfig::default();
+        config.output.html.allow_html = true;
+        let warnings: Vec<_> = render(&config)?
+            .warnings
+            .into_iter()
+            .filter(|warning| matches!(warning, BuildWarning::MissingAltText { .. }))
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "intro.md: image `diagram.png` has no alt text",
+                "intro.md: image `logo.png` has no alt text",
+            ]
+        );
+
+        config.output.html.require_alt_text = AltTextPolicy::Strict;
+        let error = BuildError::from(render(&config).err().expect("strict build fails"));
+        let BuildError::Markdown { path, message } = error else {
+            panic!("expected a markdown error, got {error}");
+        };
+        assert_eq!(path, Path::new("intro.md"));
+        assert!(message.contains("diagram.png, logo.png"), "{message}");
+
+        config.output.html.require_alt_text = AltTextPolicy::Off;
+        assert!(!render(&config)?
+            .warnings
+            .iter()
+            .any(|warning| matches!(warning, BuildWarning::MissingAltText { .. })));
+
+        Ok(())
     }
 
     #[test]
-    fn test_extract_title_no_heading() {
-        let markdown = "Just some regular text without headings.";
-        let title = extract_title(markdown);
-        assert_eq!(title, None);
+    fn test_page_footer() -> Result<()> {
+        let sources = vec![
+            (
+                PathBuf::from("intro.md"),
+                "# Intro\n\nThe body.".to_string(),
+            ),
+            (PathBuf::from("license.md"), "# License".to_string()),
+            (PathBuf::from("guide/setup.md"), "# Setup".to_string()),
+        ];
+        let mut config = BookConfig::default();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(!files[Path::new("intro.html")].contains("page-footer"));
+
+        config.output.html.page_footer =
+            Some("*{title}* © {year} Example Corp, [CC BY 4.0](license.md)".to_string());
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        let intro = &files[Path::new("intro.html")];
+        let year = build_date(None, false)?.year();
+        let notice = format!(
+            "<p><em>Intro</em> © {year} Example Corp, <a href=\"/license.html\">CC BY 4.0</a></p>"
+        );
+        let footer = intro.find(&notice).expect("page footer rendered");
+        assert!(intro.find("<p>The body.</p>").unwrap() < footer);
+        assert!(footer < intro.find("<div class=\"nav-footer\"").unwrap());
+
+        // Links resolve from the book root, whatever the page's directory
+        let setup = &files[Path::new("guide/setup.html")];
+        assert!(setup.contains("<em>Setup</em>"));
+        assert!(setup.contains("<a href=\"/license.html\">CC BY 4.0</a>"));
+
+        // The landing page rendered from index.md carries it too
+        let sources = vec![
+            (PathBuf::from("index.md"), "# Home\n\nWelcome.".to_string()),
+            (PathBuf::from("license.md"), "# License".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        let index = &files[Path::new("index.html")];
+        let footer = index
+            .find("<p><em>Home</em> © ")
+            .expect("index footer rendered");
+        assert!(index.find("<p>Welcome.</p>").unwrap() < footer);
+
+        Ok(())
     }
 
     #[test]
-    fn test_extract_title_complex_markup() {
-        let markdown = "# Title with **bold** and *italic*";
-        let title = extract_title(markdown);
-        assert_eq!(title, Some("Title with **bold** and *italic*".to_string()));
+    fn test_blog_listing() -> Result<()> {
+        let post = |date: &str, title: &str| {
+            format!("---\ndate: {date}\nauthor: Ada\n---\n# {title}\n\nAbout {title}.")
+        };
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro".to_string()),
+            (PathBuf::from("blog/alpha.md"), post("2024-03-01", "Alpha")),
+            (PathBuf::from("blog/beta.md"), post("2024-05-20", "Beta")),
+            (PathBuf::from("blog/gamma.md"), post("2023-12-31", "Gamma")),
+        ];
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        config.output.html.date_format = "%Y-%m-%d".to_string();
+        config.blog.dir = Some("blog".to_string());
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+
+        let listing = &files[Path::new("blog/index.html")];
+        assert!(listing.contains("<h1>Blog</h1>"));
+        let positions: Vec<_> = ["beta", "alpha", "gamma"]
+            .iter()
+            .map(|post| {
+                listing
+                    .find(&format!("<a href=\"/blog/{post}.html\">"))
+                    .unwrap_or_else(|| panic!("listing links {post}"))
+            })
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(listing.contains("About Beta."));
+
+        // Each post shows its date and author, and the sidebar follows the listing
+        let beta = &files[Path::new("blog/beta.html")];
+        assert!(beta.contains(">2024-05-20</time>"));
+        assert!(beta.contains("<span class=\"post-author\">Ada</span>"));
+        assert!(!files[Path::new("intro.html")].contains("post-meta"));
+        let sidebar = |page: &str| beta.find(&format!("href=\"/blog/{page}.html\" ")).unwrap();
+        assert!(sidebar("beta") < sidebar("alpha") && sidebar("alpha") < sidebar("gamma"));
+
+        config.blog.dir = None;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(!files.contains_key(Path::new("blog/index.html")));
+
+        Ok(())
     }
 
     #[test]
-    fn test_extract_title_first_heading_wins() {
-        let markdown = "# First Title\n\n## Second Title\n\n# Third Title";
-        let title = extract_title(markdown);
-        assert_eq!(title, Some("First Title".to_string()));
+    fn test_tag_pages() -> Result<()> {
+        let sources = vec![
+            (
+                PathBuf::from("intro.md"),
+                "---\ntags: [Rust, Web Assembly]\n---\n# Intro".to_string(),
+            ),
+            (
+                PathBuf::from("guide/setup.md"),
+                "---\ntags: rust\n---\n# Setup".to_string(),
+            ),
+            (PathBuf::from("guide/faq.md"), "# FAQ".to_string()),
+        ];
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+
+        // Spellings of a tag share a page, named as the first page in book order has it
+        let rust = &files[Path::new("tags/rust.html")];
+        assert!(rust.contains("<h1>rust</h1>"));
+        assert!(rust.contains("<li><a href=\"/intro.html\">Intro</a></li>"));
+        assert!(rust.contains("<li><a href=\"/guide/setup.html\">Setup</a></li>"));
+        assert!(!rust.contains("<li><a href=\"/guide/faq.html\">"));
+
+        let cloud = &files[Path::new("tags/index.html")];
+        assert!(cloud.contains(
+            "<li><a href=\"/tags/rust.html\">rust</a> <span class=\"tag-count\">2</span></li>"
+        ));
+        assert!(cloud.contains("<a href=\"/tags/web-assembly.html\">Web Assembly</a>"));
+
+        let setup = &files[Path::new("guide/setup.html")];
+        assert!(setup.contains("<li><a href=\"/tags/rust.html\">rust</a></li>"));
+        assert!(!files[Path::new("guide/faq.html")].contains("page-tags"));
+
+        // A landing page from index.md lists its tags like any page
+        let sources = vec![
+            (
+                PathBuf::from("index.md"),
+                "---\ntags: [Rust]\n---\n# Home".to_string(),
+            ),
+            (PathBuf::from("guide.md"), "# Guide".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(files[Path::new("index.html")]
+            .contains("<li><a href=\"/tags/rust.html\">Rust</a></li>"));
+
+        assert_eq!(slugify("  Rust & WebAssembly! "), "rust-webassembly");
+        Ok(())
     }
 
     #[test]
-    fn test_convert_md_links_to_html() {
-        // Test basic .md to .html conversion
-        let html = r#"<a href="page.md">Link</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(result, r#"<a href="page.html">Link</a>"#);
-
-        // Test nested path
-        let html = r#"<a href="dir/subdir/page.md">Link</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(result, r#"<a href="dir/subdir/page.html">Link</a>"#);
-
-        // Test multiple links
-        let html = r#"<a href="page1.md">Link1</a> and <a href="page2.md">Link2</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(
-            result,
-            r#"<a href="page1.html">Link1</a> and <a href="page2.html">Link2</a>"#
-        );
+    fn test_banner() -> Result<()> {
+        let sources = vec![(PathBuf::from("intro.md"), "# Intro".to_string())];
+        let mut config = BookConfig::default();
 
-        // Test that external links are NOT converted
-        let html = r#"<a href="https://example.com/page.md">External</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(
-            result,
-            r#"<a href="https://example.com/page.md">External</a>"#
-        );
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(!files[Path::new("intro.html")].contains("site-banner"));
 
-        // Test http:// links are not converted
-        let html = r#"<a href="http://example.com/page.md">External</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(
-            result,
-            r#"<a href="http://example.com/page.md">External</a>"#
-        );
+        config.output.html.banner = Some(BannerConfig {
+            text: "Version 2.0 is <out>".to_string(),
+            link: Some("/changelog.html".to_string()),
+            dismissible: true,
+        });
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        for page in ["intro.html", "index.html"] {
+            let html = &files[Path::new(page)];
+            assert!(html.contains(
+                "<div class=\"site-banner\" data-banner=\"Version 2.0 is &lt;out&gt;\">"
+            ));
+            assert!(html.contains(">Version 2.0 is &lt;out&gt;</a>"));
+            assert!(html.contains("site-banner-dismiss"));
+        }
 
-        // Test that .html links are not modified
-        let html = r#"<a href="page.html">Link</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(result, r#"<a href="page.html">Link</a>"#);
+        // Nothing to announce
+        config.output.html.banner = Some(BannerConfig::default());
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(!files[Path::new("intro.html")].contains("site-banner"));
 
-        // Test mixed internal and external links
-        let html = r#"<a href="local.md">Local</a> and <a href="https://ext.com/file.md">Ext</a>"#;
-        let result = convert_md_links_to_html(html);
-        assert_eq!(
-            result,
-            r#"<a href="local.html">Local</a> and <a href="https://ext.com/file.md">Ext</a>"#
-        );
+        Ok(())
     }
 
     #[test]
-    fn test_args_default_values() {
-        use clap::Parser;
+    fn test_github_pages_nojekyll() -> Result<()> {
+        let sources = vec![(PathBuf::from("intro.md"), "# Intro".to_string())];
+        let mut config = BookConfig::default();
 
-        // Test that we can parse minimal required args
-        let args = Args::try_parse_from(["md-book", "-i", "input", "-o", "output"]).unwrap();
-        assert_eq!(args.input, "input");
-        assert_eq!(args.output, "output");
-        assert_eq!(args.config, None);
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(!files.contains_key(Path::new(".nojekyll")));
 
-        #[cfg(feature = "watcher")]
-        assert!(!args.watch);
+        config.output.html.github_pages = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert_eq!(files[Path::new(".nojekyll")], "");
 
-        #[cfg(feature = "server")]
-        {
-            assert!(!args.serve);
-            assert_eq!(args.port, 3000);
-        }
+        Ok(())
     }
 
-    #[cfg(feature = "server")]
     #[test]
-    fn test_args_with_server_options() {
-        use clap::Parser;
+    fn test_llms_txt() -> Result<()> {
+        let sources = vec![
+            (
+                PathBuf::from("intro.md"),
+                "# Intro\n\nStart *here*.".to_string(),
+            ),
+            (
+                PathBuf::from("guide/setup.md"),
+                "---\ndescription: Installing the tool\n---\n# Setup\n\nRun it.".to_string(),
+            ),
+        ];
+        let mut config = BookConfig::default();
+        config.book.title = "Handbook".to_string();
+        config.book.description = Some("All about the tool".to_string());
+        config.book.base_url = Some("https://example.com/docs/".to_string());
+        config.markdown.frontmatter = true;
 
-        let args = Args::try_parse_from([
-            "md-book", "-i", "input", "-o", "output", "--serve", "--port", "8080",
-        ])
-        .unwrap();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(!files.contains_key(Path::new("llms.txt")));
+        assert!(!files.contains_key(Path::new("llms-full.txt")));
 
-        assert!(args.serve);
-        assert_eq!(args.port, 8080);
+        config.output.html.llms_txt = true;
+        config.output.html.llms_full_txt = true;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert_eq!(
+            files[Path::new("llms.txt")],
+            "# Handbook\n\n> All about the tool\n\n\
+             ## Guide\n\n- [Intro](https://example.com/docs/intro.html)\n\n\
+             ## guide\n\n- [Setup](https://example.com/docs/guide/setup.html): Installing the tool\n"
+        );
+        let full = &files[Path::new("llms-full.txt")];
+        assert!(full
+            .contains("# Intro\n\nURL: https://example.com/docs/intro.html\n\nIntro\nStart here."));
+        assert!(full.contains("# Setup\n\nURL: https://example.com/docs/guide/setup.html"));
+        assert!(!full.contains("Installing the tool"));
+
+        Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
     #[test]
-    fn test_process_markdown_basic_default() -> Result<()> {
-        let config = BookConfig::default();
-        let markdown = "# Hello World\n\nThis is **bold** text.";
+    fn test_robots() -> Result<()> {
+        let sources = vec![(PathBuf::from("intro.md"), "# Intro\n".to_string())];
 
-        let html = process_markdown_basic(markdown, &config)?;
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &BookConfig::default())?
+            .into_iter()
+            .collect();
+        assert!(!files.contains_key(Path::new("robots.txt")));
+        assert!(!files[Path::new("intro.html")].contains("name=\"robots\""));
 
-        assert!(html.contains("<h1>Hello World</h1>"));
-        assert!(html.contains("<strong>bold</strong>"));
+        let mut config = BookConfig::default();
+        config.book.noindex = true;
+        config.output.html.robots.enabled = true;
+        config.output.html.robots.allow = vec!["/public/".to_string()];
+        config.output.html.robots.disallow = vec!["/".to_string()];
+
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert_eq!(
+            files[Path::new("robots.txt")],
+            "User-agent: *\nAllow: /public/\nDisallow: /\n"
+        );
+        for page in ["intro.html", "index.html"] {
+            assert!(files[Path::new(page)].contains("<meta name=\"robots\" content=\"noindex\">"));
+        }
+
+        assert_eq!(
+            robots_txt(&RobotsConfig::default()),
+            "User-agent: *\nDisallow:\n"
+        );
 
         Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
     #[test]
-    fn test_process_markdown_basic_gfm() -> Result<()> {
+    fn test_search_excluded_pages() -> Result<()> {
         let mut config = BookConfig::default();
-        config.markdown.format = MarkdownFormat::Gfm;
+        config.markdown.frontmatter = true;
+        let sources = vec![
+            (
+                PathBuf::from("guide.md"),
+                "# Guide\n\nSearch me.".to_string(),
+            ),
+            (
+                PathBuf::from("legal.md"),
+                "---\nsearch: false\n---\n# Legal\n".to_string(),
+            ),
+            (
+                PathBuf::from("old.md"),
+                "---\nnoindex: true\n---\n# Old\n".to_string(),
+            ),
+        ];
+
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+
+        let ignored = "<body data-pagefind-ignore=\"all\">";
+        assert!(files[Path::new("legal.html")].contains(ignored));
+        assert!(files[Path::new("old.html")].contains(ignored));
+        assert!(!files[Path::new("guide.html")].contains("data-pagefind-ignore=\"all\""));
 
-        let markdown = "# GFM Test\n\n~~strikethrough~~\n\n- [ ] Task item";
+        Ok(())
+    }
 
-        let html = process_markdown_basic(markdown, &config)?;
+    #[test]
+    fn test_search_indexes_content_region() -> Result<()> {
+        let sources = vec![(
+            PathBuf::from("guide.md"),
+            "# Guide\n\nSearch me.".to_string(),
+        )];
 
-        assert!(html.contains("<h1>GFM Test</h1>"));
-        assert!(html.contains("strikethrough"));
+        let mut config = BookConfig::default();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        let guide = &files[Path::new("guide.html")];
+        assert!(guide.contains("<article class=\"main-article\" data-pagefind-body>"));
+        assert!(guide.contains("<header class=\"site-header\" data-pagefind-ignore>"));
+        assert!(guide
+            .contains("<nav class=\"sidebar-nav\" aria-label=\"Chapters\" data-pagefind-ignore>"));
+        assert!(guide.contains("<footer class=\"site-footer\" data-pagefind-ignore>"));
+        assert!(guide.contains("<div class=\"nav-footer\" data-pagefind-ignore>"));
+
+        // A configured root selector takes over from the built-in content region
+        config.output.html.search.root_selector = Some("main".to_string());
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        let guide = &files[Path::new("guide.html")];
+        assert!(!guide.contains("data-pagefind-body"));
+        assert!(guide.contains("<header class=\"site-header\" data-pagefind-ignore>"));
 
         Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
     #[test]
-    fn test_process_markdown_basic_mdx() -> Result<()> {
+    fn test_skip_link_and_current_page() -> Result<()> {
+        let sources = vec![
+            (PathBuf::from("intro.md"), "# Intro".to_string()),
+            (PathBuf::from("guide/setup.md"), "# Setup".to_string()),
+        ];
+        let files: BTreeMap<_, _> = build_from_sources(sources, &BookConfig::default())?
+            .into_iter()
+            .collect();
+
+        let setup = &files[Path::new("guide/setup.html")];
+        let skip_link = "<a class=\"skip-link\" href=\"#main-content\">Skip to content</a>";
+        assert!(setup.contains(skip_link));
+        assert!(setup.contains("<main id=\"main-content\""));
+        assert!(setup.contains(
+            "href=\"/guide/setup.html\" \n                   class=\"active\" aria-current=\"page\">"
+        ));
+        assert_eq!(setup.matches("aria-current=\"page\"").count(), 1);
+        assert!(!files[Path::new("intro.html")]
+            .contains("href=\"/guide/setup.html\" \n                   class=\"active\""));
+
+        let index = &files[Path::new("index.html")];
+        assert!(index.contains(skip_link));
+        assert!(index.contains("<main id=\"main-content\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_footer_version() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("guide.md"), "# Guide")?;
+
+        // The temp dir is not a checkout, so the commit is left out rather than failing
+        assert_eq!(git_short_sha(&input_dir), None);
         let mut config = BookConfig::default();
-        config.markdown.format = MarkdownFormat::Mdx;
+        config.book.version = Some("v1.2.0".to_string());
+        config.output.html.git_commit = true;
+        build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false)?;
 
-        let markdown = "# MDX Test\n\nThis is **bold** text.";
+        let guide = fs::read_to_string(output_dir.join("guide.html"))?;
+        assert!(guide.contains(r#"<span class="footer-version">v1.2.0</span>"#));
+        assert!(!guide.contains("footer-commit"));
 
-        let html = process_markdown_basic(markdown, &config)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("guide.md"), "# Guide")?;
 
-        assert!(html.contains("<h1>MDX Test</h1>"));
-        assert!(html.contains("<strong>bold</strong>"));
+        let mut config = BookConfig::default();
+        config.build.file_mode = Some("0640".to_string());
+        config.build.dir_mode = Some("0o750".to_string());
+        build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false)?;
+
+        let mode =
+            |path: &Path| -> Result<u32> { Ok(fs::metadata(path)?.permissions().mode() & 0o7777) };
+        assert_eq!(mode(&output_dir.join("guide.html"))?, 0o640);
+        assert_eq!(mode(&output_dir.join("js/search-config.json"))?, 0o640);
+        assert_eq!(mode(&output_dir)?, 0o750);
+        assert_eq!(mode(&output_dir.join("components"))?, 0o750);
+
+        config.build.file_mode = Some("0999".to_string());
+        let err =
+            build_sync_impl_sync(&build_args(&input_dir, &output_dir), &config, false).unwrap_err();
+        assert!(err.to_string().contains("Invalid build.file_mode `0999`"));
 
         Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
     #[test]
-    fn test_process_markdown_basic_with_html_allowed() -> Result<()> {
-        let mut config = BookConfig::default();
-        config.output.html.allow_html = true;
+    fn test_deterministic_builds_are_identical() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
 
-        let markdown = "# Test\n\n<div>Raw HTML</div>";
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::write(
+            input_dir.join("intro.md"),
+            "# Intro\n\n```rust\nfn main() {}\n```",
+        )?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup\n\nText.")?;
 
-        let html = process_markdown_basic(markdown, &config)?;
+        let mut config = BookConfig::default();
+        config.output.html.print_page = true;
+        config.output.html.last_updated = LastUpdatedSource::Mtime;
+
+        let mut outputs = Vec::new();
+        for name in ["first", "second"] {
+            let output_dir = temp_dir.path().join(name);
+            let mut args = build_args(&input_dir, &output_dir);
+            args.deterministic = true;
+            build_sync_impl_sync(&args, &config, false)?;
+
+            let mut files = BTreeMap::new();
+            for entry in WalkDir::new(&output_dir) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    let rel_path = entry.path().strip_prefix(&output_dir)?.to_path_buf();
+                    files.insert(rel_path, fs::read(entry.path())?);
+                }
+            }
+            outputs.push(files);
+        }
 
-        assert!(html.contains("<div>Raw HTML</div>"));
+        assert!(outputs[0].contains_key(Path::new("intro.html")));
+        assert_eq!(outputs[0], outputs[1]);
 
         Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
+    #[cfg(feature = "parallel")]
     #[test]
-    fn test_process_markdown_basic_with_html_disallowed() -> Result<()> {
-        let config = BookConfig::default();
+    fn test_single_job_build_matches_parallel_build() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
 
-        let markdown = "# Test\n\n<div>Raw HTML</div>";
+        fs::create_dir_all(input_dir.join("guide"))?;
+        for page in 0..8 {
+            fs::write(
+                input_dir.join(format!("guide/page{page}.md")),
+                format!("# Page {page}\n\n```rust\nfn page_{page}() {{}}\n```"),
+            )?;
+        }
+        fs::write(input_dir.join("intro.md"), "# Intro\n\nText.")?;
 
-        let html = process_markdown_basic(markdown, &config)?;
+        let config = BookConfig::default();
+        let mut outputs = Vec::new();
+        for jobs in [None, NonZeroUsize::new(1)] {
+            let output_dir = temp_dir.path().join(format!("out{jobs:?}"));
+            let mut args = build_args(&input_dir, &output_dir);
+            args.deterministic = true;
+            args.jobs = jobs;
+            assert_eq!(build_sync_impl_sync(&args, &config, false)?, 9);
+            outputs.push((
+                fs::read_to_string(output_dir.join("guide/page3.html"))?,
+                fs::read_to_string(output_dir.join("intro.html"))?,
+            ));
+        }
 
-        // HTML should be escaped or stripped when not allowed
-        assert!(!html.contains("<div>Raw HTML</div>"));
+        assert!(outputs[1].0.contains("page_3"));
+        assert!(outputs[1].1.contains("href=\"/guide/page0.html\""));
+        assert_eq!(outputs[0], outputs[1]);
 
         Ok(())
     }
 
-    #[cfg(not(feature = "syntax-highlighting"))]
+    #[cfg(feature = "parallel")]
     #[test]
-    fn test_process_markdown_basic_with_frontmatter() -> Result<()> {
-        let mut config = BookConfig::default();
-        config.markdown.frontmatter = true;
+    fn test_args_jobs() {
+        use clap::Parser;
 
-        let markdown = "---\ntitle: Test\n---\n\n# Hello World";
+        let args =
+            Args::try_parse_from(["md-book", "-i", "in", "-o", "out", "--jobs", "2"]).unwrap();
+        assert_eq!(args.jobs, NonZeroUsize::new(2));
 
-        let html = process_markdown_basic(markdown, &config)?;
+        for invalid in ["0", "-1", "many"] {
+            assert!(
+                Args::try_parse_from(["md-book", "-i", "in", "-o", "out", "--jobs", invalid])
+                    .is_err()
+            );
+        }
+    }
 
-        assert!(html.contains("<h1>Hello World</h1>"));
-        // Frontmatter should be processed/removed from output
-        assert!(!html.contains("---"));
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_serve_subcommand() {
+        let parse = |argv: &[&str]| Cli::try_parse_from(argv).map(Cli::into_args);
 
-        Ok(())
+        let args = parse(&[
+            "md-book", "serve", "-i", "src", "-o", "book", "--port", "4000",
+        ])
+        .unwrap();
+        assert_eq!(
+            (args.input.as_deref(), args.output.as_deref()),
+            (Some("src"), Some("book"))
+        );
+        assert!(args.serve);
+        assert_eq!(args.port, 4000);
+        #[cfg(feature = "watcher")]
+        {
+            assert!(!args.watch);
+            assert!(
+                parse(&["md-book", "serve", "-i", "src", "-o", "book", "--watch"])
+                    .unwrap()
+                    .watch
+            );
+        }
+
+        // The flags keep working without the subcommand
+        let args = parse(&["md-book", "-i", "src", "-o", "book"]).unwrap();
+        assert!(!args.serve);
+        assert_eq!(args.port, 3000);
+        assert!(
+            parse(&["md-book", "-i", "src", "-o", "book", "--serve"])
+                .unwrap()
+                .serve
+        );
+
+        // The output directory may be left to book.toml
+        let args = parse(&["md-book", "serve", "-i", "src"]).unwrap();
+        assert_eq!(args.output, None);
+        assert!(args.serve);
+        assert!(parse(&["md-book", "-i", "src", "-o", "book", "serve"]).is_err());
     }
 
     #[test]
-    #[ignore = "MathJax support not implemented yet"]
-    fn test_process_markdown_with_mathjax() -> Result<()> {
-        let mut config = BookConfig::default();
-        config.output.html.mathjax_support = true;
+    fn test_lint_subcommand() {
+        let args = Cli::try_parse_from(["md-book", "lint", "-i", "docs"])
+            .map(Cli::into_args)
+            .unwrap();
+        assert!(args.lint);
+        assert_eq!(args.input.as_deref(), Some("docs"));
+
+        let args = Cli::try_parse_from(["md-book", "-i", "docs"])
+            .map(Cli::into_args)
+            .unwrap();
+        assert!(!args.lint);
+    }
 
-        let markdown = "# Math Test\n\n$$E = mc^2$$";
+    #[test]
+    fn test_strict_fails_on_warnings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(
+            input_dir.join("intro.md"),
+            "# Intro\n\nSee [the API](api/).",
+        )?;
 
-        // Test with basic markdown processing (will work regardless of syntax highlighting feature)
-        let html = markdown::to_html(markdown);
+        let config = BookConfig::default();
+        let mut args = build_args(&input_dir, &output_dir);
+        assert_eq!(build_sync_impl_sync(&args, &config, false)?, 1);
 
-        // When implemented, should contain MathJax markup
-        assert!(html.contains("E = mc^2"));
+        args.strict = true;
+        let error = build_sync_impl_sync(&args, &config, false).unwrap_err();
+        assert!(matches!(
+            BuildError::from(error),
+            BuildError::Strict { warnings: 1 }
+        ));
+
+        // Nothing to warn about
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
+        assert_eq!(build_sync_impl_sync(&args, &config, false)?, 1);
 
         Ok(())
     }
 
     #[test]
-    fn test_page_data_serialization() -> Result<()> {
-        let page_data = PageData {
-            title: "Test Page".to_string(),
-            content: "<h1>Test</h1>".to_string(),
-            sections: vec![Section {
-                title: "Section 1".to_string(),
-                pages: vec![PageInfo {
-                    title: "Page 1".to_string(),
-                    path: "/page1".to_string(),
-                }],
-            }],
-            previous: Some(PageInfo {
-                title: "Previous".to_string(),
-                path: "/prev".to_string(),
-            }),
-            next: None,
-        };
+    fn test_clean_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
 
-        let serialized = serde_json::to_string(&page_data)?;
-        assert!(serialized.contains("Test Page"));
-        assert!(serialized.contains("Section 1"));
-        assert!(serialized.contains("/page1"));
+        let mut config = BookConfig::default();
+        let mut args = build_args(&input_dir, &output_dir);
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(output_dir.join(OUTPUT_MARKER).exists());
+        fs::create_dir_all(output_dir.join("old"))?;
+        fs::write(output_dir.join("removed.html"), "stale")?;
+        fs::write(output_dir.join("old/page.html"), "stale")?;
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(output_dir.join("removed.html").exists());
+        assert!(output_dir.join("old/page.html").exists());
+
+        args.clean = true;
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(!output_dir.join("removed.html").exists());
+        assert!(!output_dir.join("old").exists());
+        assert!(output_dir.join("intro.html").exists());
+
+        // The config default does the same
+        args.clean = false;
+        config.build.clean_before = true;
+        fs::write(output_dir.join("removed.html"), "stale")?;
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(!output_dir.join("removed.html").exists());
+
+        // Never delete the sources
+        let args = build_args(&input_dir, &input_dir);
+        let error = build_sync_impl_sync(&args, &config, false).unwrap_err();
+        assert!(error.to_string().contains("Refusing to clean"));
+        assert!(input_dir.join("intro.md").exists());
+
+        // Nor anything above them
+        let args = build_args(&input_dir, temp_dir.path());
+        let error = build_sync_impl_sync(&args, &config, false).unwrap_err();
+        assert!(error.to_string().contains("it contains"));
+        assert!(input_dir.join("intro.md").exists());
+
+        // Nor a directory of someone else's files
+        let elsewhere = temp_dir.path().join("elsewhere");
+        fs::create_dir_all(&elsewhere)?;
+        fs::write(elsewhere.join("notes.txt"), "keep")?;
+        let args = build_args(&input_dir, &elsewhere);
+        let error = build_sync_impl_sync(&args, &config, false).unwrap_err();
+        assert!(error.to_string().contains("md-book did not create it"));
+        assert!(elsewhere.join("notes.txt").exists());
+
+        // Nor the filesystem root
+        let dry_run = OutputFs {
+            dry_run: true,
+            ..OutputFs::default()
+        };
+        let error = clean_output(Path::new("/"), &[], dry_run).unwrap_err();
+        assert!(error.to_string().contains("filesystem root"));
 
         Ok(())
     }
 
-    #[cfg(feature = "syntax-highlighting")]
     #[test]
-    fn test_process_code_block_rust() -> Result<()> {
-        use syntect::parsing::SyntaxSet;
-
-        let ss = SyntaxSet::load_defaults_newlines();
-        let code = "fn main() {\n    println!(\"Hello, world!\");\n}";
-
-        let highlighted = process_code_block(code, Some("rust"), &ss)?;
+    fn test_build_manifest_in_cache_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        let cache_dir = temp_dir.path().join("state");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro")?;
+        fs::write(input_dir.join("usage.md"), "# Usage")?;
 
-        assert!(highlighted.contains("<pre"));
-        // Syntax highlighting behavior may vary, just check basic structure
-        assert!(!highlighted.is_empty());
+        let mut config = BookConfig::default();
+        config.build.cache_dir = Some(cache_dir.to_string_lossy().to_string());
+        let mut args = build_args(&input_dir, &output_dir);
+        args.cache_dir = None;
+        build_sync_impl_sync(&args, &config, false)?;
+
+        let manifest = BuildManifest::load(&cache_dir);
+        let intro = output_dir.join("intro.html");
+        assert!(manifest.files.contains_key(&manifest_key(&intro)));
+        assert!(!input_dir.join(BUILD_MANIFEST).exists());
+        assert!(!output_dir.join(BUILD_MANIFEST).exists());
+        let leftovers: Vec<_> = fs::read_dir(&cache_dir)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(leftovers, [BUILD_MANIFEST]);
+
+        // The second build finds the pages unchanged and leaves them alone,
+        // apart from one edited behind its back
+        let intro_modified = fs::metadata(&intro)?.modified()?;
+        fs::write(output_dir.join("usage.html"), "stale")?;
+        build_sync_impl_sync(&args, &config, false)?;
+        assert_eq!(fs::metadata(&intro)?.modified()?, intro_modified);
+        assert!(fs::read_to_string(output_dir.join("usage.html"))?.contains("Usage"));
+
+        // `--cache-dir` takes precedence over the config
+        let flag_dir = temp_dir.path().join("flag");
+        args.cache_dir = Some(flag_dir.to_string_lossy().to_string());
+        build_sync_impl_sync(&args, &config, false)?;
+        assert!(flag_dir.join(BUILD_MANIFEST).exists());
+
+        // Pages since removed drop out of the manifest
+        fs::remove_file(input_dir.join("usage.md"))?;
+        build_sync_impl_sync(&args, &config, false)?;
+        let manifest = BuildManifest::load(&flag_dir);
+        assert!(manifest.files.contains_key(&manifest_key(&intro)));
+        let usage = manifest_key(&output_dir.join("usage.html"));
+        assert!(!manifest.files.contains_key(&usage));
+
+        // Library builds keep no manifest unless told where
+        let output_dir = temp_dir.path().join("library");
+        let mut args = build_args(&input_dir, &output_dir);
+        args.cache_dir = None;
+        build_sync_impl_sync(&args, &BookConfig::default(), false)?;
+        assert!(output_dir.join("intro.html").exists());
+        assert!(!default_cache_dir(&output_dir).exists());
+        assert!(Cli::parse_from(["md-book"]).into_args().default_cache_dir);
 
         Ok(())
     }
 
-    #[cfg(feature = "syntax-highlighting")]
     #[test]
-    fn test_process_code_block_no_language() -> Result<()> {
-        use syntect::parsing::SyntaxSet;
+    fn test_content_hash_is_sha256() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
 
-        let ss = SyntaxSet::load_defaults_newlines();
-        let code = "some plain text code";
+    #[test]
+    fn test_keep_going_reports_every_failed_page() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("1-intro.md"), "# Intro")?;
+        fs::write(
+            input_dir.join("2-broken.md"),
+            "# Broken\n\n{{#toc section=\"missing\"}}",
+        )?;
+        fs::write(
+            input_dir.join("3-also-broken.md"),
+            "# Also <broken>\n\n{{#toc section=\"gone\"}}",
+        )?;
 
-        let highlighted = process_code_block(code, None, &ss)?;
+        let config = BookConfig::default();
+        let mut args = build_args(&input_dir, &output_dir);
+        assert!(build_sync_impl_sync(&args, &config, false).is_err());
+        assert!(!output_dir.join("1-intro.html").exists());
+
+        args.keep_going = true;
+        let error = BuildError::from(build_sync_impl_sync(&args, &config, false).unwrap_err());
+        let BuildError::Pages { failures } = &error else {
+            panic!("expected page failures, got {error:?}");
+        };
+        let paths: Vec<_> = failures
+            .iter()
+            .map(|failure| failure.path.as_path())
+            .collect();
+        assert_eq!(
+            paths,
+            [Path::new("2-broken.md"), Path::new("3-also-broken.md")]
+        );
+        let message = error.to_string();
+        assert!(message.starts_with("2 page(s) failed to render"));
+        assert!(message.contains("missing"));
+        assert!(message.contains("gone"));
 
-        assert!(highlighted.contains("<pre"));
-        assert!(highlighted.contains("some plain text code"));
+        assert!(fs::read_to_string(output_dir.join("1-intro.html"))?.contains("<h1>Intro</h1>"));
+        let placeholder = fs::read_to_string(output_dir.join("3-also-broken.html"))?;
+        assert!(placeholder.contains("Also &lt;broken&gt; failed to render"));
+        assert!(placeholder.contains("gone"));
 
         Ok(())
     }
 
     #[test]
-    fn test_copy_static_assets() -> Result<()> {
+    fn test_keep_going_with_broken_index() -> Result<()> {
         let temp_dir = TempDir::new()?;
-        let output_dir = temp_dir.path().join("output");
-        // Use absolute path to avoid issues when other tests change working directory
-        let templates_dir = project_root().join("src/templates");
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        fs::write(input_dir.join("index.md"), "# Home\n\n![](cover.png)")?;
+        fs::write(input_dir.join("guide.md"), "# Guide")?;
 
-        fs::create_dir_all(&output_dir)?;
+        let mut config = BookConfig::default();
+        config.output.html.require_alt_text = AltTextPolicy::Strict;
+        let mut args = build_args(&input_dir, &output_dir);
+        args.keep_going = true;
+        let error = BuildError::from(build_sync_impl_sync(&args, &config, false).unwrap_err());
+        let BuildError::Pages { failures } = &error else {
+            panic!("expected page failures, got {error:?}");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].path, Path::new("index.md"));
 
-        let config = BookConfig::default();
-        copy_static_assets(
-            output_dir.to_str().unwrap(),
-            templates_dir.to_str().unwrap(),
-            &config,
-        )?;
+        assert!(fs::read_to_string(output_dir.join("guide.html"))?.contains("<h1>Guide</h1>"));
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("Home failed to render"));
+        Ok(())
+    }
 
-        // Check that some assets were copied (if templates exist)
-        let _has_assets = output_dir.join("css").exists()
-            || output_dir.join("js").exists()
-            || output_dir.join("img").exists();
+    #[test]
+    fn test_trailing_slash_policy() -> Result<()> {
+        let documents = || {
+            vec![
+                (
+                    PathBuf::from("intro.md"),
+                    "# Intro\n\n[One](chapter1) [Index](chapter1/index.md) [Top](chapter1/index.html#top) [Home](/)"
+                        .to_string(),
+                ),
+                (
+                    PathBuf::from("chapter1/index.md"),
+                    "# Chapter 1".to_string(),
+                ),
+                (PathBuf::from("chapter1/setup.md"), "# Setup".to_string()),
+            ]
+        };
+        let mut config = BookConfig::default();
+        config.output.html.generate_index = false;
+
+        config.output.html.trailing_slash = Some(TrailingSlash::Always);
+        let files: BTreeMap<_, _> = build_from_sources(documents(), &config)?
+            .into_iter()
+            .collect();
+        let intro = &files[Path::new("intro.html")];
+        assert!(intro.contains("<a href=\"chapter1/\">One</a>"));
+        assert!(intro.contains("<a href=\"chapter1/\">Index</a>"));
+        assert!(intro.contains("<a href=\"chapter1/#top\">Top</a>"));
+        assert!(intro.contains("<a href=\"/\">Home</a>"));
+        assert!(intro.contains("href=\"/chapter1/\""));
+        assert!(intro.contains("href=\"/chapter1/setup.html\""));
+        assert!(files[Path::new("chapter1/index.html")]
+            .contains("href=\"/chapter1/\" \n                   class=\"active\""));
+
+        config.output.html.trailing_slash = Some(TrailingSlash::Never);
+        let files: BTreeMap<_, _> = build_from_sources(documents(), &config)?
+            .into_iter()
+            .collect();
+        let intro = &files[Path::new("intro.html")];
+        assert!(intro.contains("<a href=\"chapter1\">One</a>"));
+        assert!(intro.contains("<a href=\"chapter1\">Index</a>"));
+        assert!(intro.contains("<a href=\"chapter1#top\">Top</a>"));
+        assert!(intro.contains("href=\"/chapter1\""));
+        assert!(!intro.contains("chapter1/index.html"));
 
-        // This test passes even if no assets exist, just checking the function doesn't crash
-        assert!(output_dir.exists());
+        Ok(())
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_inline_syntax_style() -> Result<()> {
+        let sources = vec![(
+            PathBuf::from("intro.md"),
+            "# Intro\n\n```rust {2}\nfn main() {\n    let x = \"hi\";\n}\n```".to_string(),
+        )];
+        let mut config = BookConfig::default();
+        let files: BTreeMap<_, _> = build_from_sources(sources.clone(), &config)?
+            .into_iter()
+            .collect();
+        assert!(files.contains_key(Path::new("css/syntax.css")));
+        assert!(files[Path::new("intro.html")].contains("/css/syntax.css"));
+
+        config.syntax.style = HighlightStyle::Inline;
+        let files: BTreeMap<_, _> = build_from_sources(sources, &config)?.into_iter().collect();
+        assert!(!files.contains_key(Path::new("css/syntax.css")));
+        let intro = &files[Path::new("intro.html")];
+        assert!(!intro.contains("/css/syntax.css"));
+        let code = &intro[intro.find("<pre class=\"code rust\">").unwrap()..];
+        let code = &code[..code.find("</pre>").unwrap()];
+        assert!(code.contains("<span style=\"color:#"));
+        assert!(!code.contains("class=\"source"));
+        assert!(code.contains("<span class=\"line highlighted\">"));
 
         Ok(())
     }
 
+    #[cfg(feature = "syntax-highlighting")]
     #[test]
-    fn test_copy_static_assets_nonexistent_dir() {
-        let temp_dir = TempDir::new().unwrap();
-        let output_dir = temp_dir.path().join("output");
-        let templates_dir = "nonexistent_templates";
+    fn test_language_aliases() -> Result<()> {
+        let source = |lang: &str| {
+            vec![(
+                PathBuf::from("intro.md"),
+                format!("# Intro\n\n```{lang}\nname: value\n```"),
+            )]
+        };
+        let code_block = |html: &str| {
+            let code = &html[html.find("<pre").unwrap()..];
+            code[..code.find("</pre>").unwrap()].to_string()
+        };
+        let mut config = BookConfig::default();
 
-        fs::create_dir_all(&output_dir).unwrap();
+        for (lang, scope) in [
+            ("js", "source js"),
+            ("ts", "source js"),
+            ("yml", "source yaml"),
+        ] {
+            let files: BTreeMap<_, _> = build_from_sources(source(lang), &config)?
+                .into_iter()
+                .collect();
+            let code = code_block(&files[Path::new("intro.html")]);
+            assert!(
+                code.contains(&format!("<span class=\"{scope}\">")),
+                "{lang}: {code}"
+            );
+        }
 
-        let config = BookConfig::default();
-        let result = copy_static_assets(output_dir.to_str().unwrap(), templates_dir, &config);
+        // Configured aliases win over the built-in ones
+        config
+            .syntax
+            .aliases
+            .insert("yml".to_string(), "text".to_string());
+        config
+            .syntax
+            .aliases
+            .insert("conf".to_string(), "yaml".to_string());
+        let files: BTreeMap<_, _> = build_from_sources(source("yml"), &config)?
+            .into_iter()
+            .collect();
+        assert!(code_block(&files[Path::new("intro.html")]).contains("<span class=\"text plain\">"));
+        let files: BTreeMap<_, _> = build_from_sources(source("conf"), &config)?
+            .into_iter()
+            .collect();
+        assert!(
+            code_block(&files[Path::new("intro.html")]).contains("<span class=\"source yaml\">")
+        );
 
-        // Should not fail even if templates dir doesn't exist
-        assert!(result.is_ok());
+        Ok(())
     }
 
-    // WASM-specific tests
-    #[cfg(target_arch = "wasm32")]
     #[test]
-    fn test_wasm_process_markdown() {
-        use crate::wasm_process_markdown;
+    fn test_keyboard_page_navigation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(&input_dir)?;
+        for page in ["1-intro", "2-usage", "3-faq"] {
+            fs::write(input_dir.join(format!("{page}.md")), format!("# {page}"))?;
+        }
+        build_sync_impl_sync(
+            &build_args(&input_dir, &output_dir),
+            &BookConfig::default(),
+            false,
+        )?;
 
-        let markdown = "# WASM Test\n\nThis is **bold** text for WASM.";
-        let html = wasm_process_markdown(markdown);
+        let script = fs::read_to_string(output_dir.join("components/page-nav.js"))?;
+        assert!(script.contains("ArrowLeft") && script.contains("ArrowRight"));
 
-        assert!(html.contains("<h1>WASM Test</h1>"));
-        assert!(html.contains("<strong>bold</strong>"));
+        let usage = fs::read_to_string(output_dir.join("2-usage.html"))?;
+        assert!(usage.contains("<script src=\"/components/page-nav.js\" type=\"module\"></script>"));
+        assert!(usage.contains("<page-nav prev=\"/1-intro.html\" next=\"/3-faq.html\"></page-nav>"));
+        let intro = fs::read_to_string(output_dir.join("1-intro.html"))?;
+        assert!(intro.contains("<page-nav next=\"/2-usage.html\"></page-nav>"));
 
-        // WASM should handle basic markdown correctly
-        assert!(!html.is_empty());
+        Ok(())
     }
 
-    #[cfg(target_arch = "wasm32")]
     #[test]
-    fn test_wasm_process_markdown_empty() {
-        use crate::wasm_process_markdown;
+    fn test_build_date() -> Result<()> {
+        assert_eq!(build_date(Some("1700000000"), false)?.year(), 2023);
+        assert_eq!(build_date(None, true)?.year(), 1970);
+        assert!(build_date(Some("yesterday"), true).is_err());
 
-        let html = wasm_process_markdown("");
-        assert!(html.is_empty() || html == "<p></p>\n");
+        Ok(())
     }
 
-    #[cfg(target_arch = "wasm32")]
     #[test]
-    fn test_wasm_process_markdown_code_blocks() {
-        use crate::wasm_process_markdown;
+    fn test_dry_run_writes_nothing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
 
-        let markdown = "```rust\nfn main() {\n    println!(\"Hello, WASM!\");\n}\n```";
-        let html = wasm_process_markdown(markdown);
+        fs::create_dir_all(input_dir.join("guide"))?;
+        fs::create_dir_all(&output_dir)?;
+        fs::write(input_dir.join("intro.md"), "# Intro\n\n![](diagram.png)")?;
+        fs::write(input_dir.join("diagram.png"), "png")?;
+        fs::write(input_dir.join("guide/setup.md"), "# Setup\n\nText.")?;
 
-        // WASM should handle code blocks (even without syntax highlighting)
-        assert!(html.contains("<pre>") || html.contains("<code>"));
-        assert!(html.contains("fn main"));
-        assert!(html.contains("Hello, WASM!"));
+        let mut config = BookConfig::default();
+        config.output.html.print_page = true;
+        config.output.html.redirect =
+            BTreeMap::from([("old.html".to_string(), "intro.html".to_string())]);
+
+        let mut args = build_args(&input_dir, &output_dir);
+        args.dry_run = true;
+        let pages = build_sync_impl_sync(&args, &config, false)?;
+
+        assert_eq!(pages, 2);
+        assert_eq!(fs::read_dir(&output_dir)?.count(), 0);
+
+        Ok(())
     }
 
-    // Integration-style test for build function
     #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
     #[tokio::test]
-    async fn test_build_simple_book() -> Result<()> {
+    async fn test_build_single_file() -> Result<()> {
         let temp_dir = TempDir::new()?;
         let input_dir = temp_dir.path().join("src");
         let output_dir = temp_dir.path().join("book");
 
-        fs::create_dir_all(&input_dir)?;
-        fs::create_dir_all(&output_dir)?;
-
-        // Create simple markdown file
-        fs::write(input_dir.join("test.md"), "# Test Page\n\nThis is a test.")?;
-
-        let args = Args {
-            input: input_dir.to_string_lossy().to_string(),
-            output: output_dir.to_string_lossy().to_string(),
-            config: None,
-            #[cfg(feature = "watcher")]
-            watch: false,
-            #[cfg(feature = "server")]
-            serve: false,
-            #[cfg(feature = "server")]
-            port: 3000,
-        };
-
-        let config = BookConfig::default();
-        build(&args, &config, false).await?;
-
-        // Verify output was created
-        assert!(output_dir.exists());
+        fs::create_dir_all(input_dir.join("guide/img"))?;
+        fs::write(
+            input_dir.join("intro.md"),
+            "# Intro\n\nFirst chapter. Next: [setup](guide/setup.md).",
+        )?;
+        fs::write(
+            input_dir.join("guide/setup.md"),
+            "# Setup\n\nSecond chapter.\n\n![diagram](img/diagram.png)\n\n```mermaid\ngraph TD; A-->B\n```",
+        )?;
+        fs::write(input_dir.join("guide/img/diagram.png"), b"\x89PNG fake")?;
+
+        let mut args = build_args(&input_dir, &output_dir);
+        args.single_file = true;
+        build(&args, &BookConfig::default(), false).await?;
+
+        let html = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(!html.contains("<link"));
+        assert!(!html.contains("<script src"));
+        assert!(html.contains("First chapter."));
+        assert!(html.contains("Second chapter."));
+        assert!(html.contains(r#"src="data:image/png;base64,"#));
+        assert!(html.contains(r##"href="#guide-setup""##));
+        assert!(html.contains("--sl-color-neutral-200:"));
+        assert!(html.contains("<script>mermaid.initialize("));
+
+        // Nothing but the bundle is written
+        assert!(!output_dir.join("intro.html").exists());
+        assert!(!output_dir.join("css").exists());
 
         Ok(())
     }
@@ -1212,15 +8969,35 @@ mod tests {
         fs::write(input_dir.join("test.md"), "# Test Page\n\nThis is a test.")?;
 
         let args = Args {
-            input: input_dir.to_string_lossy().to_string(),
-            output: output_dir.to_string_lossy().to_string(),
+            input: Some(input_dir.to_string_lossy().to_string()),
+            output: Some(output_dir.to_string_lossy().to_string()),
             config: None,
             #[cfg(feature = "watcher")]
             watch: false,
+            #[cfg(feature = "watcher")]
+            watch_paths: Vec::new(),
             #[cfg(feature = "server")]
             serve: false,
             #[cfg(feature = "server")]
             port: 3000,
+            #[cfg(feature = "server")]
+            mount: None,
+            #[cfg(feature = "server")]
+            open: false,
+            single_file: false,
+            dry_run: false,
+            deterministic: false,
+            strict: false,
+            keep_going: false,
+            drafts: false,
+            lint: false,
+            clean: false,
+            print_config: None,
+            #[cfg(feature = "parallel")]
+            jobs: None,
+            cache_dir: Some(temp_dir.path().join("cache").to_string_lossy().to_string()),
+            default_cache_dir: false,
+            format: OutputFormat::Html,
         };
 
         let config = BookConfig::default();
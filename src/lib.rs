@@ -1,18 +1,29 @@
 pub mod config;
 pub mod core;
+pub mod lint;
 pub mod pagefind_service;
 
 // Optional server module for native builds only
 #[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "watcher")]
+pub mod watcher;
 
 pub use config::BookConfig;
-pub use core::{build, Args, PageInfo};
+pub use core::{
+    build, build_from_sources, collect_pages, dump_config, markdown_to_plaintext, render_markdown,
+    should_include, try_build, Args, BuildError, Cli, ConfigFormat, Frontmatter, OutputFormat,
+    PageFailure, PageInfo, Section, SourceFilter,
+};
+#[cfg(feature = "syntax-highlighting")]
+pub use core::{CodeBlockRenderer, CodeBlockRenderers, FenceInfo};
+#[cfg(feature = "search")]
+pub use pagefind_service::{CommandRunner, ProcessRunner};
 pub use pagefind_service::{PagefindBuilder, PagefindError};
 
 // Re-export server functionality when available
 #[cfg(feature = "server")]
-pub use server::serve_book;
+pub use server::{notify_rebuild, serve_book, LiveReload};
 
 // WASM-specific exports
 #[cfg(target_arch = "wasm32")]
@@ -24,3 +35,12 @@ pub fn wasm_process_markdown(content: &str) -> String {
     // Basic markdown processing for WASM
     markdown::to_html(content)
 }
+
+/// Render markdown with a JSON-encoded `BookConfig`, matching the CLI output
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn wasm_render(content: &str, config_json: &str) -> Result<String, JsValue> {
+    let config: BookConfig = serde_json::from_str(config_json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid config: {e}")))?;
+    render_markdown(content, &config).map_err(|e| JsValue::from_str(&e.to_string()))
+}
@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use markdown::mdast::Node;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::config::BookConfig;
+use crate::core::{build_markdown_options, source_config, SourceFilter};
+
+/// The checks `md-book lint` runs over each page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintRule {
+    /// A heading more than one level below the one before it, like `#` then `###`
+    HeadingIncrement,
+    /// More than one top-level heading on a page
+    MultipleH1,
+    /// A URL written out as plain text instead of as a link
+    BareUrl,
+    /// An image without alt text
+    MissingAltText,
+    /// Spaces or tabs at the end of a line, other than a two-space line break
+    TrailingWhitespace,
+}
+
+impl LintRule {
+    /// The rule's name as reported, such as `heading-increment`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::HeadingIncrement => "heading-increment",
+            Self::MultipleH1 => "multiple-h1",
+            Self::BareUrl => "bare-url",
+            Self::MissingAltText => "missing-alt-text",
+            Self::TrailingWhitespace => "trailing-whitespace",
+        }
+    }
+}
+
+/// One issue found in a page, at a 1-based line of its source
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LintFinding {
+    pub file: PathBuf,
+    pub line: usize,
+    pub rule: LintRule,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} [{}]",
+            self.file.display(),
+            self.line,
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+/// Lints every page under `input` that a build would include, ordered by
+/// file and line
+///
+/// # Errors
+///
+/// Returns an error if a page can't be read or parsed
+pub fn lint_book(input: &Path, config: &BookConfig) -> Result<Vec<LintFinding>> {
+    let filter = SourceFilter::new(config);
+    let mut findings = Vec::new();
+    for entry in WalkDir::new(input).into_iter().filter_map(Result::ok) {
+        let rel_path = entry.path().strip_prefix(input)?;
+        if !entry.file_type().is_file() || !filter.includes(rel_path) {
+            continue;
+        }
+        let markdown = std::fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        findings.extend(lint_markdown(rel_path, &markdown, config)?);
+    }
+    findings.sort();
+    Ok(findings)
+}
+
+/// Lints one page, parsed with the settings a build would render it with
+///
+/// # Errors
+///
+/// Returns an error if the markdown cannot be parsed
+pub fn lint_markdown(
+    rel_path: &Path,
+    markdown: &str,
+    config: &BookConfig,
+) -> Result<Vec<LintFinding>> {
+    let page_config = source_config(config, rel_path);
+    let ast = markdown::to_mdast(markdown, &build_markdown_options(&page_config).parse)
+        .map_err(|e| anyhow::anyhow!("{}: markdown parsing error: {e}", rel_path.display()))?;
+
+    let mut lint = Lint {
+        file: rel_path,
+        markdown,
+        findings: Vec::new(),
+        previous_depth: None,
+        seen_h1: false,
+        verbatim_lines: Vec::new(),
+    };
+    lint.visit(&ast);
+
+    for (index, line) in markdown.lines().enumerate() {
+        if lint.is_verbatim(index + 1) {
+            continue;
+        }
+        let content = line.trim_end_matches([' ', '\t']);
+        let trailing = &line[content.len()..];
+        let is_line_break = trailing == "  " && !content.trim().is_empty();
+        if !trailing.is_empty() && !is_line_break {
+            lint.report(
+                index + 1,
+                LintRule::TrailingWhitespace,
+                "trailing whitespace",
+            );
+        }
+    }
+
+    lint.findings.sort();
+    Ok(lint.findings)
+}
+
+/// Walks a page's mdast, collecting findings
+struct Lint<'a> {
+    file: &'a Path,
+    markdown: &'a str,
+    findings: Vec<LintFinding>,
+    previous_depth: Option<u8>,
+    seen_h1: bool,
+    /// Lines of code blocks and frontmatter, whose whitespace is content
+    verbatim_lines: Vec<RangeInclusive<usize>>,
+}
+
+impl Lint<'_> {
+    fn report(&mut self, line: usize, rule: LintRule, message: impl Into<String>) {
+        self.findings.push(LintFinding {
+            file: self.file.to_path_buf(),
+            line,
+            rule,
+            message: message.into(),
+        });
+    }
+
+    fn is_verbatim(&self, line: usize) -> bool {
+        self.verbatim_lines
+            .iter()
+            .any(|lines| lines.contains(&line))
+    }
+
+    fn visit(&mut self, node: &Node) {
+        let line = node.position().map_or(1, |position| position.start.line);
+        match node {
+            Node::Heading(heading) => {
+                if let Some(previous) = self.previous_depth {
+                    if heading.depth > previous + 1 {
+                        self.report(
+                            line,
+                            LintRule::HeadingIncrement,
+                            format!("heading level {} follows level {previous}", heading.depth),
+                        );
+                    }
+                }
+                if heading.depth == 1 {
+                    if self.seen_h1 {
+                        self.report(line, LintRule::MultipleH1, "more than one level 1 heading");
+                    }
+                    self.seen_h1 = true;
+                }
+                self.previous_depth = Some(heading.depth);
+            }
+            Node::Image(image) if image.alt.trim().is_empty() => {
+                let message = format!("image `{}` has no alt text", image.url);
+                self.report(line, LintRule::MissingAltText, message);
+            }
+            Node::ImageReference(image) if image.alt.trim().is_empty() => {
+                let message = format!("image `[{}]` has no alt text", image.identifier);
+                self.report(line, LintRule::MissingAltText, message);
+            }
+            // GFM turns bare URLs into links; spelled-out ones start with `[` or `<`
+            Node::Link(link) => {
+                let source = node
+                    .position()
+                    .and_then(|position| self.markdown.get(position.start.offset..));
+                if source.is_some_and(|source| source.starts_with(link.url.as_str())) {
+                    let message = format!("bare URL `{}` should be a link", link.url);
+                    self.report(line, LintRule::BareUrl, message);
+                }
+                return;
+            }
+            Node::Text(text) => {
+                for (offset, _) in text.value.match_indices("http") {
+                    let rest = &text.value[offset..];
+                    if rest.starts_with("http://") || rest.starts_with("https://") {
+                        let url = rest.split_whitespace().next().unwrap_or(rest);
+                        let line = line + text.value[..offset].matches('\n').count();
+                        let message = format!("bare URL `{url}` should be a link");
+                        self.report(line, LintRule::BareUrl, message);
+                    }
+                }
+            }
+            Node::Code(_) | Node::Yaml(_) | Node::Toml(_) => {
+                if let Some(position) = node.position() {
+                    self.verbatim_lines
+                        .push(position.start.line..=position.end.line);
+                }
+            }
+            _ => {}
+        }
+        for child in node.children().into_iter().flatten() {
+            self.visit(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(findings: &[LintFinding]) -> Vec<(usize, LintRule)> {
+        findings
+            .iter()
+            .map(|finding| (finding.line, finding.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_missing_alt_text() -> Result<()> {
+        let markdown = "# Images\n\n![](diagram.png)\n\n![Described](photo.jpg)\n\n![ ][logo]\n\n[logo]: logo.svg\n";
+        let findings = lint_markdown(
+            Path::new("guide/images.md"),
+            markdown,
+            &BookConfig::default(),
+        )?;
+        assert_eq!(
+            rules(&findings),
+            [(3, LintRule::MissingAltText), (7, LintRule::MissingAltText)]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "guide/images.md:3: image `diagram.png` has no alt text [missing-alt-text]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_rules() -> Result<()> {
+        let markdown = "# Title\n\n### Too deep\n\n## Back\n\n### Fine\n\n# Second title\n";
+        let findings = lint_markdown(Path::new("a.md"), markdown, &BookConfig::default())?;
+        assert_eq!(
+            rules(&findings),
+            [(3, LintRule::HeadingIncrement), (9, LintRule::MultipleH1)]
+        );
+        assert_eq!(findings[0].message, "heading level 3 follows level 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_bare_urls_and_whitespace() -> Result<()> {
+        let markdown = "# Links \n\nSee https://example.com/docs or\nhttp://example.org.  \nand [this](https://example.com) or <https://example.net>.\n";
+        let findings = lint_markdown(Path::new("a.md"), markdown, &BookConfig::default())?;
+        assert_eq!(
+            rules(&findings),
+            [
+                (1, LintRule::TrailingWhitespace),
+                (3, LintRule::BareUrl),
+                (4, LintRule::BareUrl),
+            ]
+        );
+
+        // GFM links bare URLs itself, which still counts
+        let mut config = BookConfig::default();
+        config.markdown.format = crate::config::MarkdownFormat::Gfm;
+        let findings = lint_markdown(Path::new("a.md"), markdown, &config)?;
+        assert_eq!(
+            rules(&findings),
+            [
+                (1, LintRule::TrailingWhitespace),
+                (3, LintRule::BareUrl),
+                (4, LintRule::BareUrl),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_whitespace_skips_code_and_frontmatter() -> Result<()> {
+        let markdown = "---\ntitle: Rows \n---\n\n# Table\n\n```text\nrow one   \n```\n\n    indented \n\nDone \n";
+        let mut config = BookConfig::default();
+        config.markdown.frontmatter = true;
+        let findings = lint_markdown(Path::new("a.md"), markdown, &config)?;
+        assert_eq!(rules(&findings), [(13, LintRule::TrailingWhitespace)]);
+        Ok(())
+    }
+}
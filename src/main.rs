@@ -1,22 +1,23 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use md_book::config;
-use md_book::core::{build, Args};
+use md_book::core::{build, dump_config, Args, Cli};
+use md_book::{lint, BookConfig};
+use std::path::Path;
 
 #[cfg(any(feature = "server", feature = "watcher"))]
 use futures::future;
 
+#[cfg(all(feature = "server", feature = "watcher"))]
+use md_book::notify_rebuild;
 #[cfg(feature = "server")]
 use md_book::serve_book;
 #[cfg(feature = "server")]
 use tokio::sync::broadcast;
 
 #[cfg(feature = "watcher")]
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-#[cfg(feature = "watcher")]
-use tokio::time::Duration;
+use md_book::watcher::{watch_files, watch_paths};
 
 use clap::Parser;
-use std::path::Path;
 
 #[cfg(any(
     feature = "server",
@@ -41,7 +42,7 @@ fn main() -> Result<()> {
 }
 
 async fn main_impl() -> Result<()> {
-    let args = Args::parse();
+    let args = Cli::parse().into_args();
 
     #[cfg(any(feature = "watcher", feature = "server"))]
     let watch_enabled = {
@@ -60,6 +61,13 @@ async fn main_impl() -> Result<()> {
 
     // Load configuration
     let config = config::load_config(args.config.as_deref())?;
+    if let Some(format) = args.print_config {
+        print!("{}", dump_config(&config, format)?);
+        return Ok(());
+    }
+    if args.lint {
+        return lint_book(&args, &config);
+    }
 
     // Initial build
     #[cfg(any(feature = "server", feature = "watcher"))]
@@ -102,12 +110,16 @@ async fn main_impl() -> Result<()> {
             // Start server if requested
             #[cfg(feature = "server")]
             if should_serve {
-                let output_dir = args.output.clone();
+                let output_dir = args.output_dir(&config).to_string();
                 let port = args.port;
+                let mount = args.mount.clone();
+                let open = args.open;
                 let reload_tx = reload_tx.clone();
 
                 handles.push(tokio::spawn(async move {
-                    if let Err(e) = serve_book(output_dir, port, reload_tx).await {
+                    if let Err(e) =
+                        serve_book(output_dir, port, mount, reload_tx, watch_enabled, open).await
+                    {
                         eprintln!("Server error: {}", e);
                     }
                 }));
@@ -116,35 +128,27 @@ async fn main_impl() -> Result<()> {
             // Start watcher if requested
             #[cfg(feature = "watcher")]
             if should_watch {
-                let mut watch_paths = vec![args.input.clone()];
-                if let Some(templates_dir) = get_templates_dir(&config) {
-                    println!("Adding template dir to watch: {}", templates_dir);
-                    watch_paths.push(templates_dir);
-                }
-
+                let watch_paths = watch_paths(&args, &config);
                 let args = args.clone();
-                let config = config.clone();
                 let reload_tx = reload_tx.clone();
 
                 handles.push(tokio::spawn(async move {
-                    if let Err(e) = watch_files(
-                        watch_paths,
-                        move || {
-                            let args = args.clone();
-                            let config = config.clone();
-                            async move {
-                                #[cfg(feature = "tokio")]
-                                {
-                                    build(&args, &config, watch_enabled).await
-                                }
-                                #[cfg(not(feature = "tokio"))]
-                                {
-                                    build(&args, &config, watch_enabled)
-                                }
-                            }
-                        },
-                        reload_tx,
-                    )
+                    if let Err(e) = watch_files(watch_paths, move || {
+                        let args = args.clone();
+                        let reload_tx = reload_tx.clone();
+                        async move {
+                            // Config edits are picked up on the next rebuild
+                            let result = match config::load_config(args.config.as_deref()) {
+                                Ok(config) => build(&args, &config, watch_enabled).await,
+                                Err(e) => Err(e),
+                            };
+                            #[cfg(feature = "server")]
+                            notify_rebuild(&reload_tx, &result);
+                            #[cfg(not(feature = "server"))]
+                            let () = reload_tx;
+                            result
+                        }
+                    })
                     .await
                     {
                         eprintln!("Watch error: {}", e);
@@ -164,10 +168,17 @@ async fn main_impl() -> Result<()> {
 
 #[allow(dead_code)]
 fn main_impl_sync() -> Result<()> {
-    let args = Args::parse();
+    let args = Cli::parse().into_args();
 
     // Load configuration
     let _config = config::load_config(args.config.as_deref())?;
+    if let Some(format) = args.print_config {
+        print!("{}", dump_config(&_config, format)?);
+        return Ok(());
+    }
+    if args.lint {
+        return lint_book(&args, &_config);
+    }
 
     // Initial build (synchronous)
     #[cfg(not(feature = "tokio"))]
@@ -180,66 +191,14 @@ fn main_impl_sync() -> Result<()> {
     anyhow::bail!("Cannot use sync main with tokio feature enabled")
 }
 
-fn get_templates_dir(config: &md_book::BookConfig) -> Option<String> {
-    let templates_dir = &config.paths.templates;
-    if Path::new(templates_dir).exists() {
-        Some(templates_dir.clone())
-    } else {
-        None
+/// Prints each lint finding, failing if there were any
+fn lint_book(args: &Args, config: &BookConfig) -> Result<()> {
+    let findings = lint::lint_book(Path::new(args.input_dir(config)?), config)?;
+    for finding in &findings {
+        println!("{finding}");
     }
-}
-
-#[cfg(feature = "watcher")]
-async fn watch_files<F, Fut>(paths: Vec<String>, rebuild: F, reload_tx: ReloadSender) -> Result<()>
-where
-    F: Fn() -> Fut + Send + Sync + 'static,
-    Fut: std::future::Future<Output = Result<()>> + Send,
-{
-    let (tx, mut rx) = tokio::sync::mpsc::channel(32);
-
-    let mut watcher = RecommendedWatcher::new(
-        move |res| {
-            if let Ok(event) = res {
-                println!("Change detected: {:?}", event);
-                let _ = tx.blocking_send(());
-            }
-        },
-        notify::Config::default(),
-    )?;
-
-    // Watch all paths
-    for path in &paths {
-        println!("Watching {}", path);
-        watcher.watch(std::path::Path::new(path), RecursiveMode::Recursive)?;
-    }
-
-    // Debounce timer
-    let mut debounce = tokio::time::interval(Duration::from_millis(500));
-    let mut pending = false;
-
-    loop {
-        tokio::select! {
-            Some(_) = rx.recv() => {
-                pending = true;
-            }
-            _ = debounce.tick() => {
-                if pending {
-                    pending = false;
-                    println!("Rebuilding...");
-                    if let Err(e) = rebuild().await {
-                        eprintln!("Rebuild error: {}", e);
-                    } else {
-                        #[cfg(feature = "server")]
-                        { let _ = reload_tx.send(()); }
-                    }
-                }
-            }
-        }
+    if !findings.is_empty() {
+        bail!("{} lint issue(s) found", findings.len());
     }
+    Ok(())
 }
-
-#[cfg(all(feature = "watcher", feature = "server"))]
-type ReloadSender = broadcast::Sender<()>;
-
-#[cfg(all(feature = "watcher", not(feature = "server")))]
-type ReloadSender = ();
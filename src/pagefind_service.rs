@@ -1,7 +1,28 @@
 use anyhow::Result;
+#[cfg(feature = "search")]
+use std::num::NonZeroUsize;
+#[cfg(feature = "search")]
+use std::path::Path;
 use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::config::SearchConfig;
+
+#[cfg(feature = "search-index")]
+use pagefind::api::PagefindIndex;
+#[cfg(feature = "search-index")]
+use pagefind::options::PagefindServiceConfig;
+#[cfg(feature = "search-index")]
+use std::collections::BTreeMap;
+#[cfg(feature = "search")]
+use std::ffi::OsString;
+#[cfg(feature = "search")]
+use std::future::Future;
+#[cfg(feature = "search")]
+use std::pin::Pin;
+#[cfg(feature = "search")]
+use std::sync::Arc;
+
 #[cfg(feature = "search")]
 use jiff::Zoned;
 
@@ -19,6 +40,9 @@ pub enum PagefindError {
     #[error("Source path does not exist: {path}")]
     SourcePathNotFound { path: PathBuf },
 
+    #[error("No source paths to index")]
+    NoSources,
+
     #[error("Indexing failed: {message}")]
     IndexingFailed { message: String },
 
@@ -30,10 +54,42 @@ pub enum PagefindError {
     WasmError { message: String },
 }
 
+/// Runs the pagefind processes [`PagefindBuilder::build`] spawns. Tests swap
+/// in a runner that records the commands instead of executing them.
+#[cfg(feature = "search")]
+pub trait CommandRunner: std::fmt::Debug + Send + Sync {
+    fn run(
+        &self,
+        command: std::process::Command,
+    ) -> Pin<Box<dyn Future<Output = std::io::Result<std::process::Output>> + Send>>;
+}
+
+/// The default runner: spawns the command and waits for it to exit
+#[cfg(feature = "search")]
+#[derive(Debug, Default)]
+pub struct ProcessRunner;
+
+#[cfg(feature = "search")]
+impl CommandRunner for ProcessRunner {
+    fn run(
+        &self,
+        command: std::process::Command,
+    ) -> Pin<Box<dyn Future<Output = std::io::Result<std::process::Output>> + Send>> {
+        Box::pin(tokio::process::Command::from(command).output())
+    }
+}
+
 #[cfg(feature = "search")]
 #[derive(Debug)]
 pub struct PagefindBuilder {
-    source_path: PathBuf,
+    sources: Vec<PathBuf>,
+    concurrency: NonZeroUsize,
+    /// The executable and the arguments preceding the pagefind flags
+    program: (OsString, Vec<OsString>),
+    runner: Arc<dyn CommandRunner>,
+    root_selector: Option<String>,
+    exclude_selectors: Vec<String>,
+    index_dir: String,
 }
 
 #[cfg(not(feature = "search"))]
@@ -42,35 +98,104 @@ pub struct PagefindBuilder;
 
 #[cfg(feature = "search")]
 impl PagefindBuilder {
-    pub async fn new(source_path: PathBuf) -> Result<Self, PagefindError> {
-        // Validate source path exists
-        if !source_path.exists() {
-            return Err(PagefindError::SourcePathNotFound { path: source_path });
+    pub async fn new(source_path: PathBuf, search: &SearchConfig) -> Result<Self, PagefindError> {
+        Self::with_sources(vec![source_path], search).await
+    }
+
+    /// Indexes several built sites, such as a monorepo's books, each into
+    /// its own index directory. At most `search.concurrency` pagefind
+    /// processes run at once.
+    pub async fn with_sources(
+        sources: Vec<PathBuf>,
+        search: &SearchConfig,
+    ) -> Result<Self, PagefindError> {
+        if sources.is_empty() {
+            return Err(PagefindError::NoSources);
+        }
+        // Validate source paths exist
+        if let Some(path) = sources.iter().find(|path| !path.exists()) {
+            return Err(PagefindError::SourcePathNotFound { path: path.clone() });
+        }
+
+        let concurrency = search
+            .concurrency
+            .unwrap_or_else(|| std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN));
+        Ok(Self {
+            sources,
+            concurrency,
+            program: pagefind_program(search, std::env::var_os("PAGEFIND_BIN")),
+            runner: Arc::new(ProcessRunner),
+            root_selector: search.root_selector.clone(),
+            exclude_selectors: search.exclude_selectors.clone(),
+            index_dir: search.index_dir.clone(),
+        })
+    }
+
+    /// Arguments passed to the pagefind CLI to index `site`, derived from the
+    /// book's search config
+    pub fn command_args(&self, site: &Path) -> Vec<OsString> {
+        let mut args = vec![
+            OsString::from("--site"),
+            site.into(),
+            "--output-subdir".into(),
+            (&self.index_dir).into(),
+        ];
+        if let Some(root_selector) = &self.root_selector {
+            args.push("--root-selector".into());
+            args.push(root_selector.into());
         }
+        for selector in &self.exclude_selectors {
+            args.push("--exclude-selectors".into());
+            args.push(selector.into());
+        }
+        args
+    }
 
-        Ok(Self { source_path })
+    /// Runs pagefind through `runner` instead of spawning real processes
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// The pagefind invocation indexing `site`
+    pub fn command(&self, site: &Path) -> std::process::Command {
+        let (program, leading_args) = &self.program;
+        let mut command = std::process::Command::new(program);
+        command.args(leading_args).args(self.command_args(site));
+        command
     }
 
     pub async fn build(&self) -> Result<(), PagefindError> {
         let start_time = Zoned::now();
 
-        // Simple implementation using tokio command to run pagefind CLI
-        // This is a fallback approach when the Rust API is not stable
-        let output = tokio::process::Command::new("pagefind")
-            .arg("--site")
-            .arg(&self.source_path)
-            .output()
-            .await
-            .map_err(|e| PagefindError::IndexingFailed {
-                message: format!("Failed to run pagefind command: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(PagefindError::IndexingFailed {
-                message: format!("Pagefind command failed: {}", stderr),
-            });
+        let mut sites = self.sources.iter();
+        let mut running = tokio::task::JoinSet::new();
+        let mut result = Ok(());
+        loop {
+            while running.len() < self.concurrency.get() {
+                let Some(site) = sites.next() else {
+                    break;
+                };
+                running.spawn(run_pagefind(
+                    Arc::clone(&self.runner),
+                    site.clone(),
+                    self.command(site),
+                ));
+            }
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let outcome = joined
+                .map_err(|e| PagefindError::IndexingFailed {
+                    message: format!("Pagefind task failed: {}", e),
+                })
+                .and_then(|outcome| outcome);
+            // Let the other sites finish, then report the first failure
+            if result.is_ok() {
+                result = outcome;
+            }
         }
+        result?;
 
         let end_time = Zoned::now();
         let duration = end_time
@@ -87,24 +212,127 @@ impl PagefindBuilder {
         Ok(())
     }
 
-    /// Returns the configured source path
+    /// Returns the configured source path, or the first of several
     pub fn source_path(&self) -> Option<&PathBuf> {
-        Some(&self.source_path)
+        self.sources.first()
+    }
+
+    /// Returns every site this builder indexes
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+}
+
+/// The executable that runs pagefind, with any arguments it needs first:
+/// `bin` (from `PAGEFIND_BIN`), else `search.pagefind-binary`, else
+/// `npx pagefind` when `search.pagefind-npx` is set, else `pagefind` from `PATH`
+#[cfg(feature = "search")]
+fn pagefind_program(search: &SearchConfig, bin: Option<OsString>) -> (OsString, Vec<OsString>) {
+    if let Some(bin) = bin.filter(|bin| !bin.is_empty()) {
+        return (bin, Vec::new());
+    }
+    if let Some(bin) = &search.pagefind_binary {
+        return (bin.into(), Vec::new());
+    }
+    if search.pagefind_npx {
+        // `--yes` so CI never stops at npx's install prompt
+        return ("npx".into(), vec!["--yes".into(), "pagefind".into()]);
+    }
+    ("pagefind".into(), Vec::new())
+}
+
+/// Runs the pagefind CLI over one site
+#[cfg(feature = "search")]
+async fn run_pagefind(
+    runner: Arc<dyn CommandRunner>,
+    site: PathBuf,
+    command: std::process::Command,
+) -> Result<(), PagefindError> {
+    // Simple implementation running the pagefind CLI
+    // This is a fallback approach when the Rust API is not stable
+    let program = command.get_program().to_os_string();
+    let output = runner
+        .run(command)
+        .await
+        .map_err(|e| PagefindError::IndexingFailed {
+            message: format!(
+                "Failed to run pagefind command `{}` (set PAGEFIND_BIN or search.pagefind-binary): {}",
+                program.to_string_lossy(),
+                e
+            ),
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PagefindError::IndexingFailed {
+            message: format!("Pagefind command failed for {}: {}", site.display(), stderr),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "search-index")]
+impl PagefindBuilder {
+    /// Builds a search index over in-memory pages (output path → HTML) using the
+    /// pagefind library API, returning the generated bundle files keyed by their
+    /// path relative to the bundle directory.
+    ///
+    /// Nothing is read from or written to disk, so this also works where the
+    /// pagefind CLI is unavailable.
+    pub async fn build_in_memory(
+        pages: &BTreeMap<String, String>,
+        search: &SearchConfig,
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>, PagefindError> {
+        let options = PagefindServiceConfig::builder()
+            .root_selector(
+                search
+                    .root_selector
+                    .clone()
+                    .unwrap_or_else(|| "html".to_string()),
+            )
+            .exclude_selectors(search.exclude_selectors.clone())
+            .build();
+        let mut index = PagefindIndex::new(Some(options))?;
+
+        for (path, html) in pages {
+            index
+                .add_html_file(Some(path.clone()), None, html.clone())
+                .await
+                .map_err(|e| PagefindError::IndexingFailed {
+                    message: format!("Failed to index {path}: {e}"),
+                })?;
+        }
+
+        let files = index
+            .get_files()
+            .await
+            .map_err(|e| PagefindError::IndexingFailed {
+                message: format!("Failed to build index: {e}"),
+            })?;
+
+        Ok(files
+            .into_iter()
+            .map(|file| (file.filename, file.contents))
+            .collect())
     }
 
+    /// In-browser build path: indexes the generated pages without a CLI.
+    ///
+    /// Behind `search-index` rather than `search`, so it doesn't bring in
+    /// tokio. pagefind 1.4 itself still depends on actix-web and tokio's
+    /// networking, which don't build for `wasm32-unknown-unknown`.
     #[cfg(target_arch = "wasm32")]
-    pub async fn build_wasm(&self) -> Result<(), PagefindError> {
-        // WASM-specific implementation
-        // This would use different APIs optimized for WebAssembly
-        Err(PagefindError::WasmError {
-            message: "WASM build not yet implemented".to_string(),
-        })
+    pub async fn build_wasm(
+        pages: &BTreeMap<String, String>,
+        search: &SearchConfig,
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>, PagefindError> {
+        Self::build_in_memory(pages, search).await
     }
 }
 
 #[cfg(not(feature = "search"))]
 impl PagefindBuilder {
-    pub async fn new(_source_path: PathBuf) -> Result<Self, PagefindError> {
+    pub async fn new(_source_path: PathBuf, _search: &SearchConfig) -> Result<Self, PagefindError> {
         Err(PagefindError::IndexingFailed {
             message: "Search feature not enabled".to_string(),
         })
@@ -132,7 +360,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let source_path = temp_dir.path().to_path_buf();
 
-        let builder = PagefindBuilder::new(source_path.clone()).await;
+        let builder = PagefindBuilder::new(source_path.clone(), &SearchConfig::default()).await;
         assert!(builder.is_ok());
 
         let builder = builder.unwrap();
@@ -143,7 +371,7 @@ mod tests {
     #[tokio::test]
     async fn test_invalid_source_path() {
         let invalid_path = PathBuf::from("/nonexistent/path");
-        let result = PagefindBuilder::new(invalid_path.clone()).await;
+        let result = PagefindBuilder::new(invalid_path.clone(), &SearchConfig::default()).await;
 
         assert!(result.is_err());
         match result.unwrap_err() {
@@ -153,4 +381,244 @@ mod tests {
             _ => panic!("Expected SourcePathNotFound error"),
         }
     }
+
+    #[cfg(feature = "search")]
+    #[tokio::test]
+    async fn test_command_args_from_search_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let search = SearchConfig {
+            root_selector: Some("main".to_string()),
+            exclude_selectors: vec![".nav-footer".to_string(), "doc-toc".to_string()],
+            ..SearchConfig::default()
+        };
+
+        let builder = PagefindBuilder::new(temp_dir.path().to_path_buf(), &search)
+            .await
+            .unwrap();
+        let args: Vec<String> = builder
+            .command_args(temp_dir.path())
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+
+        let mut expected = vec![
+            "--site".to_string(),
+            temp_dir.path().to_string_lossy().into_owned(),
+        ];
+        expected.extend(
+            [
+                "--output-subdir",
+                "pagefind",
+                "--root-selector",
+                "main",
+                "--exclude-selectors",
+                ".nav-footer",
+                "--exclude-selectors",
+                "doc-toc",
+            ]
+            .map(String::from),
+        );
+        assert_eq!(args, expected);
+
+        let builder = PagefindBuilder::new(temp_dir.path().to_path_buf(), &SearchConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(builder.command_args(temp_dir.path()).len(), 4);
+    }
+
+    #[cfg(feature = "search")]
+    #[tokio::test]
+    async fn test_with_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let sites = vec![temp_dir.path().join("api"), temp_dir.path().join("guide")];
+        for site in &sites {
+            std::fs::create_dir_all(site).unwrap();
+        }
+        let search = SearchConfig {
+            concurrency: NonZeroUsize::new(1),
+            ..SearchConfig::default()
+        };
+
+        let builder = PagefindBuilder::with_sources(sites.clone(), &search)
+            .await
+            .unwrap();
+        assert_eq!(builder.sources(), sites.as_slice());
+        assert_eq!(builder.source_path(), Some(&sites[0]));
+        assert_eq!(builder.concurrency.get(), 1);
+        // One pagefind invocation per site
+        for site in &sites {
+            let args = builder.command_args(site);
+            assert_eq!(args[..2], [OsString::from("--site"), site.into()]);
+        }
+
+        let mut missing = sites.clone();
+        missing.push(temp_dir.path().join("missing"));
+        assert!(matches!(
+            PagefindBuilder::with_sources(missing, &search).await,
+            Err(PagefindError::SourcePathNotFound { path }) if path.ends_with("missing")
+        ));
+        assert!(matches!(
+            PagefindBuilder::with_sources(Vec::new(), &search).await,
+            Err(PagefindError::NoSources)
+        ));
+    }
+
+    #[cfg(feature = "search")]
+    #[tokio::test]
+    async fn test_pagefind_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let site = temp_dir.path();
+        let program = |builder: &PagefindBuilder| {
+            let command = builder.command(site);
+            let mut argv = vec![command.get_program().to_os_string()];
+            argv.extend(command.get_args().take(3).map(OsString::from));
+            argv
+        };
+
+        let mut search = SearchConfig::default();
+        assert_eq!(
+            pagefind_program(&search, None),
+            ("pagefind".into(), Vec::new())
+        );
+        search.pagefind_npx = true;
+        assert_eq!(
+            pagefind_program(&search, None),
+            ("npx".into(), vec!["--yes".into(), "pagefind".into()])
+        );
+        search.pagefind_binary = Some("/opt/pagefind/bin/pagefind".to_string());
+        assert_eq!(
+            pagefind_program(&search, Some(OsString::new())),
+            ("/opt/pagefind/bin/pagefind".into(), Vec::new())
+        );
+
+        // The environment beats the config
+        assert_eq!(
+            pagefind_program(&search, Some("/usr/local/bin/pagefind_extended".into())),
+            ("/usr/local/bin/pagefind_extended".into(), Vec::new())
+        );
+
+        // The resolved program is what gets spawned
+        if std::env::var_os("PAGEFIND_BIN").is_none() {
+            let builder = PagefindBuilder::new(site.to_path_buf(), &search)
+                .await
+                .unwrap();
+            assert_eq!(
+                program(&builder),
+                [
+                    OsString::from("/opt/pagefind/bin/pagefind"),
+                    "--site".into(),
+                    site.into(),
+                    "--output-subdir".into(),
+                ]
+            );
+        }
+    }
+
+    /// Records each command's arguments and reports success without running it
+    #[cfg(feature = "search")]
+    #[derive(Debug, Default)]
+    struct FakeRunner {
+        calls: std::sync::Mutex<Vec<Vec<OsString>>>,
+    }
+
+    #[cfg(feature = "search")]
+    impl CommandRunner for FakeRunner {
+        fn run(
+            &self,
+            command: std::process::Command,
+        ) -> Pin<Box<dyn Future<Output = std::io::Result<std::process::Output>> + Send>> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(command.get_args().map(OsString::from).collect());
+            Box::pin(async {
+                Ok(std::process::Output {
+                    status: std::process::ExitStatus::default(),
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            })
+        }
+    }
+
+    #[cfg(feature = "search")]
+    #[tokio::test]
+    async fn test_build_with_fake_runner() {
+        let temp_dir = TempDir::new().unwrap();
+        let sites = vec![temp_dir.path().join("api"), temp_dir.path().join("guide")];
+        for site in &sites {
+            std::fs::create_dir_all(site).unwrap();
+        }
+        let search = SearchConfig {
+            root_selector: Some("main".to_string()),
+            exclude_selectors: vec![".nav-footer".to_string()],
+            ..SearchConfig::default()
+        };
+        let runner = Arc::new(FakeRunner::default());
+
+        PagefindBuilder::with_sources(sites.clone(), &search)
+            .await
+            .unwrap()
+            .with_runner(runner.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let mut calls = runner.calls.lock().unwrap().clone();
+        calls.sort();
+        assert_eq!(calls.len(), 2);
+        for (args, site) in calls.iter().zip(&sites) {
+            let site_at = args.iter().position(|arg| arg == "--site").unwrap();
+            assert_eq!(args[site_at + 1], site.as_os_str());
+            assert!(args
+                .windows(2)
+                .any(|pair| pair == ["--root-selector", "main"]));
+            assert!(args
+                .windows(2)
+                .any(|pair| pair == ["--exclude-selectors", ".nav-footer"]));
+        }
+    }
+
+    #[cfg(feature = "search")]
+    #[tokio::test]
+    async fn test_build_in_memory() {
+        let mut pages = BTreeMap::new();
+        pages.insert(
+            "intro.html".to_string(),
+            "<html><body><h1>Introduction</h1><p>Welcome to the book</p></body></html>".to_string(),
+        );
+        pages.insert(
+            "guide/setup.html".to_string(),
+            "<html><body><h1>Setup</h1><p>Install the tool</p></body></html>".to_string(),
+        );
+
+        let files = PagefindBuilder::build_in_memory(&pages, &SearchConfig::default())
+            .await
+            .unwrap();
+
+        assert!(!files.is_empty());
+        assert!(files
+            .keys()
+            .any(|path| path.ends_with("pagefind-entry.json")));
+        assert!(files.values().all(|contents| !contents.is_empty()));
+    }
+
+    #[cfg(all(feature = "search-index", target_arch = "wasm32"))]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn test_build_wasm() {
+        let mut pages = BTreeMap::new();
+        pages.insert(
+            "a.html".to_string(),
+            "<html><body><h1>Alpha</h1><p>First page</p></body></html>".to_string(),
+        );
+        pages.insert(
+            "b.html".to_string(),
+            "<html><body><h1>Beta</h1><p>Second page</p></body></html>".to_string(),
+        );
+
+        let files = PagefindBuilder::build_wasm(&pages, &SearchConfig::default())
+            .await
+            .unwrap();
+        assert!(!files.is_empty());
+    }
 }
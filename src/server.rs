@@ -2,45 +2,571 @@ use anyhow::Result;
 #[cfg(feature = "server")]
 use futures::{SinkExt, StreamExt};
 #[cfg(feature = "server")]
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::time::UNIX_EPOCH;
+#[cfg(feature = "server")]
 use tokio::sync::broadcast;
 #[cfg(feature = "server")]
+use warp::filters::BoxedFilter;
+#[cfg(feature = "server")]
+use warp::http::{header, HeaderValue, StatusCode};
+#[cfg(feature = "server")]
+use warp::path::{FullPath, Tail};
+#[cfg(feature = "server")]
 use warp::ws::{Message, WebSocket};
 #[cfg(feature = "server")]
-use warp::Filter;
+use warp::{Filter, Reply};
+
+/// What the live-reload socket tells open pages after a rebuild
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiveReload {
+    /// The rebuild succeeded; pages reload
+    Reload,
+    /// The rebuild failed; pages show the error as an overlay
+    Error(String),
+}
+
+#[cfg(feature = "server")]
+impl LiveReload {
+    /// `reload`, or a JSON `{"type": "error", "message": ...}` object
+    fn message(&self) -> Message {
+        match self {
+            Self::Reload => Message::text("reload"),
+            Self::Error(message) => Message::text(
+                serde_json::json!({ "type": "error", "message": message }).to_string(),
+            ),
+        }
+    }
+}
+
+/// Reports the outcome of a watch-mode rebuild to every page connected to
+/// the live-reload socket
+#[cfg(feature = "server")]
+pub fn notify_rebuild(reload_tx: &broadcast::Sender<LiveReload>, result: &Result<()>) {
+    let event = match result {
+        Ok(()) => LiveReload::Reload,
+        Err(e) => LiveReload::Error(format!("{e:#}")),
+    };
+    // Nobody may be connected yet
+    let _ = reload_tx.send(event);
+}
 
 #[cfg(feature = "server")]
 pub async fn serve_book(
     output_dir: String,
     port: u16,
-    reload_tx: broadcast::Sender<()>,
+    mount: Option<String>,
+    reload_tx: broadcast::Sender<LiveReload>,
+    watch: bool,
+    open: bool,
 ) -> Result<()> {
-    let static_files =
-        warp::fs::dir(output_dir.clone()).or(warp::fs::file(format!("{}/index.html", output_dir)));
+    let (url, server) = bind_book(output_dir, port, mount.as_deref(), reload_tx, watch)?;
+    println!("Serving book at {url}");
+    // Only once the port is ours, so the browser never races the server
+    if open {
+        open_browser(&url);
+    }
+    server.await;
+    Ok(())
+}
+
+/// Binds the book's routes to `port` on localhost, returning the URL the
+/// book is served at and the server to run
+#[cfg(feature = "server")]
+fn bind_book(
+    output_dir: String,
+    port: u16,
+    mount: Option<&str>,
+    reload_tx: broadcast::Sender<LiveReload>,
+    watch: bool,
+) -> Result<(String, impl std::future::Future<Output = ()>)> {
+    let files = mounted_files(output_dir, mount, watch)?;
+    let (addr, server) = warp::serve(files.or(live_reload(reload_tx)))
+        .try_bind_ephemeral(([127, 0, 0, 1], port))
+        .map_err(|e| anyhow::anyhow!("Could not listen on port {port}: {e}"))?;
+    let url = format!("http://localhost:{}{}", addr.port(), mount_location(mount));
+    Ok((url, server))
+}
+
+/// Opens `url` in the default browser, warning instead of failing where
+/// there is none to open, as on headless CI
+#[cfg(feature = "server")]
+fn open_browser(url: &str) {
+    let spawned = opener_command(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    match spawned {
+        // Reap the opener so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Warning: could not open a browser at {url}: {e}"),
+    }
+}
+
+/// The platform's command for opening a URL with its default handler
+#[cfg(feature = "server")]
+fn opener_command(url: &str) -> std::process::Command {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        // `start` is a cmd builtin; its first quoted argument is the window title
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = std::process::Command::new("xdg-open");
+    command.arg(url);
+    command
+}
+
+/// The URL path the book is served from: `/`, or `/docs/` for `--mount docs`
+#[cfg(feature = "server")]
+fn mount_location(mount: Option<&str>) -> String {
+    crate::core::url_root(mount.unwrap_or_default())
+}
 
-    // Add WebSocket route for live reload
-    let reload = warp::path("live-reload")
+/// Serves the book under `mount`, such as the path of `book.base_url`, with
+/// the bare root redirecting there; without a mount it is served at the root
+#[cfg(feature = "server")]
+fn mounted_files(
+    output_dir: String,
+    mount: Option<&str>,
+    watch: bool,
+) -> Result<BoxedFilter<(warp::reply::Response,)>> {
+    let files = static_files(output_dir, watch).map(Reply::into_response);
+    let location = mount_location(mount);
+    if location == "/" {
+        return Ok(files.boxed());
+    }
+
+    let uri = location
+        .parse::<warp::http::Uri>()
+        .map_err(|e| anyhow::anyhow!("Invalid mount path `{location}`: {e}"))?;
+    let root = warp::get()
+        .and(warp::path::end())
+        .map(move || warp::redirect::temporary(uri.clone()).into_response());
+    let prefix = location
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(warp::any().boxed(), |prefix, segment| {
+            prefix.and(warp::path(segment.to_string())).boxed()
+        });
+    Ok(root.or(prefix.and(files)).unify().boxed())
+}
+
+/// The WebSocket route pages connect to for live reload
+#[cfg(feature = "server")]
+fn live_reload(
+    reload_tx: broadcast::Sender<LiveReload>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    warp::path("live-reload")
         .and(warp::ws())
         .map(move |ws: warp::ws::Ws| {
-            let reload_tx = reload_tx.clone();
-            ws.on_upgrade(move |socket| handle_live_reload(socket, reload_tx))
+            // Subscribe before the upgrade so no event sent after the
+            // handshake is missed
+            let rx = reload_tx.subscribe();
+            ws.on_upgrade(move |socket| handle_live_reload(socket, rx))
+        })
+}
+
+/// Serves the built book. Directories resolve to their `index.html`, with
+/// `/guide` redirected to `/guide/` so relative links keep working; unknown
+/// paths fall back to the book's index. Directory listings are never served.
+#[cfg(feature = "server")]
+fn static_files(
+    output_dir: String,
+    watch: bool,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let files = warp::fs::dir(output_dir.clone())
+        .or(warp::fs::file(format!("{}/index.html", output_dir)))
+        .unify()
+        .and(warp::header::optional::<String>("if-none-match"))
+        .map(move |file: warp::fs::File, if_none_match: Option<String>| {
+            cached_file(file, if_none_match, watch)
         });
+    directory_redirect(output_dir).or(files)
+}
 
-    println!("Serving book at http://localhost:{}", port);
-    warp::serve(static_files.or(reload))
-        .run(([127, 0, 0, 1], port))
-        .await;
-    Ok(())
+/// Adds an `ETag` and `Cache-Control` to a served file, answering a matching
+/// `If-None-Match` with `304 Not Modified`
+#[cfg(feature = "server")]
+fn cached_file(
+    file: warp::fs::File,
+    if_none_match: Option<String>,
+    watch: bool,
+) -> warp::reply::Response {
+    let etag = file_etag(file.path());
+    let cache_control = cache_control(file.path(), watch);
+    let not_modified = matches!(
+        (&etag, &if_none_match),
+        (Some(etag), Some(header)) if etag_matches(header, etag)
+    );
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        file.into_response()
+    };
+    let headers = response.headers_mut();
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(value) = cache_control {
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    }
+    response
+}
+
+/// Weak validator built from the file's size and modification time
+#[cfg(feature = "server")]
+fn file_etag(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "W/\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos()
+    ))
+}
+
+#[cfg(feature = "server")]
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Fingerprinted assets never change under the same name, so they can be
+/// cached forever; pages must be revalidated while watching for edits
+#[cfg(feature = "server")]
+fn cache_control(path: &Path, watch: bool) -> Option<&'static str> {
+    if is_fingerprinted(path) {
+        Some("public, max-age=31536000, immutable")
+    } else if watch && path.extension().is_some_and(|ext| ext == "html") {
+        Some("no-cache")
+    } else {
+        None
+    }
 }
 
+/// Whether the file name carries a content hash, like Pagefind's
+/// `en_4f1c2a9.pf_fragment`
 #[cfg(feature = "server")]
-async fn handle_live_reload(ws: WebSocket, reload_tx: broadcast::Sender<()>) {
-    let mut rx = reload_tx.subscribe();
+fn is_fingerprinted(path: &Path) -> bool {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+    stem.split(['.', '_', '-']).any(|part| {
+        part.len() >= 7
+            && part.chars().all(|c| c.is_ascii_hexdigit())
+            && part.chars().any(|c| c.is_ascii_digit())
+    })
+}
+
+/// Redirects a request for a directory without a trailing slash to the slashed
+/// path. `tail` is the path below the mount, which is the whole path unmounted.
+#[cfg(feature = "server")]
+fn directory_redirect(
+    output_dir: String,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path::full())
+        .and(warp::path::tail())
+        .and_then(move |path: FullPath, tail: Tail| {
+            let output_dir = output_dir.clone();
+            async move {
+                let requested = path.as_str();
+                let rel_path = tail.as_str();
+                let is_directory = !requested.ends_with('/')
+                    && !rel_path.split('/').any(|part| part == "..")
+                    && Path::new(&output_dir).join(rel_path).is_dir();
+                if !is_directory {
+                    return Err(warp::reject::not_found());
+                }
+
+                let location = format!("{requested}/")
+                    .parse::<warp::http::Uri>()
+                    .map_err(|_| warp::reject::not_found())?;
+                Ok(warp::redirect::permanent(location))
+            }
+        })
+}
+
+#[cfg(feature = "server")]
+async fn handle_live_reload(ws: WebSocket, mut rx: broadcast::Receiver<LiveReload>) {
     let (mut ws_tx, _) = ws.split();
 
-    while rx.recv().await.is_ok() {
-        if let Err(e) = ws_tx.send(Message::text("reload")).await {
+    while let Ok(event) = rx.recv().await {
+        if let Err(e) = ws_tx.send(event.message()).await {
             eprintln!("WebSocket send error: {}", e);
             break;
         }
     }
 }
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::core::{build, Args};
+    use crate::BookConfig;
+    use clap::Parser;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_directory_serves_its_index() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("guide/empty")).unwrap();
+        fs::write(temp_dir.path().join("index.html"), "book index").unwrap();
+        fs::write(temp_dir.path().join("guide/index.html"), "guide index").unwrap();
+        fs::write(temp_dir.path().join("guide/setup.html"), "setup").unwrap();
+        let routes = static_files(temp_dir.path().to_string_lossy().into_owned(), false);
+
+        let response = warp::test::request().path("/guide/").reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), "guide index");
+
+        let response = warp::test::request().path("/guide").reply(&routes).await;
+        assert_eq!(response.status(), 308);
+        assert_eq!(response.headers()["location"], "/guide/");
+
+        let response = warp::test::request()
+            .path("/guide/setup.html")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.body(), "setup");
+
+        // No index of its own: never a listing of the directory
+        let response = warp::test::request()
+            .path("/guide/empty/")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.body(), "book index");
+    }
+
+    #[tokio::test]
+    async fn test_mounted_book() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("guide")).unwrap();
+        fs::write(temp_dir.path().join("index.html"), "book index").unwrap();
+        fs::write(temp_dir.path().join("guide/setup.html"), "setup").unwrap();
+        let routes = mounted_files(
+            temp_dir.path().to_string_lossy().into_owned(),
+            Some("/docs/v1/"),
+            false,
+        )
+        .unwrap();
+
+        let response = warp::test::request()
+            .path("/docs/v1/guide/setup.html")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), "setup");
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        assert_eq!(response.status(), 307);
+        assert_eq!(response.headers()["location"], "/docs/v1/");
+
+        let response = warp::test::request().path("/docs/v1").reply(&routes).await;
+        assert_eq!(response.status(), 308);
+        assert_eq!(response.headers()["location"], "/docs/v1/");
+
+        let response = warp::test::request()
+            .path("/docs/v1/guide")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.headers()["location"], "/docs/v1/guide/");
+
+        let response = warp::test::request().path("/docs/v1/").reply(&routes).await;
+        assert_eq!(response.body(), "book index");
+
+        // Nothing is served outside the mount
+        let response = warp::test::request()
+            .path("/guide/setup.html")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_mounted_book_stylesheet() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("src");
+        let output_dir = temp_dir.path().join("book");
+        fs::create_dir_all(input_dir.join("guide")).unwrap();
+        fs::write(input_dir.join("guide/setup.md"), "# Setup").unwrap();
+        let args = Args::try_parse_from([
+            "md-book",
+            "--input",
+            input_dir.to_str().unwrap(),
+            "--output",
+            output_dir.to_str().unwrap(),
+            "--mount",
+            "/docs/v1",
+        ])
+        .unwrap();
+        build(&args, &BookConfig::default(), false).await.unwrap();
+        let routes = mounted_files(
+            output_dir.to_string_lossy().into_owned(),
+            args.mount.as_deref(),
+            false,
+        )
+        .unwrap();
+
+        let response = warp::test::request()
+            .path("/docs/v1/guide/setup.html")
+            .reply(&routes)
+            .await;
+        let page = String::from_utf8_lossy(response.body()).into_owned();
+        let stylesheet = page
+            .split("<link rel=\"stylesheet\" href=\"")
+            .filter_map(|rest| rest.split('"').next())
+            .find(|href| href.ends_with("styles.css"))
+            .unwrap();
+        assert_eq!(stylesheet, "/docs/v1/css/styles.css");
+        let response = warp::test::request().path(stylesheet).reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert!(!response.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_open_after_bind() {
+        let parse = |argv: &[&str]| Args::try_parse_from(argv).unwrap();
+        assert!(parse(&["md-book", "--serve", "--open"]).open);
+        let cli = crate::core::Cli::try_parse_from(["md-book", "serve", "--open"]).unwrap();
+        assert!(cli.into_args().open);
+        assert!(!parse(&["md-book", "--serve"]).open);
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().to_string_lossy().into_owned();
+        let (reload_tx, _) = broadcast::channel(1);
+        let (url, _server) = bind_book(
+            output_dir.clone(),
+            0,
+            Some("docs"),
+            reload_tx.clone(),
+            false,
+        )
+        .unwrap();
+        assert!(url.starts_with("http://localhost:"));
+        assert!(url.ends_with("/docs/"));
+
+        // A taken port fails before serve_book could reach the opener
+        let port: u16 = url["http://localhost:".len()..url.len() - "/docs/".len()]
+            .parse()
+            .unwrap();
+        let err = serve_book(output_dir, port, None, reload_tx, false, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(&format!("port {port}")));
+
+        let command = opener_command("http://localhost:3000/");
+        assert_eq!(command.get_args().last().unwrap(), "http://localhost:3000/");
+    }
+
+    #[tokio::test]
+    async fn test_cache_headers() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("css")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("pagefind/fragment")).unwrap();
+        fs::write(temp_dir.path().join("index.html"), "book index").unwrap();
+        fs::write(temp_dir.path().join("css/styles.css"), "body {}").unwrap();
+        fs::write(
+            temp_dir
+                .path()
+                .join("pagefind/fragment/en_4f1c2a9.pf_fragment"),
+            "fragment",
+        )
+        .unwrap();
+        let output_dir = temp_dir.path().to_string_lossy().into_owned();
+        let routes = static_files(output_dir.clone(), true);
+
+        let response = warp::test::request()
+            .path("/css/styles.css")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get("cache-control").is_none());
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+        let response = warp::test::request()
+            .path("/css/styles.css")
+            .header("if-none-match", &etag)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+        assert!(response.body().is_empty());
+
+        let response = warp::test::request()
+            .path("/pagefind/fragment/en_4f1c2a9.pf_fragment")
+            .reply(&routes)
+            .await;
+        assert_eq!(
+            response.headers()["cache-control"],
+            "public, max-age=31536000, immutable"
+        );
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        assert_eq!(response.headers()["cache-control"], "no-cache");
+        assert!(response.headers().contains_key("etag"));
+
+        let routes = static_files(output_dir, false);
+        let response = warp::test::request().path("/").reply(&routes).await;
+        assert!(response.headers().get("cache-control").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_errors_reach_the_browser() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("src");
+        let templates_dir = temp_dir.path().join("templates");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::create_dir_all(&templates_dir).unwrap();
+        fs::write(input_dir.join("intro.md"), "# Intro").unwrap();
+        fs::write(templates_dir.join("page.html.tera"), "{{ page.content }").unwrap();
+
+        let args = Args::parse_from([
+            "md-book",
+            "--input",
+            &input_dir.to_string_lossy(),
+            "--output",
+            &temp_dir.path().join("book").to_string_lossy(),
+            "--dry-run",
+        ]);
+        let mut config = BookConfig::default();
+        config.paths.templates = templates_dir.to_string_lossy().into_owned();
+
+        let (reload_tx, _) = broadcast::channel(16);
+        let mut client = warp::test::ws()
+            .path("/live-reload")
+            .handshake(live_reload(reload_tx.clone()))
+            .await
+            .unwrap();
+
+        let result = build(&args, &config, true).await;
+        notify_rebuild(&reload_tx, &result);
+        let message = client.recv().await.unwrap();
+        let event: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(event["type"], "error");
+        assert!(event["message"]
+            .as_str()
+            .unwrap()
+            .contains("page.html.tera at line 1"));
+
+        // Fixing the template reloads the page
+        fs::write(templates_dir.join("page.html.tera"), "{{ page.content }}").unwrap();
+        let result = build(&args, &config, true).await;
+        notify_rebuild(&reload_tx, &result);
+        let message = client.recv().await.unwrap();
+        assert_eq!(message.to_str(), Ok("reload"));
+    }
+}
@@ -0,0 +1,40 @@
+/**
+ * Keyboard navigation between pages: Left/Right arrow go to the `prev` and
+ * `next` URLs set as attributes. Keys are left alone while typing in a
+ * field, with a modifier held, or while the search modal is open.
+ */
+class PageNav extends HTMLElement {
+  constructor() {
+    super();
+    this.handleKeydown = this.handleKeydown.bind(this);
+  }
+
+  connectedCallback() {
+    document.addEventListener('keydown', this.handleKeydown);
+  }
+
+  disconnectedCallback() {
+    document.removeEventListener('keydown', this.handleKeydown);
+  }
+
+  handleKeydown(e) {
+    if (e.defaultPrevented || e.altKey || e.ctrlKey || e.metaKey || e.shiftKey) return;
+    if (this.isTyping(e.target) || document.querySelector('search-modal')?.isOpen) return;
+
+    const url = e.key === 'ArrowLeft' ? this.getAttribute('prev')
+      : e.key === 'ArrowRight' ? this.getAttribute('next')
+      : null;
+    if (url) {
+      e.preventDefault();
+      window.location.href = url;
+    }
+  }
+
+  isTyping(target) {
+    const element = target instanceof Element ? target : null;
+    return !!element && (element.isContentEditable
+      || element.closest('input, textarea, select, sl-input, sl-textarea, [contenteditable]') !== null);
+  }
+}
+
+customElements.define('page-nav', PageNav);
@@ -1,7 +1,8 @@
 document.addEventListener('DOMContentLoaded', () => {
   document.querySelectorAll('pre code').forEach(codeBlock => {
     const copyButton = document.createElement('sl-copy-button');
-    copyButton.value = codeBlock.textContent;
+    // Terminal sessions carry just their commands, without prompts or output
+    copyButton.value = codeBlock.parentElement.dataset.copy ?? codeBlock.textContent;
     copyButton.size = 'small';
     copyButton.variant = 'neutral';
     codeBlock.parentElement.appendChild(copyButton);
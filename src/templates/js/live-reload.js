@@ -1,8 +1,32 @@
 const socket = new WebSocket(`ws://${location.host}/live-reload`);
 
+// Shows a failed rebuild over the page until the next successful one reloads it
+function showBuildError(message) {
+    let overlay = document.getElementById('live-reload-error');
+    if (!overlay) {
+        overlay = document.createElement('div');
+        overlay.id = 'live-reload-error';
+        overlay.className = 'live-reload-error';
+        overlay.innerHTML = '<button class="live-reload-error-close" aria-label="Dismiss">&times;</button>'
+            + '<h2>Build failed</h2><pre></pre>';
+        overlay.querySelector('button').addEventListener('click', () => overlay.remove());
+        document.body.appendChild(overlay);
+    }
+    overlay.querySelector('pre').textContent = message;
+}
+
 socket.addEventListener('message', (event) => {
     if (event.data === 'reload') {
         location.reload();
+        return;
+    }
+    try {
+        const data = JSON.parse(event.data);
+        if (data.type === 'error') {
+            showBuildError(data.message);
+        }
+    } catch (e) {
+        console.warn('Unexpected live reload message', event.data);
     }
 });
 
@@ -11,4 +35,4 @@ socket.addEventListener('close', () => {
     setTimeout(() => {
         location.reload();
     }, 1000);
-}); 
\ No newline at end of file
+}); 
@@ -11,6 +11,8 @@ class PagefindSearch {
             debounceDelay: 300,
             minQueryLength: 2,
             maxResults: 20,
+            useBooleanAnd: true,
+            excerptLength: 30,
             ...options
         };
         
@@ -27,10 +29,12 @@ class PagefindSearch {
         if (this.isInitialized) return;
         
         try {
+            await this.loadConfig();
             this.pagefind = await import(`${this.options.bundlePath}pagefind.js`);
             await this.pagefind.options({
                 bundlePath: this.options.bundlePath,
-                baseUrl: this.options.baseUrl
+                baseUrl: this.options.baseUrl,
+                excerptLength: this.options.excerptLength
             });
             await this.pagefind.init();
             this.isInitialized = true;
@@ -41,6 +45,20 @@ class PagefindSearch {
         }
     }
 
+    /**
+     * Apply the book's `[output.html.search]` settings, written next to this script
+     */
+    async loadConfig() {
+        try {
+            const response = await fetch(new URL('search-config.json', import.meta.url));
+            if (response.ok) {
+                this.options = { ...this.options, ...(await response.json()) };
+            }
+        } catch (error) {
+            console.warn('Search config not loaded, using defaults:', error);
+        }
+    }
+
     /**
      * Perform search with debouncing
      */
@@ -79,7 +97,7 @@ class PagefindSearch {
         // Preload for better performance
         await this.pagefind.preload(query);
         
-        const searchResult = await this.pagefind.search(query);
+        const searchResult = await this.runQuery(query);
         const results = await Promise.all(
             searchResult.results
                 .slice(0, this.options.maxResults)
@@ -104,6 +122,31 @@ class PagefindSearch {
         };
     }
 
+    /**
+     * Pagefind matches every term; without `useBooleanAnd` each term is
+     * searched separately and the results merged, so any term matches
+     */
+    async runQuery(query) {
+        const terms = query.split(/\s+/).filter(Boolean);
+        if (this.options.useBooleanAnd || terms.length < 2) {
+            return this.pagefind.search(query);
+        }
+
+        const searches = await Promise.all(terms.map((term) => this.pagefind.search(term)));
+        const merged = new Map();
+        for (const search of searches) {
+            for (const result of search.results) {
+                const seen = merged.get(result.id);
+                if (!seen || seen.score < result.score) merged.set(result.id, result);
+            }
+        }
+        const results = [...merged.values()].sort((a, b) => b.score - a.score);
+        return {
+            results,
+            unfilteredResultCount: Math.max(...searches.map((search) => search.unfilteredResultCount))
+        };
+    }
+
     /**
      * Handle URL parameters for search
      */
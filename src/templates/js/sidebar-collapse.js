@@ -0,0 +1,43 @@
+/**
+ * Collapsible Sidebar Sections
+ * Remembers which sections the reader expanded or collapsed across pages.
+ * The section containing the current page is always expanded.
+ */
+
+const STORAGE_KEY = 'md-book-sidebar-sections';
+
+function loadState() {
+    try {
+        return JSON.parse(localStorage.getItem(STORAGE_KEY)) || {};
+    } catch (e) {
+        return {};
+    }
+}
+
+function saveState(state) {
+    try {
+        localStorage.setItem(STORAGE_KEY, JSON.stringify(state));
+    } catch (e) {
+        // Storage may be unavailable (private mode, quota); collapsing still works
+    }
+}
+
+document.addEventListener('DOMContentLoaded', () => {
+    const state = loadState();
+    const sections = document.querySelectorAll('details.sidebar-section[data-section]');
+
+    sections.forEach(section => {
+        const id = section.dataset.section;
+
+        if (section.classList.contains('is-active')) {
+            section.open = true;
+        } else if (id in state) {
+            section.open = state[id];
+        }
+
+        section.addEventListener('toggle', () => {
+            state[id] = section.open;
+            saveState(state);
+        });
+    });
+});
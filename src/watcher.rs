@@ -1,29 +1,228 @@
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc::channel;
-use std::time::Duration;
 use anyhow::Result;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+
+use crate::config::BookConfig;
+use crate::core::Args;
+
+/// Everything a rebuild depends on: the input and templates directories,
+/// `build.watch_paths`, `--watch-path` and the config files that were loaded
+pub fn watch_paths(args: &Args, config: &BookConfig) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = args
+        .input_dir(config)
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    if Path::new(&config.paths.templates).exists() {
+        paths.push(PathBuf::from(&config.paths.templates));
+    }
+    paths.extend(config.build.watch_paths.iter().map(PathBuf::from));
+    paths.extend(args.watch_paths.iter().map(PathBuf::from));
+    paths.push(PathBuf::from("book.toml"));
+    paths.extend(args.config.iter().map(PathBuf::from));
+    paths
+}
+
+/// The watched directories and files, resolved so they compare equal to
+/// the paths in change events
+#[derive(Debug, Default, Clone)]
+struct WatchSet {
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl WatchSet {
+    fn matches(&self, path: &Path) -> bool {
+        self.dirs.iter().any(|dir| path.starts_with(dir))
+            || self.files.iter().any(|file| file == path)
+    }
+}
 
-pub fn watch<F>(path: &str, callback: F) -> Result<()> 
+/// Calls `rebuild` whenever something under `paths` changes, debounced.
+/// Files are watched through their parent directory, so editors that save
+/// by replacing the file keep triggering rebuilds.
+pub async fn watch_files<F, Fut>(paths: Vec<PathBuf>, rebuild: F) -> Result<()>
 where
-    F: Fn() -> Result<()> + Send + 'static,
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<()>> + Send,
 {
-    let (tx, rx) = channel();
+    let mut watch_set = WatchSet::default();
+    for path in &paths {
+        match std::fs::canonicalize(path) {
+            Ok(path) if path.is_dir() => watch_set.dirs.push(path),
+            Ok(path) => watch_set.files.push(path),
+            // Only the default book.toml is optional
+            Err(_) if path == Path::new("book.toml") => {}
+            Err(e) => eprintln!("Not watching {}: {}", path.display(), e),
+        }
+    }
 
-    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_secs(2))?;
-    watcher.watch(Path::new(path), RecursiveMode::Recursive)?;
+    let (tx, rx) = mpsc::channel(32);
+    let matcher = watch_set.clone();
+    let mut watcher = RecommendedWatcher::new(
+        move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if event.paths.iter().any(|path| matcher.matches(path)) {
+                    println!("Change detected: {:?}", event);
+                    let _ = tx.blocking_send(());
+                }
+            }
+        },
+        notify::Config::default(),
+    )?;
 
-    println!("Watching for changes in {}...", path);
+    for dir in &watch_set.dirs {
+        println!("Watching {}", dir.display());
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+    }
+    for file in &watch_set.files {
+        println!("Watching {}", file.display());
+        let parent = file.parent().unwrap_or(file);
+        watcher.watch(parent, RecursiveMode::NonRecursive)?;
+    }
+
+    debounce_rebuilds(rx, Duration::from_millis(500), rebuild).await;
+    drop(watcher);
+    Ok(())
+}
+
+/// Runs `rebuild` once a burst of change events has been quiet for `delay`.
+/// Events arriving while a rebuild runs schedule exactly one more rebuild
+/// after it, so the last save is never left unbuilt. Returns when the
+/// sending side of `events` is gone.
+async fn debounce_rebuilds<F, Fut>(mut events: mpsc::Receiver<()>, delay: Duration, rebuild: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let mut pending = events.recv().await.is_some();
+    while pending {
+        while let Ok(Some(())) = tokio::time::timeout(delay, events.recv()).await {}
 
-    loop {
-        match rx.recv() {
-            Ok(_event) => {
-                println!("Change detected, rebuilding...");
-                if let Err(e) = callback() {
-                    eprintln!("Error rebuilding: {}", e);
+        println!("Rebuilding...");
+        if let Err(e) = rebuild().await {
+            eprintln!("Rebuild error: {:#}", e);
+        }
+
+        let mut changed_during_rebuild = false;
+        while events.try_recv().is_ok() {
+            changed_during_rebuild = true;
+        }
+        pending = changed_during_rebuild || events.recv().await.is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_watch_paths() {
+        let args = Args::parse_from([
+            "md-book",
+            "-i",
+            "src",
+            "-o",
+            "book",
+            "--config",
+            "site.toml",
+            "--watch-path",
+            "images",
+            "--watch-path",
+            "theme",
+        ]);
+        let mut config = BookConfig::default();
+        config.paths.templates = "missing-templates".to_string();
+        config.build.watch_paths = vec!["shared".to_string()];
+
+        let paths: Vec<_> = watch_paths(&args, &config)
+            .into_iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            paths,
+            ["src", "shared", "images", "theme", "book.toml", "site.toml"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extra_watch_path_triggers_rebuild() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_dir = temp_dir.path().join("src");
+        let images_dir = temp_dir.path().join("images");
+        let config_file = temp_dir.path().join("site.toml");
+        fs::create_dir_all(&input_dir)?;
+        fs::create_dir_all(&images_dir)?;
+        fs::write(&config_file, "")?;
+        fs::write(temp_dir.path().join("notes.txt"), "")?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let watcher = tokio::spawn(watch_files(
+            vec![input_dir, images_dir.clone(), config_file.clone()],
+            move || {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(());
+                    Ok(())
                 }
-            }
-            Err(e) => eprintln!("Watch error: {}", e),
+            },
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        fs::write(images_dir.join("logo.png"), "png")?;
+        assert!(tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .is_ok());
+
+        // Only the config file, not its siblings
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        while rx.try_recv().is_ok() {}
+        fs::write(temp_dir.path().join("notes.txt"), "unrelated")?;
+        assert!(tokio::time::timeout(Duration::from_millis(1200), rx.recv())
+            .await
+            .is_err());
+        fs::write(&config_file, "[book]\ntitle = \"Edited\"")?;
+        assert!(tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .is_ok());
+
+        watcher.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_change_during_rebuild_schedules_one_more() {
+        let (tx, rx) = mpsc::channel(32);
+        // A burst of saves is one rebuild
+        for _ in 0..3 {
+            tx.send(()).await.unwrap();
         }
+
+        let rebuilds = Arc::new(AtomicUsize::new(0));
+        let mid_rebuild_save = Arc::new(Mutex::new(Some(tx)));
+        let counter = rebuilds.clone();
+        debounce_rebuilds(rx, Duration::from_millis(20), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let tx = mid_rebuild_save.lock().unwrap().take();
+            async move {
+                // Two saves land while the first rebuild runs, then the
+                // watcher goes away
+                if let Some(tx) = tx {
+                    tx.send(()).await.unwrap();
+                    tx.send(()).await.unwrap();
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert_eq!(rebuilds.load(Ordering::SeqCst), 2);
     }
 }